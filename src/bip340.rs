@@ -0,0 +1,367 @@
+use ff::PrimeField;
+use k256::elliptic_curve::bigint::{U256, U512};
+use k256::elliptic_curve::generic_array::GenericArray;
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::point::{AffineCoordinates, DecompressPoint};
+use k256::elliptic_curve::subtle::Choice;
+use k256::{AffinePoint, ProjectivePoint, Scalar};
+use pasta_curves::group::{prime::PrimeCurveAffine, GroupEncoding};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use myp::{Secret, ThresholdError};
+use crate::key_generate::lagrange_coefficient;
+use crate::nonce::HedgedNonce;
+
+//bip-340 schnorr on secp256k1 with the key split over the signers, what a taproot key path spend
+//need. the public key and R are x only, both stand for the point with even y: if the group key has
+//odd y every signer negate its share, and if R has odd y every signer negate its nonces.
+//nonces are committed in pairs like ed25519.rs, R = sum(D_i + rho_i * E_i)
+
+//the two nonces of one signing, sign_share take it by value so it is used for one message only
+pub struct SigningNonce {
+    hiding : Secret<Scalar>,
+    binding : Secret<Scalar>,
+}
+
+impl SigningNonce {
+    //a pair preprocessed ahead of the message, from a NonceStore
+    pub fn new(hiding: Secret<Scalar>, binding: Secret<Scalar>) -> SigningNonce {
+        SigningNonce { hiding, binding }
+    }
+}
+
+//what a signer broadcast before anyone sign, index is its place in the key ceremony (from 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceCommitment {
+    pub index : u128,
+    pub hiding : AffinePoint,
+    pub binding : AffinePoint,
+}
+
+//SHA256(SHA256(tag) | SHA256(tag) | parts)
+pub fn tagged_hash(tag: &str, part: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    for i in part {
+        hasher.update(i);
+    }
+    hasher.finalize().into()
+}
+
+fn scalar_from_hash(hash: &[u8; 32]) -> Scalar {
+    <Scalar as Reduce<U256>>::reduce_bytes(hash.into())
+}
+
+//the 32 bytes x of a point, as bip-340 publish keys and R
+pub fn x_only(point: &AffinePoint) -> [u8; 32] {
+    point.x().into()
+}
+
+//the point with even y and this x, x must be below the field size
+pub fn lift_x(x: &[u8; 32]) -> Result<AffinePoint, ThresholdError> {
+    Option::from(AffinePoint::decompress(x.into(), Choice::from(0))).ok_or(ThresholdError::InvalidEncoding("x only point"))
+}
+
+//1 or -1, the factor that move a point to its even y twin
+fn even_y_factor(point: &AffinePoint) -> Scalar {
+    if bool::from(point.y_is_odd()) { -Scalar::ONE } else { Scalar::ONE }
+}
+
+//e = H_challenge(x(R) | x(P) | m) mod n
+pub fn challenge(r: &[u8; 32], pub_key: &[u8; 32], message: &[u8]) -> Scalar {
+    scalar_from_hash(&tagged_hash("BIP0340/challenge", &[r, pub_key, message]))
+}
+
+//single key signing exactly as bip-340 specify it, with aux the 32 bytes of fresh randomness.
+//only the reference for the test vectors, the daemon sign with the threshold rounds
+#[cfg(test)]
+pub fn sign(secret: &Secret<Scalar>, message: &[u8], aux: &[u8; 32]) -> Result<[u8; 64], ThresholdError> {
+    if bool::from(secret.expose().is_zero()) {
+        return Err(ThresholdError::OutOfRange("secret"));
+    }
+    let pub_key = (ProjectivePoint::GENERATOR * secret.expose()).to_affine();
+    let d = Secret::new(even_y_factor(&pub_key) * secret.expose());
+    let mut t = tagged_hash("BIP0340/aux", &[aux]);
+    for (i, j) in t.iter_mut().zip(d.expose().to_repr()) {
+        *i ^= j;
+    }
+    let k = Secret::new(scalar_from_hash(&tagged_hash("BIP0340/nonce", &[&t, &x_only(&pub_key), message])));
+    if bool::from(k.expose().is_zero()) {
+        return Err(ThresholdError::NonInvertible);
+    }
+    let r = (ProjectivePoint::GENERATOR * k.expose()).to_affine();
+    let e = challenge(&x_only(&r), &x_only(&pub_key), message);
+    let s = even_y_factor(&r) * k.expose() + e * d.expose();
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&x_only(&r));
+    signature[32..].copy_from_slice(&s.to_repr());
+    Ok(signature)
+}
+
+//the bip-340 check, pub_key is the x only key
+pub fn verify(pub_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> Result<(), ThresholdError> {
+    let p = lift_x(pub_key)?;
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    let s: Scalar = Option::from(Scalar::from_repr(s.into())).ok_or(ThresholdError::InvalidEncoding("s"))?;
+    let mut r = [0u8; 32];
+    r.copy_from_slice(&signature[..32]);
+    let e = challenge(&r, pub_key, message);
+    let point = (ProjectivePoint::GENERATOR * s - p * e).to_affine();
+    //an r that is not below the field size never equal x of a point
+    if bool::from(point.is_identity()) || bool::from(point.y_is_odd()) || x_only(&point) != r {
+        return Err(ThresholdError::InvalidSignature);
+    }
+    Ok(())
+}
+
+pub fn pub_key_share(share: &Secret<Scalar>) -> AffinePoint {
+    (ProjectivePoint::GENERATOR * share.expose()).to_affine()
+}
+
+//hedged from the key share, the message and the rng, so a stuck rng can't give one nonce to two messages
+pub fn commit(rng: &mut (impl CryptoRng + RngCore), pri: &Secret<Scalar>, index: u128, message: &[u8]) -> (SigningNonce, NonceCommitment) {
+    let mut drbg = HedgedNonce::new(rng, "bip340-nonce", &pri.expose().to_repr(), message);
+    let mut nonce = || Secret::new(<Scalar as Reduce<U512>>::reduce_bytes(GenericArray::from_slice(&drbg.wide())));
+    let (hiding, binding) = (nonce(), nonce());
+    let commitment = NonceCommitment {
+        index,
+        hiding : pub_key_share(&hiding),
+        binding : pub_key_share(&binding),
+    };
+    (SigningNonce { hiding, binding }, commitment)
+}
+
+//rho_i, over the message, the group key and every commitment in signer order
+fn binding_factor(index: u128, message: &[u8], commitment: &[NonceCommitment], pub_key: &AffinePoint) -> Scalar {
+    let mut transcript = Vec::new();
+    for i in commitment {
+        transcript.extend_from_slice(&i.index.to_be_bytes());
+        transcript.extend_from_slice(&i.hiding.to_bytes());
+        transcript.extend_from_slice(&i.binding.to_bytes());
+    }
+    let message_hash = Sha256::digest(message);
+    scalar_from_hash(&tagged_hash("myp/bip340-binding", &[&x_only(pub_key), &message_hash, &transcript, &index.to_be_bytes()]))
+}
+
+//one commitment for every signer in signer_set, in the same order, and none of them the identity
+fn check_commitment(signer_set: &[u128], commitment: &[NonceCommitment]) -> Result<(), ThresholdError> {
+    if commitment.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: commitment.len() });
+    }
+    for (party, (index, i)) in signer_set.iter().zip(commitment).enumerate() {
+        if i.index != *index || bool::from(i.hiding.is_identity()) || bool::from(i.binding.is_identity()) {
+            return Err(ThresholdError::VerificationFailed { party });
+        }
+    }
+    Ok(())
+}
+
+//R, rho_i of every signer and e
+fn group_commitment(message: &[u8], commitment: &[NonceCommitment], pub_key: &AffinePoint) -> Result<(AffinePoint, Vec<Scalar>, Scalar), ThresholdError> {
+    let mut r = ProjectivePoint::IDENTITY;
+    let mut rho = Vec::new();
+    for i in commitment {
+        let rho_i = binding_factor(i.index, message, commitment, pub_key);
+        r += ProjectivePoint::from(i.hiding) + i.binding * rho_i;
+        rho.push(rho_i);
+    }
+    let r = r.to_affine();
+    if bool::from(r.is_identity()) {
+        return Err(ThresholdError::InvalidPoint);
+    }
+    let e = challenge(&x_only(&r), &x_only(pub_key), message);
+    Ok((r, rho, e))
+}
+
+//s_i = (+-)(d_i + rho_i * e_i) + e * lambda_i * (+-)x_i, pub_key is the full group key point
+pub fn sign_share(
+    nonce : SigningNonce,
+    pri : &Secret<Scalar>,
+    index : u128,
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    pub_key : &AffinePoint,
+) -> Result<Scalar, ThresholdError> {
+    check_commitment(signer_set, commitment)?;
+    let party = signer_set.iter().position(|i| *i == index).ok_or(ThresholdError::OutOfRange("index"))?;
+    //the aggregator must have passed on this signer's own commitment, else the nonce sign for another R
+    if commitment[party].hiding != pub_key_share(&nonce.hiding) || commitment[party].binding != pub_key_share(&nonce.binding) {
+        return Err(ThresholdError::VerificationFailed { party });
+    }
+    let (r, rho, e) = group_commitment(message, commitment, pub_key)?;
+    let lambda: Scalar = lagrange_coefficient(index, signer_set)?;
+    let k = even_y_factor(&r) * (nonce.hiding.expose() + rho[party] * nonce.binding.expose());
+    Ok(k + e * lambda * even_y_factor(pub_key) * pri.expose())
+}
+
+//verification_shares[i] = x_i * G of signer_set[i], the same equation as sign_share on the points
+pub fn verify_share(
+    index : u128,
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    s : &Scalar,
+    verification_shares : &[AffinePoint],
+    pub_key : &AffinePoint,
+) -> Result<(), ThresholdError> {
+    check_commitment(signer_set, commitment)?;
+    if verification_shares.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: verification_shares.len() });
+    }
+    let party = signer_set.iter().position(|i| *i == index).ok_or(ThresholdError::OutOfRange("index"))?;
+    let (r, rho, e) = group_commitment(message, commitment, pub_key)?;
+    let lambda: Scalar = lagrange_coefficient(index, signer_set)?;
+    let nonce_point = (ProjectivePoint::from(commitment[party].hiding) + commitment[party].binding * rho[party]) * even_y_factor(&r);
+    let right = nonce_point + verification_shares[party] * (e * lambda * even_y_factor(pub_key));
+    if ProjectivePoint::GENERATOR * s != right {
+        return Err(ThresholdError::VerificationFailed { party });
+    }
+    Ok(())
+}
+
+//every share is checked before they are added, a bad one is blamed by its place in signer_set.
+//the result is x(R) | s, it verify under x_only(pub_key)
+pub fn combine(
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    s : &[Scalar],
+    verification_shares : &[AffinePoint],
+    pub_key : &AffinePoint,
+) -> Result<[u8; 64], ThresholdError> {
+    if s.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: s.len() });
+    }
+    let mut s_sum = Scalar::ZERO;
+    for (index, s_i) in signer_set.iter().zip(s) {
+        verify_share(*index, signer_set, message, commitment, s_i, verification_shares, pub_key)?;
+        s_sum += s_i;
+    }
+    let (r, _, _) = group_commitment(message, commitment, pub_key)?;
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&x_only(&r));
+    signature[32..].copy_from_slice(&s_sum.to_repr());
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use hex_literal::hex;
+    use k256::schnorr::signature::hazmat::PrehashVerifier;
+    use crate::key_generate::{CollectScalarShare, DealScalarShare};
+
+    #[test]
+    fn bip340_vector_test(){
+        //secret key, public key, aux, message, signature, from bip-0340/test-vectors.csv 0 to 3
+        let sign_vector = [
+            (hex!("0000000000000000000000000000000000000000000000000000000000000003"),
+             hex!("F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+             hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+             hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+             hex!("E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA821525F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0")),
+            (hex!("B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF"),
+             hex!("DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+             hex!("0000000000000000000000000000000000000000000000000000000000000001"),
+             hex!("243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89"),
+             hex!("6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE33418906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A")),
+            (hex!("C90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B14E5C9"),
+             hex!("DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8"),
+             hex!("C87AA53824B4D7AE2EB035A2B5BBBCCC080E76CDC6D1692C4B0B62D798E6D906"),
+             hex!("7E2D58D8B3BCDF1ABADEC7829054F90DDA9805AAB56C77333024B9D0A508B75C"),
+             hex!("5831AAEED7B44BB74E5EAB94BA9D4294C49BCF2A60728D8B4C200F50DD313C1BAB745879A5AD954A72C45A91C3A51D3C7ADEA98D82F8481E0E1E03674A6F3FB7")),
+            (hex!("0B432B2677937381AEF05BB02A66ECD012773062CF3FA2549E44F58ED2401710"),
+             hex!("25D1DFF95105F5253C4022F628A996AD3A0D95FBF21D468A1B33F8C160D8F517"),
+             hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"),
+             hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"),
+             hex!("7EB0509757E246F19449885651611CB965ECC1A187DD51B64FDA1EDC9637D5EC97582B9CB13DB3933705B32BA982AF5AF25FD78881EBB32771FC5922EFC66EA3")),
+        ];
+        for (secret, pub_key, aux, message, signature) in sign_vector {
+            let secret = Secret::new(Option::from(Scalar::from_repr(secret.into())).unwrap());
+            assert_eq!(x_only(&pub_key_share(&secret)), pub_key);
+            assert_eq!(sign(&secret, &message, &aux), Ok(signature));
+            assert_eq!(verify(&pub_key, &message, &signature), Ok(()));
+        }
+
+        //public key, message, signature and whether it is valid, vectors 4 to 14
+        let key = hex!("DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659");
+        let message = hex!("243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89");
+        let verify_vector = [
+            (hex!("D69C3509BB99E412E68B0FE8544E72837DFA30746D8BE2AA65975F29D22DC7B9"), hex!("4DF3C3F68FCC83B27E9D42C90431A72499F17875C81A599B566C9889B9696703"),
+             hex!("00000000000000000000003B78CE563F89A0ED9414F5AA28AD0D96D6795F9C6376AFB1548AF603B3EB45C9F8207DEE1060CB71C04E80F593060B07D28308D7F4"), true),
+            //public key not on the curve
+            (hex!("EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34"), message,
+             hex!("6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E17776969E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B"), false),
+            //R has odd y
+            (key, message, hex!("FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A14602975563CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2"), false),
+            //negated message
+            (key, message, hex!("1FA62E331EDBC21C394792D2AB1100A7B432B013DF3F6FF4F99FCB33E0E1515F28890B3EDB6E7189B630448B515CE4F8622A954CFE545735AAEA5134FCCDB2BD"), false),
+            //negated s
+            (key, message, hex!("6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769961764B3AA9B2FFCB6EF947B6887A226E8D7C93E00C5ED0C1834FF0D0C2E6DA6"), false),
+            //s * G - e * P is the identity
+            (key, message, hex!("0000000000000000000000000000000000000000000000000000000000000000123DDA8328AF9C23A94C1FEECFD123BA4FB73476F0D594DCB65C6425BD186051"), false),
+            (key, message, hex!("00000000000000000000000000000000000000000000000000000000000000017615FBAF5AE28864013C099742DEADB4DBA87F11AC6754F93780D5A1837CF197"), false),
+            //r is not x of a point
+            (key, message, hex!("4A298DACAE57395A15D0795DDBFD1DCB564DA82B0F269BC70A74F8220429BA1D69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B"), false),
+            //r is the field size
+            (key, message, hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B"), false),
+            //s is the curve order
+            (key, message, hex!("6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141"), false),
+            //public key is not below the field size
+            (hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30"), message,
+             hex!("6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E17776969E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B"), false),
+        ];
+        for (pub_key, message, signature, valid) in verify_vector {
+            assert_eq!(verify(&pub_key, &message, &signature).is_ok(), valid);
+        }
+    }
+
+    #[test]
+    fn bip340_threshold_test(){
+        //3 of 5 from the scalar field key ceremony, a few runs so both parities of the group key come up
+        let mut rng = rand::thread_rng();
+        for _ in 0..4 {
+            let deal = DealScalarShare { output_max : 5, output_min : 3 };
+            let (dealt, commitment): (Vec<Secret<Vec<Scalar>>>, Vec<Vec<AffinePoint>>) = (0..5).map(|_| deal.deal(&mut rng).unwrap()).unzip();
+            let mut share = Vec::new();
+            let mut all_verification_shares = Vec::new();
+            let mut pub_key = AffinePoint::IDENTITY;
+            for i in 1..=5u128 {
+                let (own, key, verification_share) = CollectScalarShare {
+                    key_share : Secret::new(dealt.iter().map(|j| j.expose()[i as usize - 1]).collect()),
+                    commitment : commitment.clone(),
+                    self_num : i,
+                }.collect().unwrap();
+                share.push(own);
+                all_verification_shares = verification_share;
+                pub_key = key;
+            }
+
+            let signer_set = [2, 4, 5];
+            let verification_shares: Vec<AffinePoint> = signer_set.iter().map(|i| all_verification_shares[*i as usize - 1]).collect();
+            assert!(signer_set.iter().all(|i| verification_shares.contains(&pub_key_share(&share[*i as usize - 1]))));
+            let message = b"taproot key path";
+            let (nonce, commitment): (Vec<SigningNonce>, Vec<NonceCommitment>) = signer_set.iter()
+                .map(|i| commit(&mut rng, &share[*i as usize - 1], *i, message)).unzip();
+            let s: Vec<Scalar> = nonce.into_iter().zip(&signer_set)
+                .map(|(n, i)| sign_share(n, &share[*i as usize - 1], *i, &signer_set, message, &commitment, &pub_key).unwrap()).collect();
+            let signature = combine(&signer_set, message, &commitment, &s, &verification_shares, &pub_key).unwrap();
+
+            assert_eq!(verify(&x_only(&pub_key), message, &signature), Ok(()));
+            assert_eq!(verify(&x_only(&pub_key), b"another message", &signature), Err(ThresholdError::InvalidSignature));
+            //k256's own bip-340 verifier take it too
+            let verifying_key = k256::schnorr::VerifyingKey::from_bytes(&x_only(&pub_key)).unwrap();
+            let schnorr = k256::schnorr::Signature::try_from(&signature[..]).unwrap();
+            assert!(verifying_key.verify_prehash(message, &schnorr).is_ok());
+
+            //a wrong share is blamed by its place in signer_set
+            let mut bad_s = s.clone();
+            bad_s[2] += Scalar::ONE;
+            assert_eq!(combine(&signer_set, message, &commitment, &bad_s, &verification_shares, &pub_key), Err(ThresholdError::VerificationFailed { party: 2 }));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::ops::Mul;
+use std::time::{Duration, Instant};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload as AeadPayload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ff::Field;
+use hkdf::Hkdf;
+use pasta_curves::group::{cofactor::CofactorCurveAffine, Curve, GroupEncoding};
+use pasta_curves::pallas;
+use sha2::Sha256;
+use rand::{rngs::StdRng, CryptoRng, Rng, RngCore, SeedableRng};
+use myp::ThresholdError;
+use crate::identity::IdentityKey;
+use crate::curve::EcdsaCurve;
+use crate::message::{EchoMsg, Message, Payload, SealedMsg, SessionId, SignedMsg};
+use crate::transport::Transport;
+#[cfg(test)]
+use crate::transport::{memory_network, Faults, MemoryTransport};
+
+//wraps any transport so a point to point message is only readable by its receiver and every
+//message is signed by its sender's identity key
+//  point to point: ephemeral e, E = e * G, key = hkdf(e * P_to), chacha20poly1305 with E and the
+//  header as associated data, then the sender sign the header and the ciphertext
+//  broadcast: the sender sign the encoded message, every receiver echo it to the others on the
+//  first delivery, two different signed messages of the same kind from one party is equivocation
+const P2P_LABEL: &str = "secure-p2p";
+const BROADCAST_LABEL: &str = "secure-broadcast";
+
+pub struct SecureTransport<T: Transport> {
+    inner : T,
+    identity : IdentityKey,
+    //every party's identity key in index order
+    public : Vec<pallas::Affine>,
+    //the first broadcast body of each (sender, kind), to compare the echoes with
+    seen : Vec<(usize, u8, Vec<u8>)>,
+    //seeded from the caller's rng, for the ephemeral keys and the signatures
+    rng : StdRng,
+}
+
+//session id | from | to | ephemeral, the associated data and the start of what the sender sign
+fn sealed_header(session_id: &SessionId, from: usize, to: usize, ephemeral: &pallas::Affine) -> Vec<u8> {
+    let mut header = session_id.to_vec();
+    header.extend_from_slice(&(from as u32).to_be_bytes());
+    header.extend_from_slice(&(to as u32).to_be_bytes());
+    header.extend_from_slice(&ephemeral.to_bytes());
+    header
+}
+
+fn channel_cipher(session_id: &SessionId, shared: pallas::Affine, header: &[u8]) -> ChaCha20Poly1305 {
+    let hkdf = Hkdf::<Sha256>::new(Some(session_id), &shared.to_bytes());
+    let mut key = [0u8; 32];
+    let mut info = P2P_LABEL.as_bytes().to_vec();
+    info.extend_from_slice(header);
+    //32 bytes is far below the limit of 255 * 32
+    hkdf.expand(&info, &mut key).expect("hkdf output length");
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+impl<T: Transport> SecureTransport<T> {
+    pub fn new(rng: &mut (impl CryptoRng + RngCore), inner: T, identity: IdentityKey, public: Vec<pallas::Affine>) -> Result<SecureTransport<T>, ThresholdError> {
+        if public.len() != inner.parties() {
+            return Err(ThresholdError::LengthMismatch { expected: inner.parties(), found: public.len() });
+        }
+        if public[inner.index()] != identity.public() {
+            return Err(ThresholdError::VerificationFailed { party: inner.index() });
+        }
+        Ok(SecureTransport {
+            inner,
+            identity,
+            public,
+            seen : Vec::new(),
+            rng : StdRng::from_seed(rng.gen()),
+        })
+    }
+
+    fn seal<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<Message<P>, ThresholdError> {
+        let from = self.inner.index();
+        let to = match message.to() {
+            Some(to) if to < self.public.len() => to,
+            _ => return Err(ThresholdError::OutOfRange("to")),
+        };
+        //every message has its own ephemeral key, so the key is never used twice and the nonce can be fixed
+        let secret = pallas::Scalar::random(&mut self.rng);
+        let ephemeral = pallas::Affine::mul(pallas::Affine::generator(), secret).to_affine();
+        let shared = pallas::Affine::mul(self.public[to], secret).to_affine();
+        let header = sealed_header(&message.session_id, from, to, &ephemeral);
+        let ciphertext = channel_cipher(&message.session_id, shared, &header)
+            .encrypt(Nonce::from_slice(&[0u8; 12]), AeadPayload { msg : &message.to_bytes(), aad : &header })
+            .map_err(|_| ThresholdError::InvalidEncoding("message is too long to encrypt"))?;
+        let mut data = header;
+        data.extend_from_slice(&ciphertext);
+        Ok(Message::new(message.session_id, Payload::Sealed(SealedMsg {
+            from,
+            to,
+            ephemeral,
+            ciphertext,
+            signature : self.identity.sign(&mut self.rng, P2P_LABEL, &data),
+        })))
+    }
+
+    fn open<P: EcdsaCurve>(&self, session_id: &SessionId, sealed: &SealedMsg) -> Result<Message<P>, ThresholdError> {
+        let index = self.inner.index();
+        let fail = ThresholdError::VerificationFailed { party: sealed.from };
+        if sealed.from >= self.public.len() || sealed.to != index || bool::from(sealed.ephemeral.is_identity()) {
+            return Err(fail);
+        }
+        let header = sealed_header(session_id, sealed.from, sealed.to, &sealed.ephemeral);
+        let mut data = header.clone();
+        data.extend_from_slice(&sealed.ciphertext);
+        if !sealed.signature.verify(self.public[sealed.from], P2P_LABEL, &data) {
+            return Err(fail);
+        }
+        let shared = pallas::Affine::mul(sealed.ephemeral, self.identity.secret()).to_affine();
+        let plain_text = channel_cipher(session_id, shared, &header)
+            .decrypt(Nonce::from_slice(&[0u8; 12]), AeadPayload { msg : &sealed.ciphertext, aad : &header })
+            .map_err(|_| fail.clone())?;
+        let message = Message::from_bytes(&plain_text)?;
+        if message.session_id != *session_id || message.from() != sealed.from || message.to() != Some(index) {
+            return Err(fail);
+        }
+        Ok(message)
+    }
+
+    //Some the first time a broadcast is seen, None for a copy or a forgery, and an error if the
+    //sender signed two different messages of the same kind
+    fn accept<P: EcdsaCurve>(&mut self, session_id: &SessionId, signed: &SignedMsg) -> Result<Option<Message<P>>, ThresholdError> {
+        if signed.from >= self.public.len() || !signed.signature.verify(self.public[signed.from], BROADCAST_LABEL, &signed.body) {
+            return Ok(None);
+        }
+        let message = match Message::from_bytes(&signed.body) {
+            Ok(message) => message,
+            Err(_) => return Ok(None),
+        };
+        if message.session_id != *session_id || message.from() != signed.from || message.to().is_some() {
+            return Ok(None);
+        }
+        let tag = message.payload.tag();
+        if let Some((_, _, body)) = self.seen.iter().find(|(from, kind, _)| *from == signed.from && *kind == tag) {
+            if *body != signed.body {
+                return Err(ThresholdError::VerificationFailed { party: signed.from });
+            }
+            return Ok(None);
+        }
+        self.seen.push((signed.from, tag, signed.body.clone()));
+        if signed.from != self.inner.index() {
+            let echo = Message::<P>::new(*session_id, Payload::Echo(EchoMsg {
+                from : self.inner.index(),
+                signed : signed.clone(),
+            }));
+            //best effort, the sender's own broadcast reach most of the others anyway
+            let _ = self.inner.broadcast(&echo);
+        }
+        Ok(Some(message))
+    }
+}
+
+impl<T: Transport> Transport for SecureTransport<T> {
+    fn index(&self) -> usize {
+        self.inner.index()
+    }
+
+    fn parties(&self) -> usize {
+        self.inner.parties()
+    }
+
+    fn send<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        let sealed = self.seal(message)?;
+        self.inner.send(&sealed)
+    }
+
+    fn broadcast<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        if message.to().is_some() {
+            return Err(ThresholdError::OutOfRange("to"));
+        }
+        let body = message.to_bytes();
+        let signed = SignedMsg {
+            from : self.inner.index(),
+            signature : self.identity.sign(&mut self.rng, BROADCAST_LABEL, &body),
+            body,
+        };
+        //record it, so an echo of anything else under this party's name is caught
+        self.accept::<P>(&message.session_id, &signed)?;
+        self.inner.broadcast(&Message::<P>::new(message.session_id, Payload::Signed(signed)))
+    }
+
+    //unsigned messages, forgeries and messages this party can't open are dropped like lost ones
+    fn receive<P: EcdsaCurve>(&mut self, timeout: Duration) -> Result<Option<Message<P>>, ThresholdError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let outer: Message<P> = match self.inner.receive(deadline.saturating_duration_since(Instant::now()))? {
+                Some(outer) => outer,
+                None => return Ok(None),
+            };
+            let result = match &outer.payload {
+                Payload::Sealed(sealed) => self.open(&outer.session_id, sealed).ok(),
+                Payload::Signed(signed) if signed.from == outer.from() => self.accept(&outer.session_id, signed)?,
+                Payload::Echo(echo) => self.accept(&outer.session_id, &echo.signed)?,
+                _ => None,
+            };
+            if result.is_some() {
+                return Ok(result);
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn reliable(&self) -> bool {
+        self.inner.reliable()
+    }
+}
+
+//a memory network where every party has a fresh identity key
+#[cfg(test)]
+pub fn secure_memory_network(rng: &mut (impl CryptoRng + RngCore), parties: usize, faults: Faults) -> Vec<SecureTransport<MemoryTransport>> {
+    let identity: Vec<IdentityKey> = (0..parties).map(|_| IdentityKey::generate(rng)).collect();
+    let public: Vec<pallas::Affine> = identity.iter().map(|i| i.public()).collect();
+    memory_network(parties, faults).into_iter().zip(identity)
+        .map(|(inner, identity)| SecureTransport {
+            inner,
+            identity,
+            public : public.clone(),
+            seen : Vec::new(),
+            rng : StdRng::from_seed(rng.gen()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use pasta_curves::group::ff::PrimeField;
+    use crate::message::{KeyCommitMsg, KeyShareMsg};
+    use crate::transport::{run_keygen, simulate_over};
+
+    #[test]
+    fn secure_keygen_test(){
+        let lossy = Faults {
+            drop : 0.2,
+            duplicate : 0.2,
+            reorder : 0.3,
+        };
+        let result = simulate_over(secure_memory_network(&mut rand::thread_rng(), 3, lossy), |session| {
+            run_keygen::<_, pallas::Affine>(session, &mut rand::thread_rng(), 2)
+        });
+        let generator = pallas::Affine::generator();
+        let pub_key = result[0].as_ref().unwrap().1;
+        for (index, i) in result.into_iter().enumerate() {
+            let (key_share, key, verification_share) = i.unwrap();
+            assert_eq!(key, pub_key);
+            assert_eq!(verification_share[index], pallas::Affine::mul(generator, key_share.expose()).to_affine());
+        }
+    }
+
+    #[test]
+    fn sealed_test(){
+        let mut network = secure_memory_network(&mut rand::thread_rng(), 3, Faults::none());
+        let session_id = [3u8; 32];
+        let message: Message = Message::new(session_id, Payload::KeyShare(KeyShareMsg { from : 0, to : 1, key_share : pallas::Scalar::from_u128(0xbc9a_7856_3412) }));
+        let sealed = network[0].seal(&message).unwrap();
+        let inner = match &sealed.payload {
+            Payload::Sealed(i) => i.clone(),
+            _ => panic!("not sealed"),
+        };
+        assert!(!inner.ciphertext.windows(6).any(|i| i == [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]));
+        assert_eq!(network[1].open::<pallas::Affine>(&session_id, &inner), Ok(message));
+
+        //party 2 pretend it is the receiver, it doesn't have the key
+        let mut stolen = inner.clone();
+        stolen.to = 2;
+        assert!(network[2].open::<pallas::Affine>(&session_id, &stolen).is_err());
+
+        //a flipped bit or another session fail the signature
+        let mut broken = inner.clone();
+        broken.ciphertext[0] ^= 1;
+        assert_eq!(network[1].open::<pallas::Affine>(&session_id, &broken), Err(ThresholdError::VerificationFailed { party: 0 }));
+        assert!(network[1].open::<pallas::Affine>(&[4u8; 32], &inner).is_err());
+    }
+
+    #[test]
+    fn equivocation_test(){
+        let mut network = secure_memory_network(&mut rand::thread_rng(), 3, Faults::none());
+        let session_id = [5u8; 32];
+        let generator = pallas::Affine::generator();
+        let first: Message = Message::new(session_id, Payload::KeyCommit(KeyCommitMsg { from : 0, commitment : vec![generator] }));
+        network[0].broadcast(&first).unwrap();
+        assert_eq!(network[1].receive(Duration::from_millis(100)), Ok(Some(first.clone())));
+
+        //party 0 tell party 2 something else under the same kind, party 2 echo it to party 1
+        let other: Message = Message::new(session_id, Payload::KeyCommit(KeyCommitMsg {
+            from : 0,
+            commitment : vec![pallas::Affine::mul(generator, pallas::Scalar::from_u128(2)).to_affine()],
+        }));
+        let body = other.to_bytes();
+        let signed = SignedMsg {
+            from : 0,
+            signature : network[0].identity.sign(&mut rand::thread_rng(), BROADCAST_LABEL, &body),
+            body,
+        };
+        let echo: Message = Message::new(session_id, Payload::Echo(EchoMsg { from : 2, signed }));
+        network[2].inner.broadcast(&echo).unwrap();
+        assert_eq!(network[1].receive::<pallas::Affine>(Duration::from_millis(100)), Err(ThresholdError::VerificationFailed { party: 0 }));
+
+        //a plain message without signature never come through
+        let mut network = secure_memory_network(&mut rand::thread_rng(), 3, Faults::none());
+        let plain: Message = Message::new(session_id, Payload::KeyCommit(KeyCommitMsg { from : 2, commitment : vec![generator] }));
+        network[2].inner.broadcast(&plain).unwrap();
+        assert_eq!(network[0].receive::<pallas::Affine>(Duration::from_millis(100)), Ok(None));
+    }
+}
@@ -0,0 +1,488 @@
+use num_bigint::BigUint;
+use pasta_curves::group::{ff::PrimeField, Curve, GroupEncoding};
+use pasta_curves::pallas;
+use myp::ThresholdError;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use crate::curve::{EcdsaCurve, EcdsaSignature};
+use crate::bip340;
+use crate::daemon::{self, decode_hex, encode_hex, parse_big, parse_hex, parse_point, parse_point_on, parse_scalar, parse_scalar_on, parse_u128, Ceremony, DaemonConfig, SignCurve};
+use crate::identifiable_abort::{commitment_r, identify_abort, ShareCheck};
+use crate::identity::IdentityKey;
+use crate::paillier_modulus::{generate_paillier_key, PaillierModulusProof};
+use crate::ring_pedersen::{RingPedersenParams, RingPedersenProof};
+use crate::tool::to_hex;
+use crate::verify_in_circuit;
+
+//every command read `name value ...` lines like the daemon config and print the same kind of lines,
+//so what one command print can be given to the next
+pub const USAGE: &str = "usage: myp <command> <file>...
+  identity                          a new identity key for the daemon config
+  paillier-keygen                   pri_p, pri_q, mta_pub_n and ring pedersen params with their proof
+  prove <paillier> <verifier>       modulus proof of our mta_pub_n to the verifier's ring pedersen params
+  keygen <config> [file]...         run the key ceremony, print the key file
+  sign <config> [file]...           run the signing ceremony, print the signature and our share
+  reshare <config> <key file>...    share the key again to a new threshold, print the new key file
+  verify [--circuit] <file>...      check a signature and every modulus proof in the files
+  audit <file>...                   find who sent a wrong share, from the sign output of every party
+  daemon <config> [file]...         run whichever ceremony the config has
+a key_file in the config is opened and written with the passphrase in MYP_PASSPHRASE
+";
+
+//the lines of every file given to a command, # start a comment
+struct Settings {
+    line : Vec<(String, Vec<String>)>,
+}
+
+fn read_files(path: &[&str]) -> Result<String, ThresholdError> {
+    if path.is_empty() {
+        return Err(ThresholdError::InvalidEncoding("no file given, see myp help"));
+    }
+    let mut text = String::new();
+    for i in path {
+        text += &std::fs::read_to_string(i)?;
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+impl Settings {
+    fn parse(text: &str) -> Settings {
+        let mut line = Vec::new();
+        for i in text.lines() {
+            let mut word = i.split('#').next().unwrap_or("").split_whitespace();
+            if let Some(name) = word.next() {
+                line.push((name.to_string(), word.map(|i| i.to_string()).collect()));
+            }
+        }
+        Settings { line }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.line.iter().any(|i| i.0 == name)
+    }
+
+    fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [String]> + 'a {
+        self.line.iter().filter(move |i| i.0 == name).map(|i| i.1.as_slice())
+    }
+
+    //a setting can be in more than one file, but it must be the same everywhere
+    fn values(&self, name: &'static str) -> Result<&[String], ThresholdError> {
+        let mut found = self.all(name);
+        let first = found.next().ok_or(ThresholdError::InvalidEncoding(name))?;
+        if found.any(|i| i != first) {
+            return Err(ThresholdError::InvalidEncoding(name));
+        }
+        Ok(first)
+    }
+
+    fn one(&self, name: &'static str) -> Result<&str, ThresholdError> {
+        match self.values(name)? {
+            [value] => Ok(value),
+            _ => Err(ThresholdError::InvalidEncoding(name)),
+        }
+    }
+}
+
+//the digest, pub key and r, s and recovery id of a sign output, read on its curve
+fn ecdsa_signature<P: EcdsaCurve>(settings: &Settings) -> Result<([u8; 32], P, EcdsaSignature<P>), ThresholdError> {
+    let recovery_id = parse_u128(settings.one("recovery_id")?)?;
+    if recovery_id > 3 {
+        return Err(ThresholdError::OutOfRange("recovery_id"));
+    }
+    let signature = EcdsaSignature {
+        r : parse_scalar_on(settings.one("r")?)?,
+        s : parse_scalar_on(settings.one("s")?)?,
+        recovery_id : recovery_id as u8,
+    };
+    Ok((parse_hex(settings.one("digest")?)?, parse_point_on(settings.one("pub_key")?)?, signature))
+}
+
+//s * R == m * G + r * pub_key with r the x of R, for pallas which has no standard verifier
+fn signature_valid<P: EcdsaCurve>(settings: &Settings) -> Result<bool, ThresholdError> {
+    let (digest, pub_key, signature) = ecdsa_signature::<P>(settings)?;
+    let commitment: P = parse_point_on(settings.one("commitment")?)?;
+    let (r, _) = commitment.ecdsa_r()?;
+    Ok(r == signature.r && (commitment * signature.s).to_affine() == (P::generator() * P::digest_scalar(&digest) + pub_key * r).to_affine())
+}
+
+//the k256 verifier, and the recovery id give the pub key back
+fn secp256k1_valid(settings: &Settings) -> Result<bool, ThresholdError> {
+    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+    let (digest, pub_key, signature) = ecdsa_signature::<k256::AffinePoint>(settings)?;
+    let key = VerifyingKey::from_affine(pub_key).map_err(|_| ThresholdError::InvalidEncoding("pub_key"))?;
+    let Ok(ecdsa) = Signature::from_slice(&signature.to_bytes()) else {
+        return Ok(false);
+    };
+    let recovered = RecoveryId::from_byte(signature.recovery_id).and_then(|i| VerifyingKey::recover_from_prehash(&digest, &ecdsa, i).ok());
+    Ok(key.verify_prehash(&digest, &ecdsa).is_ok() && recovered == Some(key))
+}
+
+//the same with the p256 verifier
+fn p256_valid(settings: &Settings) -> Result<bool, ThresholdError> {
+    use p256::ecdsa::{Signature, VerifyingKey};
+    use k256::ecdsa::RecoveryId;
+    let (digest, pub_key, signature) = ecdsa_signature::<p256::AffinePoint>(settings)?;
+    let key = VerifyingKey::from_affine(pub_key).map_err(|_| ThresholdError::InvalidEncoding("pub_key"))?;
+    let Ok(ecdsa) = Signature::from_slice(&signature.to_bytes()) else {
+        return Ok(false);
+    };
+    let recovered = RecoveryId::from_byte(signature.recovery_id).and_then(|i| VerifyingKey::recover_from_prehash(&digest, &ecdsa, i).ok());
+    Ok(key.verify_prehash(&digest, &ecdsa).is_ok() && recovered == Some(key))
+}
+
+//a bip340 sign output, the message is the 32 byte sighash and pub_key the secp256k1 group key point
+fn bip340_valid(settings: &Settings) -> Result<bool, ThresholdError> {
+    if sign_curve(settings)? != SignCurve::Secp256k1 || settings.one("scheme")? != "bip340" {
+        return Err(ThresholdError::InvalidEncoding("signature is bip340 on secp256k1"));
+    }
+    let message: [u8; 32] = parse_hex(settings.one("message")?)?;
+    let signature: [u8; 64] = parse_hex(settings.one("signature")?)?;
+    let pub_key: k256::AffinePoint = parse_point_on(settings.one("pub_key")?)?;
+    Ok(bip340::verify(&bip340::x_only(&pub_key), &message, &signature).is_ok())
+}
+
+//the curve line of a sign output, pallas if there is none
+fn sign_curve(settings: &Settings) -> Result<SignCurve, ThresholdError> {
+    if !settings.has("curve") {
+        return Ok(SignCurve::Pallas);
+    }
+    SignCurve::parse(settings.one("curve")?)
+}
+
+//the passphrase of a key_file in the config
+const PASSPHRASE_VAR: &str = "MYP_PASSPHRASE";
+
+fn ceremony(rng: &mut (impl CryptoRng + RngCore), command: &str, path: &[&str]) -> Result<String, ThresholdError> {
+    let passphrase = std::env::var(PASSPHRASE_VAR).unwrap_or_default();
+    let config = DaemonConfig::parse_with_passphrase(&read_files(path)?, passphrase.as_bytes())?;
+    match (command, &config.ceremony) {
+        ("daemon", _) | ("keygen", Ceremony::Keygen { .. }) | ("sign", Ceremony::Sign { .. } | Ceremony::SignBip340 { .. }) | ("reshare", Ceremony::Reshare { .. }) => daemon::run(config, rng),
+        _ => Err(ThresholdError::InvalidEncoding("config is for another ceremony")),
+    }
+}
+
+fn paillier_keygen(rng: &mut (impl CryptoRng + RngCore)) -> String {
+    let (pri_p, pri_q) = generate_paillier_key(rng);
+    let (params, proof) = RingPedersenParams::generate(rng);
+    paillier_file(&pri_p, &pri_q, &params, &proof)
+}
+
+fn paillier_file(pri_p: &BigUint, pri_q: &BigUint, params: &RingPedersenParams, proof: &RingPedersenProof) -> String {
+    format!("# keep pri_p and pri_q, publish the rest\npri_p {}\npri_q {}\nmta_pub_n {}\nring_pedersen {} {}\n",
+        pri_p, pri_q, pri_p * pri_q, encode_hex(params), encode_hex(proof))
+}
+
+fn ring_pedersen(settings: &Settings) -> Result<(RingPedersenParams, RingPedersenProof), ThresholdError> {
+    match settings.values("ring_pedersen")? {
+        [params, proof] => Ok((decode_hex(params)?, decode_hex(proof)?)),
+        _ => Err(ThresholdError::InvalidEncoding("ring_pedersen")),
+    }
+}
+
+fn prove(rng: &mut (impl CryptoRng + RngCore), own: &str, verifier: &str) -> Result<String, ThresholdError> {
+    let own = Settings::parse(&read_files(&[own])?);
+    let verifier = Settings::parse(&read_files(&[verifier])?);
+    let pri_p = parse_big(own.one("pri_p")?)?;
+    let pri_q = parse_big(own.one("pri_q")?)?;
+    //params without a valid proof could let the verifier learn about p and q
+    let (params, proof) = ring_pedersen(&verifier)?;
+    if !params.verify(&proof) {
+        return Err(ThresholdError::InvalidModulus(params.n_tilde));
+    }
+    let modulus_proof = PaillierModulusProof::prove(rng, &pri_p, &pri_q, &params)?;
+    Ok(format!("modulus_proof {} {}\n", &pri_p * &pri_q, encode_hex(&modulus_proof)))
+}
+
+fn verify(path: &[&str], circuit: bool) -> Result<String, ThresholdError> {
+    let settings = Settings::parse(&read_files(path)?);
+    let mut checked = false;
+    if settings.has("modulus_proof") {
+        //made to our own ring pedersen params
+        let (params, _) = ring_pedersen(&settings)?;
+        for i in settings.all("modulus_proof") {
+            let [mta_pub_n, proof] = i else {
+                return Err(ThresholdError::InvalidEncoding("modulus_proof"));
+            };
+            let mta_pub_n = parse_big(mta_pub_n)?;
+            if !decode_hex::<PaillierModulusProof>(proof)?.verify(&mta_pub_n, &params) {
+                return Err(ThresholdError::InvalidModulus(mta_pub_n));
+            }
+        }
+        checked = true;
+    }
+    if settings.has("s") {
+        let curve = sign_curve(&settings)?;
+        let valid = match curve {
+            SignCurve::Pallas => signature_valid::<pallas::Affine>(&settings)?,
+            SignCurve::Secp256k1 => secp256k1_valid(&settings)?,
+            SignCurve::P256 => p256_valid(&settings)?,
+        };
+        if !valid {
+            return Err(ThresholdError::InvalidSignature);
+        }
+        //the circuit is on pallas
+        if circuit {
+            if curve != SignCurve::Pallas {
+                return Err(ThresholdError::InvalidEncoding("--circuit is only for pallas"));
+            }
+            let digest = parse_hex(settings.one("digest")?)?;
+            let commitment = parse_point(settings.one("commitment")?)?;
+            let s = parse_scalar(settings.one("s")?)?;
+            let pub_key = parse_point(settings.one("pub_key")?)?;
+            if !verify_in_circuit(commitment_r(commitment)?, s, commitment, pallas::Affine::digest_scalar(&digest), pub_key) {
+                return Err(ThresholdError::InvalidSignature);
+            }
+        }
+        checked = true;
+    }
+    if settings.has("signature") {
+        if circuit {
+            return Err(ThresholdError::InvalidEncoding("--circuit is only for pallas"));
+        }
+        if !bip340_valid(&settings)? {
+            return Err(ThresholdError::InvalidSignature);
+        }
+        checked = true;
+    }
+    if !checked {
+        return Err(ThresholdError::InvalidEncoding("nothing to verify"));
+    }
+    Ok("valid\n".to_string())
+}
+
+//share <index> <s_i> <share check> of every party and share_commitment <index> <k_i * G> <sigma_i * G>
+//of every party's round 3 message, which every sign output has
+fn audit(path: &[&str]) -> Result<String, ThresholdError> {
+    let settings = Settings::parse(&read_files(path)?);
+    let cheater = match sign_curve(&settings)? {
+        SignCurve::Pallas => audit_on::<pallas::Affine>(&settings)?,
+        SignCurve::Secp256k1 => audit_on::<k256::AffinePoint>(&settings)?,
+        SignCurve::P256 => audit_on::<p256::AffinePoint>(&settings)?,
+    };
+    if cheater.is_empty() {
+        return Ok("valid\n".to_string());
+    }
+    let cheater: Vec<String> = cheater.iter().map(|i| i.to_string()).collect();
+    Ok(format!("cheater {}\n", cheater.join(" ")))
+}
+
+//identify_abort on the curve of the sign output
+fn audit_on<P: EcdsaCurve>(settings: &Settings) -> Result<Vec<usize>, ThresholdError> {
+    let digest = parse_hex(settings.one("digest")?)?;
+    let commitment: P = parse_point_on(settings.one("commitment")?)?;
+    let pub_key: P = parse_point_on(settings.one("pub_key")?)?;
+    let mut share: Vec<Option<(P::Scalar, ShareCheck<P>)>> = Vec::new();
+    for i in settings.all("share") {
+        let [index, s, check] = i else {
+            return Err(ThresholdError::InvalidEncoding("share"));
+        };
+        let index = parse_u128(index)? as usize;
+        if index >= 1 << 16 {
+            return Err(ThresholdError::OutOfRange("share index"));
+        }
+        if share.len() <= index {
+            share.resize(index + 1, None);
+        }
+        let value = (parse_scalar_on(s)?, decode_hex(check)?);
+        if share[index].is_some_and(|i| i != value) {
+            return Err(ThresholdError::InvalidEncoding("share"));
+        }
+        share[index] = Some(value);
+    }
+    let member = share.len();
+    let share: Vec<(P::Scalar, ShareCheck<P>)> = share.into_iter().flatten().collect();
+    if share.len() != member || member == 0 {
+        return Err(ThresholdError::LengthMismatch { expected: member, found: share.len() });
+    }
+    let mut share_commitment = vec![None; member];
+    for i in settings.all("share_commitment") {
+        let [index, k, sigma] = i else {
+            return Err(ThresholdError::InvalidEncoding("share_commitment"));
+        };
+        let index = parse_u128(index)? as usize;
+        if index >= member {
+            return Err(ThresholdError::OutOfRange("share_commitment index"));
+        }
+        let value = (parse_point_on(k)?, parse_point_on(sigma)?);
+        if share_commitment[index].is_some_and(|i| i != value) {
+            return Err(ThresholdError::InvalidEncoding("share_commitment"));
+        }
+        share_commitment[index] = Some(value);
+    }
+    let share_commitment: Vec<(P, P)> = share_commitment.into_iter().flatten().collect();
+    let (s, check): (Vec<_>, Vec<_>) = share.into_iter().unzip();
+    identify_abort(&digest, commitment, pub_key, &share_commitment, &s, &check)
+}
+
+//args without the program name, out put what to print
+pub fn run(args: &[String]) -> Result<String, ThresholdError> {
+    let args: Vec<&str> = args.iter().map(|i| i.as_str()).collect();
+    //keys and proofs leave the machine, so they are drawn from the os
+    let rng = &mut OsRng;
+    match args.as_slice() {
+        [] | ["help"] => Ok(USAGE.to_string()),
+        ["identity"] => {
+            let key = IdentityKey::generate(rng);
+            Ok(format!("identity {}\npublic {}\n", to_hex(&key.secret().to_repr()), to_hex(&key.public().to_bytes())))
+        }
+        ["paillier-keygen"] => Ok(paillier_keygen(rng)),
+        ["prove", own, verifier] => prove(rng, own, verifier),
+        [command @ ("keygen" | "sign" | "reshare" | "daemon"), path @ ..] => ceremony(rng, command, path),
+        ["verify", "--circuit", path @ ..] => verify(path, true),
+        ["verify", path @ ..] => verify(path, false),
+        ["audit", path @ ..] => audit(path),
+        _ => Err(ThresholdError::InvalidEncoding("unknown command, see myp help")),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::Mul;
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use rand::Rng;
+    use ff::Field;
+    use myp::Secret;
+    use crate::paillier_modulus::test_paillier_key;
+    use crate::party::{test_party_secret, Party, PartySecret};
+    use crate::ring_pedersen::{test_ring_pedersen, test_ring_pedersen_params};
+    use crate::transport::{run_signing, simulate, Faults};
+
+    fn args(text: &[&str]) -> Vec<String> {
+        text.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn cli_test(){
+        let dir = std::env::temp_dir().join(format!("myp-cli-{}", to_hex(&rand::thread_rng().gen::<[u8; 8]>())));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, text: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+
+        //every party make a paillier key and prove it to the others, the keys are the fixed ones
+        //as paillier-keygen take too long to find them in a test
+        let paillier: Vec<String> = (0..3).map(|i| {
+            let (pri_p, pri_q) = test_paillier_key(i);
+            let (params, proof) = test_ring_pedersen(i);
+            file(&format!("paillier{}", i), &paillier_file(&pri_p, &pri_q, &params, &proof))
+        }).collect();
+        let proof = file("proof", &run(&args(&["prove", &paillier[0], &paillier[1]])).unwrap());
+        assert_eq!(run(&args(&["verify", &paillier[1], &proof])), Ok("valid\n".to_string()));
+        //made to party 1, party 2 can't use it
+        let modulus = parse_big(Settings::parse(&std::fs::read_to_string(&paillier[0]).unwrap()).one("mta_pub_n").unwrap()).unwrap();
+        assert_eq!(run(&args(&["verify", &paillier[2], &proof])), Err(ThresholdError::InvalidModulus(modulus)));
+
+        //sign with the generated paillier keys, then verify and audit the output
+        let key: Vec<Settings> = paillier.iter().map(|i| Settings::parse(&std::fs::read_to_string(i).unwrap())).collect();
+        let value = |i: usize, name: &'static str| parse_big(key[i].one(name).unwrap()).unwrap();
+        let mta_pub_n: Vec<BigUint> = (0..3).map(|i| value(i, "mta_pub_n")).collect();
+        let w: Vec<pallas::Scalar> = (0..3).map(|_| pallas::Scalar::random(rand::thread_rng())).collect();
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let result = simulate(3, Faults::none(), |session| {
+            let i = session.index();
+            let secret = PartySecret::<pallas::Affine> {
+                k : Secret::new(pallas::Scalar::random(rand::thread_rng())),
+                r : Secret::new(pallas::Scalar::random(rand::thread_rng())),
+                w : Secret::new(w[i]),
+                pri_p : Secret::new(value(i, "pri_p")),
+                pri_q : Secret::new(value(i, "pri_q")),
+            };
+            let mut party = Party::new(i, secret, mta_pub_n.clone(), test_ring_pedersen_params(3))?;
+            run_signing(session, &mut rand::thread_rng(), &mut party, &digest)
+        });
+        let result = match result.into_iter().collect::<Result<Vec<_>, ThresholdError>>() {
+            Ok(result) => result,
+            //x of R doesn't fit in the scalar field, too rare to care in a test
+            Err(ThresholdError::OutOfRange("r")) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let w: pallas::Scalar = w.iter().sum();
+        let pub_key = pallas::Affine::mul(pallas::Affine::generator(), w).to_affine();
+        let pub_key = file("pub_key", &format!("pub_key {}\n", to_hex(&pub_key.to_bytes())));
+        let output: Vec<String> = result.iter().enumerate().map(|(i, (commitment, s, own, round3))| {
+            let mut text = daemon::sign_output(&digest, *commitment, *s).unwrap();
+            text += &format!("share {} {} {}\n", i, to_hex(&own.s.to_repr()), encode_hex(&own.share_check(&mut rand::thread_rng(), *commitment)));
+            for j in round3 {
+                text += &format!("share_commitment {} {} {}\n", j.from, to_hex(&j.k_commitment.to_bytes()), to_hex(&j.sigma_commitment.to_bytes()));
+            }
+            file(&format!("sign{}", i), &text)
+        }).collect();
+        assert_eq!(run(&args(&["verify", &output[0], &pub_key])), Ok("valid\n".to_string()));
+        assert_eq!(run(&args(&["audit", &output[0], &output[1], &output[2], &pub_key])), Ok("valid\n".to_string()));
+
+        //party 1 lied about s_1
+        let (commitment, s, own, _) = &result[1];
+        let bad_s = pallas::Scalar::add(&own.s, &pallas::Scalar::one());
+        let bad = file("bad", &(daemon::sign_output(&digest, *commitment, pallas::Scalar::add(s, &pallas::Scalar::one())).unwrap()
+            + &format!("share 1 {} {}\n", to_hex(&bad_s.to_repr()), encode_hex(&own.share_check(&mut rand::thread_rng(), *commitment)))));
+        assert_eq!(run(&args(&["verify", &bad, &pub_key])), Err(ThresholdError::InvalidSignature));
+        assert_eq!(run(&args(&["audit", &output[0], &bad, &output[2], &pub_key])), Ok("cheater 1\n".to_string()));
+        //every party's share is needed, and two files can't disagree
+        assert_eq!(run(&args(&["audit", &output[0], &output[2], &pub_key])), Err(ThresholdError::LengthMismatch { expected: 3, found: 2 }));
+        assert_eq!(run(&args(&["verify", &output[0], &bad, &pub_key])), Err(ThresholdError::InvalidEncoding("s")));
+        //the round 3 commitments are the same in every output, party 1 can't give its own
+        let text = std::fs::read_to_string(&output[1]).unwrap();
+        let k_commitment = to_hex(&result[1].3[1].k_commitment.to_bytes());
+        let moved = file("moved", &text.replace(&format!("share_commitment 1 {}", k_commitment), &format!("share_commitment 1 {}", to_hex(&pallas::Affine::generator().to_bytes()))));
+        assert_eq!(run(&args(&["audit", &output[0], &moved, &output[2], &pub_key])), Err(ThresholdError::InvalidEncoding("share_commitment")));
+
+        //a P-256 signature is read on the curve its output name
+        let secret: Vec<PartySecret<p256::AffinePoint>> = (0..3).map(|i| test_party_secret(&mut rand::thread_rng(), i)).collect();
+        let w: p256::Scalar = secret.iter().map(|i| *i.w.expose()).sum();
+        let mta_pub_n: Vec<BigUint> = secret.iter().map(|i| i.pri_p.expose() * i.pri_q.expose()).collect();
+        let secret: Vec<std::sync::Mutex<Option<PartySecret<p256::AffinePoint>>>> = secret.into_iter().map(|i| std::sync::Mutex::new(Some(i))).collect();
+        let result = simulate(3, Faults::none(), |session| {
+            let own = secret[session.index()].lock().unwrap().take().ok_or(ThresholdError::UnexpectedRound(1))?;
+            let mut party = Party::new(session.index(), own, mta_pub_n.clone(), test_ring_pedersen_params(3))?;
+            run_signing(session, &mut rand::thread_rng(), &mut party, &digest)
+        });
+        if let Ok(result) = result.into_iter().collect::<Result<Vec<_>, ThresholdError>>() {
+            let (commitment, s, _, _) = &result[0];
+            let pub_key = (p256::AffinePoint::GENERATOR * w).to_affine();
+            let text = daemon::sign_output(&digest, *commitment, *s).unwrap() + &format!("pub_key {}\n", to_hex(&pub_key.to_bytes()));
+            let p256 = file("p256", &text);
+            assert_eq!(run(&args(&["verify", &p256])), Ok("valid\n".to_string()));
+            //the recovery id is checked too, it must give the pub key back
+            let recovery_id = Settings::parse(&text).one("recovery_id").unwrap().to_string();
+            let flipped = file("flipped", &text.replace(&format!("recovery_id {}", recovery_id), &format!("recovery_id {}", parse_u128(&recovery_id).unwrap() ^ 1)));
+            assert_eq!(run(&args(&["verify", &flipped])), Err(ThresholdError::InvalidSignature));
+            assert_eq!(run(&args(&["verify", "--circuit", &p256])), Err(ThresholdError::InvalidEncoding("--circuit is only for pallas")));
+            let wrong = file("wrong", &text.replace("curve p256", "curve secp256k1"));
+            assert!(run(&args(&["verify", &wrong])).is_err());
+
+            //audit on P-256, party 0 and 2 both lied about their share and both are named
+            let audit: Vec<String> = result.iter().enumerate().map(|(i, (commitment, _, own, round3))| {
+                let s = if i == 1 { own.s } else { own.s + p256::Scalar::ONE };
+                let mut text = format!("curve p256\ndigest {}\ncommitment {}\nshare {} {} {}\n", to_hex(&digest), to_hex(&commitment.to_bytes()),
+                    i, to_hex(&s.to_repr()), encode_hex(&own.share_check(&mut rand::thread_rng(), *commitment)));
+                for j in round3 {
+                    let (k, sigma) = j.share_commitment();
+                    text += &format!("share_commitment {} {} {}\n", j.from, to_hex(&k.to_bytes()), to_hex(&sigma.to_bytes()));
+                }
+                file(&format!("p256-audit{}", i), &text)
+            }).collect();
+            let p256_key = file("p256-key", &format!("pub_key {}\n", to_hex(&pub_key.to_bytes())));
+            assert_eq!(run(&args(&["audit", &audit[0], &audit[1], &audit[2], &p256_key])), Ok("cheater 0 2\n".to_string()));
+        }
+
+        //a bip340 signature with the secp256k1 key it is under
+        let message: [u8; 32] = rand::thread_rng().gen();
+        let secret = Secret::new(k256::Scalar::random(&mut rand::thread_rng()));
+        let pub_key = bip340::pub_key_share(&secret);
+        let signature = bip340::sign(&secret, &message, &[0u8; 32]).unwrap();
+        let text = format!("curve secp256k1\nscheme bip340\nmessage {}\nsignature {}\npub_key {}\n", to_hex(&message), to_hex(&signature), to_hex(&pub_key.to_bytes()));
+        assert_eq!(run(&args(&["verify", &file("bip340", &text)])), Ok("valid\n".to_string()));
+        let other = file("bip340-other", &text.replace(&to_hex(&message), &to_hex(&[0u8; 32])));
+        assert_eq!(run(&args(&["verify", &other])), Err(ThresholdError::InvalidSignature));
+        let ecdsa = file("bip340-ecdsa", &text.replace("scheme bip340", "scheme ecdsa"));
+        assert_eq!(run(&args(&["verify", &ecdsa])), Err(ThresholdError::InvalidEncoding("signature is bip340 on secp256k1")));
+
+        assert!(run(&args(&["frobnicate"])).is_err());
+        assert_eq!(run(&args(&["verify"])), Err(ThresholdError::InvalidEncoding("no file given, see myp help")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
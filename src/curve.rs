@@ -0,0 +1,322 @@
+use ff::{FromUniformBytes, PrimeField};
+use num_bigint::BigUint;
+use k256::elliptic_curve::{bigint::{U256, U512}, generic_array::GenericArray, ops::Reduce, point::AffineCoordinates, scalar::IsHigh, Curve, FieldBytes};
+use pasta_curves::arithmetic::{Coordinates, CurveAffine};
+use pasta_curves::group::{prime::PrimeCurveAffine, GroupEncoding};
+use pasta_curves::{pallas, vesta};
+use myp::{ThresholdError, Wipe};
+
+//the affine point of a curve the key generation, lagrange and schnorr code run on. all the arithmetic
+//come from group and ff, a new curve is one impl of this and EcdsaCurve for threshold ecdsa on it
+pub trait ThresholdCurve: PrimeCurveAffine<Scalar: Wipe, Curve: pasta_curves::group::Curve<AffineRepr = Self>> + GroupEncoding {
+    //the name a key file and a config know the curve by
+    const NAME: &'static str;
+
+    //64 uniform bytes to a scalar, the bias is 2^-256 at most
+    fn scalar_from_wide(bytes: &[u8; 64]) -> Self::Scalar;
+}
+
+//what threshold ecdsa need on top of the group
+pub trait EcdsaCurve: ThresholdCurve {
+    //secp256k1 verifiers only take the low s, the recovery id flip with s
+    const LOW_S: bool = false;
+
+    //r = x(R) mod n and the recovery id, bit 0 is y of R odd and bit 1 is x of R >= n
+    fn ecdsa_r(&self) -> Result<(Self::Scalar, u8), ThresholdError>;
+
+    //a 32 byte message digest as the scalar m, reduced the way the curve's standard verifier do
+    fn digest_scalar(digest: &[u8; 32]) -> Self::Scalar;
+
+    fn is_high(_s: &Self::Scalar) -> bool {
+        false
+    }
+}
+
+//the integer of a scalar for the paillier mta, the repr is little endian on pasta and big endian
+//on the rustcrypto curves, the repr of one tell which
+pub fn scalar_to_big<F: PrimeField>(value: &F) -> BigUint {
+    let repr = value.to_repr();
+    if F::ONE.to_repr().as_ref()[0] == 1 {
+        BigUint::from_bytes_le(repr.as_ref())
+    } else {
+        BigUint::from_bytes_be(repr.as_ref())
+    }
+}
+
+//value mod the scalar modulus, horner over the u64 digits from the top
+pub fn big_to_scalar<F: PrimeField>(value: &BigUint) -> F {
+    let base = F::from_u128(1 << 64);
+    value.to_u64_digits().iter().rev().fold(F::ZERO, |acc, i| acc * base + F::from(*i))
+}
+
+//the order q of the scalar field
+pub fn scalar_modulus<F: PrimeField>() -> BigUint {
+    scalar_to_big(&-F::ONE) + 1u32
+}
+
+//pallas and vesta are a cycle, x of a point is in the other curve's scalar field with the same repr.
+//an x at or above the scalar modulus has no r, it is too rare to reduce for
+fn pasta_r<C: CurveAffine>(point: &C) -> Result<(C::ScalarExt, u8), ThresholdError>
+where C::ScalarExt: PrimeField<Repr = <C::Base as PrimeField>::Repr> {
+    let coordinates: Coordinates<C> = Option::from(point.coordinates()).ok_or(ThresholdError::InvalidPoint)?;
+    let r = Option::from(C::ScalarExt::from_repr(coordinates.x().to_repr())).ok_or(ThresholdError::OutOfRange("r"))?;
+    Ok((r, bool::from(coordinates.y().is_odd()) as u8))
+}
+
+//big endian digest, reduced mod the scalar modulus
+fn pasta_digest<F: FromUniformBytes<64>>(digest: &[u8; 32]) -> F {
+    let mut wide = [0u8; 64];
+    for (i, byte) in digest.iter().rev().enumerate() {
+        wide[i] = *byte;
+    }
+    F::from_uniform_bytes(&wide)
+}
+
+impl ThresholdCurve for pallas::Affine {
+    const NAME: &'static str = "pallas";
+
+    fn scalar_from_wide(bytes: &[u8; 64]) -> pallas::Scalar {
+        pallas::Scalar::from_uniform_bytes(bytes)
+    }
+}
+
+impl EcdsaCurve for pallas::Affine {
+    fn ecdsa_r(&self) -> Result<(pallas::Scalar, u8), ThresholdError> {
+        pasta_r(self)
+    }
+
+    fn digest_scalar(digest: &[u8; 32]) -> pallas::Scalar {
+        pasta_digest(digest)
+    }
+}
+
+impl ThresholdCurve for vesta::Affine {
+    const NAME: &'static str = "vesta";
+
+    fn scalar_from_wide(bytes: &[u8; 64]) -> vesta::Scalar {
+        vesta::Scalar::from_uniform_bytes(bytes)
+    }
+}
+
+impl EcdsaCurve for vesta::Affine {
+    fn ecdsa_r(&self) -> Result<(vesta::Scalar, u8), ThresholdError> {
+        pasta_r(self)
+    }
+
+    fn digest_scalar(digest: &[u8; 32]) -> vesta::Scalar {
+        pasta_digest(digest)
+    }
+}
+
+//x of R for the curves from the rustcrypto elliptic-curve crates, in big endian
+fn x_scalar<C: Curve, S: Reduce<U256, Bytes = FieldBytes<C>> + PrimeField<Repr = FieldBytes<C>>>(x: &FieldBytes<C>, y_odd: bool) -> (S, u8) {
+    let r = S::reduce_bytes(x);
+    let overflow = r.to_repr() != *x;
+    (r, y_odd as u8 | (overflow as u8) << 1)
+}
+
+impl ThresholdCurve for k256::AffinePoint {
+    const NAME: &'static str = "secp256k1";
+
+    fn scalar_from_wide(bytes: &[u8; 64]) -> k256::Scalar {
+        <k256::Scalar as Reduce<U512>>::reduce_bytes(GenericArray::from_slice(bytes))
+    }
+}
+
+impl EcdsaCurve for k256::AffinePoint {
+    const LOW_S: bool = true;
+
+    fn ecdsa_r(&self) -> Result<(k256::Scalar, u8), ThresholdError> {
+        if bool::from(self.is_identity()) {
+            return Err(ThresholdError::InvalidPoint);
+        }
+        Ok(x_scalar::<k256::Secp256k1, k256::Scalar>(&self.x(), self.y_is_odd().into()))
+    }
+
+    fn digest_scalar(digest: &[u8; 32]) -> k256::Scalar {
+        <k256::Scalar as Reduce<U256>>::reduce_bytes(digest.into())
+    }
+
+    fn is_high(s: &k256::Scalar) -> bool {
+        s.is_high().into()
+    }
+}
+
+impl ThresholdCurve for p256::AffinePoint {
+    const NAME: &'static str = "p256";
+
+    //high * 2^256 + low, p256 only reduce 32 bytes at a time
+    fn scalar_from_wide(bytes: &[u8; 64]) -> p256::Scalar {
+        let high = <p256::Scalar as Reduce<U256>>::reduce_bytes(GenericArray::from_slice(&bytes[..32]));
+        let low = <p256::Scalar as Reduce<U256>>::reduce_bytes(GenericArray::from_slice(&bytes[32..]));
+        let mut shift = p256::Scalar::from_u128(1 << 64);
+        for _ in 0..2 {
+            shift = shift.square();
+        }
+        high * shift + low
+    }
+}
+
+impl EcdsaCurve for p256::AffinePoint {
+    fn ecdsa_r(&self) -> Result<(p256::Scalar, u8), ThresholdError> {
+        if bool::from(self.is_identity()) {
+            return Err(ThresholdError::InvalidPoint);
+        }
+        Ok(x_scalar::<p256::NistP256, p256::Scalar>(&self.x(), self.y_is_odd().into()))
+    }
+
+    fn digest_scalar(digest: &[u8; 32]) -> p256::Scalar {
+        <p256::Scalar as Reduce<U256>>::reduce_bytes(digest.into())
+    }
+}
+
+//(r, s) as the standard verifiers take it, with the recovery id for secp256k1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcdsaSignature<P: EcdsaCurve> {
+    pub r : P::Scalar,
+    pub s : P::Scalar,
+    pub recovery_id : u8,
+}
+
+impl<P: EcdsaCurve> EcdsaSignature<P> {
+    //commitment R and s from the threshold signing, s * R == m * G + r * pub_key
+    pub fn new(commitment: P, s: P::Scalar) -> Result<EcdsaSignature<P>, ThresholdError> {
+        let (r, mut recovery_id) = commitment.ecdsa_r()?;
+        let mut s = s;
+        if P::LOW_S && P::is_high(&s) {
+            s = -s;
+            recovery_id ^= 1;
+        }
+        Ok(EcdsaSignature { r, s, recovery_id })
+    }
+
+    //r | s, each in the curve's own scalar encoding
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut out = self.r.to_repr().as_ref().to_vec();
+        out.extend_from_slice(self.s.to_repr().as_ref());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use k256::ecdsa::{signature::hazmat::PrehashVerifier, RecoveryId};
+    use pasta_curves::group::Curve;
+    use sha2::{Digest, Sha256};
+    use myp::Secret;
+    use crate::group_eddsa::{combine_partial, hedged_pre_compute, message_challenge};
+    use crate::key_generate::{lagrange_coefficient, test_scalar_keys, CalculatePubKey};
+    use crate::party::{combine_round4, test_party_secret, Party, PartySecret, Round3Msg};
+    use crate::ring_pedersen::test_ring_pedersen_params;
+
+    //three parties in one process, the pub key and the signature on the digest
+    fn threshold_sign<P: EcdsaCurve>(digest: &[u8; 32]) -> (P, P, EcdsaSignature<P>) {
+        let mut rng = rand::thread_rng();
+        let secret: Vec<PartySecret<P>> = (0..3).map(|i| test_party_secret(&mut rng, i)).collect();
+        let mta_pub_n: Vec<BigUint> = secret.iter().map(|i| i.pri_p.expose() * i.pri_q.expose()).collect();
+        let w_sum: P::Scalar = secret.iter().map(|i| *i.w.expose()).sum();
+        let mut party = Vec::new();
+        for (i, secret) in secret.into_iter().enumerate() {
+            party.push(Party::<P>::new(i, secret, mta_pub_n.clone(), test_ring_pedersen_params(3)).unwrap());
+        }
+        let round1: Vec<_> = party.iter_mut().map(|i| i.round1(&mut rng).unwrap()).collect();
+        let mut round2 = Vec::new();
+        for i in party.iter_mut() {
+            round2.extend(i.round2(&mut rng, &round1).unwrap());
+        }
+        let round3: Vec<Round3Msg<P>> = party.iter_mut().map(|i| i.round3(&round2).unwrap()).collect();
+        let presignature: Vec<_> = party.iter_mut().map(|i| i.presign(&round3).unwrap()).collect();
+        let commitment = presignature[0].commitment();
+        let round4: Vec<_> = presignature.into_iter().map(|i| i.sign_digest(digest).unwrap().0).collect();
+        let s = combine_round4(0, 3, &round4).unwrap();
+        let pub_key = (P::generator() * w_sum).to_affine();
+        (pub_key, commitment, EcdsaSignature::new(commitment, s).unwrap())
+    }
+
+    #[test]
+    fn secp256k1_test(){
+        let digest: [u8; 32] = Sha256::digest(b"threshold ecdsa on secp256k1").into();
+        let (pub_key, _, signature) = threshold_sign::<k256::AffinePoint>(&digest);
+        assert!(!bool::from(signature.s.is_high()));
+
+        //the standard verifier take it, and the recovery id give the pub key back
+        let verifying_key = k256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+        let ecdsa = k256::ecdsa::Signature::from_slice(&signature.to_bytes()).unwrap();
+        assert!(verifying_key.verify_prehash(&digest, &ecdsa).is_ok());
+        let recovery_id = RecoveryId::from_byte(signature.recovery_id).unwrap();
+        assert_eq!(k256::ecdsa::VerifyingKey::recover_from_prehash(&digest, &ecdsa, recovery_id).unwrap(), verifying_key);
+
+        let other: [u8; 32] = Sha256::digest(b"another message").into();
+        assert!(verifying_key.verify_prehash(&other, &ecdsa).is_err());
+    }
+
+    #[test]
+    fn p256_test(){
+        let digest: [u8; 32] = Sha256::digest(b"threshold ecdsa on p-256").into();
+        let (pub_key, _, signature) = threshold_sign::<p256::AffinePoint>(&digest);
+        let verifying_key = p256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+        let ecdsa = p256::ecdsa::Signature::from_slice(&signature.to_bytes()).unwrap();
+        assert!(verifying_key.verify_prehash(&digest, &ecdsa).is_ok());
+    }
+
+    #[test]
+    fn curve_key_generate_test(){
+        //the scalar key ceremony on secp256k1, any 2 of the 3 shares give the pub key
+        let key = test_scalar_keys::<k256::AffinePoint>(&mut rand::thread_rng(), 3, 2);
+        let pub_key = CalculatePubKey {
+            degree : 2,
+            coefficient : vec![2, 3],
+            pub_key : vec![key[0].2[1], key[0].2[2]],
+        }.calculate().unwrap();
+        assert_eq!(pub_key, key[0].1);
+        assert_eq!((k256::AffinePoint::generator() * key[1].0.expose()).to_affine(), key[0].2[1]);
+    }
+
+    #[test]
+    fn vesta_test(){
+        //the same ecdsa rounds on vesta, checked with s * R == m * G + r * pub_key
+        let digest: [u8; 32] = Sha256::digest(b"threshold ecdsa on vesta").into();
+        let (pub_key, commitment, signature) = threshold_sign::<vesta::Affine>(&digest);
+        let m = vesta::Affine::digest_scalar(&digest);
+        assert_eq!((commitment * signature.s).to_affine(), (vesta::Affine::generator() * m + pub_key * signature.r).to_affine());
+
+        //and the schnorr of group_eddsa, 2 of 3 from the key ceremony with all 3 signing
+        let message = 42;
+        let mut rng = rand::thread_rng();
+        let key = test_scalar_keys::<vesta::Affine>(&mut rng, 3, 2);
+        let (pub_key, verification_shares) = (key[0].1, key[0].2.clone());
+        let partial: Vec<_> = key.iter().enumerate().map(|(i, key)| {
+            let lambda: vesta::Scalar = lagrange_coefficient((i + 1) as u128, &[1, 2, 3]).unwrap();
+            let pri = Secret::new(lambda * key.0.expose());
+            hedged_pre_compute::<vesta::Affine>(&mut rng, &pri, message)
+        }).collect();
+
+        let challenge = message_challenge::<vesta::Scalar>(message);
+        let (commitment, s) = combine_partial(
+            &[1, 2, 3],
+            &partial.iter().map(|i| i.0).collect::<Vec<_>>(),
+            &partial.iter().map(|i| i.1).collect::<Vec<_>>(),
+            &verification_shares,
+            challenge,
+        ).unwrap();
+        assert_eq!((vesta::Affine::generator() * s).to_affine(), (pub_key * challenge + commitment).to_affine());
+    }
+
+    #[test]
+    fn wide_test(){
+        //p256 reduce the two halves on their own, 2^256 mod n check the shift
+        let mut bytes = [0u8; 64];
+        bytes[31] = 1;
+        let expected = hex_literal::hex!("00000000ffffffff00000000000000004319055258e8617b0c46353d039cdaaf");
+        assert_eq!(p256::AffinePoint::scalar_from_wide(&bytes), Option::from(p256::Scalar::from_repr(expected.into())).unwrap());
+        let mut low = [0u8; 64];
+        low[63] = 7;
+        assert_eq!(p256::AffinePoint::scalar_from_wide(&low), p256::Scalar::from_u128(7));
+        assert_eq!(k256::AffinePoint::scalar_from_wide(&low), k256::Scalar::from_u128(7));
+        //pasta take the bytes little endian
+        let mut low = [0u8; 64];
+        low[0] = 7;
+        assert_eq!(vesta::Affine::scalar_from_wide(&low), vesta::Scalar::from_u128(7));
+    }
+}
@@ -0,0 +1,1306 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use rand::{CryptoRng, Rng, RngCore};
+use num_bigint::BigUint;
+use pasta_curves::group::{ff::PrimeField, GroupEncoding};
+use pasta_curves::pallas;
+use myp::{Secret, ThresholdError};
+use crate::channel::SecureTransport;
+use crate::identity::{IdentityKey, IdentitySignature};
+use crate::key_file::{KdfParams, KeyFile};
+use crate::curve::{EcdsaCurve, EcdsaSignature, ThresholdCurve};
+use crate::message::{route, Encode, Message, SessionId};
+use crate::nonce_store::NonceStore;
+use crate::party::Party;
+use crate::ring_pedersen::{RingPedersenParams, RingPedersenProof};
+use crate::tool::{from_hex, to_hex};
+use crate::transport::{run_bip340_signing, run_keygen, run_reshare, run_signing, Session, Transport, ROUND_TIMEOUT};
+
+//one party of a ceremony in its own process, every pair of parties share one tcp or unix socket
+//connection, the lower index listen and the higher one dial, and before any message both ends
+//sign the session id and a fresh nonce from each side with their identity key
+const HANDSHAKE_VERSION: u8 = 1;
+const HANDSHAKE_LABEL: &str = "party-daemon-handshake";
+//a frame is a u32 length and a Message, nothing in the protocol comes close to this
+const MAX_FRAME: usize = 1 << 20;
+const DIAL_INTERVAL: Duration = Duration::from_millis(50);
+//an inbound connection get this long to authenticate, the listener run one handshake at a time
+//so a silent client only hold up the real peers this long and not until the deadline
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+//new nonce pairs put in the store when it runs out
+const NONCE_BATCH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+pub struct Peer {
+    pub address : Address,
+    pub identity : pallas::Affine,
+}
+
+//the curve of a ceremony and its key file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignCurve {
+    Pallas,
+    Secp256k1,
+    P256,
+}
+
+impl SignCurve {
+    pub fn parse(text: &str) -> Result<SignCurve, ThresholdError> {
+        match text {
+            "pallas" => Ok(SignCurve::Pallas),
+            "secp256k1" => Ok(SignCurve::Secp256k1),
+            "p256" => Ok(SignCurve::P256),
+            _ => Err(ThresholdError::InvalidEncoding("config curve")),
+        }
+    }
+}
+
+//the key file of the sign ceremony, on its curve
+pub enum SignKey {
+    Pallas(KeyFile),
+    Secp256k1(KeyFile<k256::AffinePoint>),
+    P256(KeyFile<p256::AffinePoint>),
+}
+
+//the old key of a reshare, the share at index + 1, the key and every party's old verification share
+pub struct OldKey<P: ThresholdCurve> {
+    pub key_share : P::Scalar,
+    pub pub_key : P,
+    pub verification_share : Vec<P>,
+}
+
+//the old key on the curve of the reshare
+pub enum ReshareKey {
+    Pallas(OldKey<pallas::Affine>),
+    Secp256k1(OldKey<k256::AffinePoint>),
+    P256(OldKey<p256::AffinePoint>),
+}
+
+pub enum Ceremony {
+    //a random key dealt by every party on the curve, threshold of the parties can sign
+    Keygen {
+        curve : SignCurve,
+        threshold : usize,
+    },
+    //w is the signing share of the key file, k and r are always hedged from it in run.
+    //the digest is the 32 byte hash of the message, as the standard verifiers take it.
+    //ring_pedersen is every party's params, checked with its proof, for the mta range proofs
+    Sign {
+        key : SignKey,
+        mta_pub_n : Vec<BigUint>,
+        ring_pedersen : Vec<RingPedersenParams>,
+        digest : [u8; 32],
+    },
+    //bip340 schnorr with the secp256k1 key file, the message is the 32 byte sighash.
+    //the nonce pair is from the nonce store if there is one, else hedged for this message
+    SignBip340 {
+        key : KeyFile<k256::AffinePoint>,
+        message : [u8; 32],
+        nonce_store : Option<PathBuf>,
+    },
+    //the old key to the new threshold
+    Reshare {
+        key : ReshareKey,
+        threshold : usize,
+    },
+}
+
+//the key_share, pub_key and verification_share lines, read once the curve is known
+struct KeyLines<'a> {
+    key_share : Option<&'a str>,
+    pub_key : Option<&'a str>,
+    verification_share : Vec<Option<&'a str>>,
+}
+
+impl KeyLines<'_> {
+    //the old key on P, from the key file if there is one, then the paillier key is the file's too
+    fn old_key<P: ThresholdCurve>(&self, key_file: Option<&Path>, passphrase: &[u8], parties: usize, index: &mut Option<usize>, paillier: &mut Option<(BigUint, BigUint)>) -> Result<OldKey<P>, ThresholdError> {
+        if let Some(path) = key_file {
+            let key: KeyFile<P> = load_key(path, passphrase, parties, index)?;
+            *paillier = Some((key.pri_p.expose().clone(), key.pri_q.expose().clone()));
+            return Ok(OldKey { key_share : *key.share.expose(), pub_key : key.pub_key, verification_share : key.verification_share.clone() });
+        }
+        let verification_share = self.verification_share.iter().copied().collect::<Option<Vec<&str>>>()
+            .filter(|i| i.len() == parties)
+            .ok_or(ThresholdError::InvalidEncoding("config verification_share missing"))?;
+        Ok(OldKey {
+            key_share : parse_scalar_on(self.key_share.ok_or(ThresholdError::InvalidEncoding("config key_share missing"))?)?,
+            pub_key : parse_point_on(self.pub_key.ok_or(ThresholdError::InvalidEncoding("config pub_key missing"))?)?,
+            verification_share : verification_share.into_iter().map(parse_point_on).collect::<Result<Vec<P>, ThresholdError>>()?,
+        })
+    }
+}
+
+//peer has every party including this one, peer[index].address is where this party listen
+pub struct DaemonConfig {
+    pub session_id : SessionId,
+    pub index : usize,
+    pub identity : IdentityKey,
+    pub peer : Vec<Peer>,
+    pub ceremony : Ceremony,
+    pub timeout : Duration,
+    //where keygen and reshare save the key, sign and reshare read it from there too
+    pub key_file : Option<PathBuf>,
+    pub passphrase : Vec<u8>,
+    //pri_p and pri_q that go into the key file, or that sign use
+    pub paillier : Option<(BigUint, BigUint)>,
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Listener {
+    fn bind(address: &Address) -> Result<Listener, ThresholdError> {
+        let listener = match address {
+            Address::Tcp(address) => Listener::Tcp(TcpListener::bind(address)?),
+            Address::Unix(path) => {
+                //a socket left by an earlier run, anything else at the path is not ours to remove
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Listener::Unix(UnixListener::bind(path)?)
+            }
+        };
+        match &listener {
+            Listener::Tcp(i) => i.set_nonblocking(true)?,
+            Listener::Unix(i) => i.set_nonblocking(true)?,
+        }
+        Ok(listener)
+    }
+
+    //None if nobody is waiting
+    fn accept(&self) -> Result<Option<Stream>, ThresholdError> {
+        let result = match self {
+            Listener::Tcp(i) => i.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Listener::Unix(i) => i.accept().map(|(stream, _)| Stream::Unix(stream)),
+        };
+        match result {
+            Ok(stream) => {
+                stream.set_nonblocking(false)?;
+                Ok(Some(stream))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Stream {
+    fn connect(address: &Address) -> std::io::Result<Stream> {
+        match address {
+            Address::Tcp(address) => TcpStream::connect(address).map(Stream::Tcp),
+            Address::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+        }
+    }
+
+    fn try_clone(&self) -> std::io::Result<Stream> {
+        match self {
+            Stream::Tcp(i) => i.try_clone().map(Stream::Tcp),
+            Stream::Unix(i) => i.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(i) => i.set_nonblocking(nonblocking),
+            Stream::Unix(i) => i.set_nonblocking(nonblocking),
+        }
+    }
+
+    //the peer read everything already written and then get eof
+    fn shutdown_write(&self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(i) => i.shutdown(std::net::Shutdown::Write),
+            Stream::Unix(i) => i.shutdown(std::net::Shutdown::Write),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(i) => i.set_read_timeout(timeout),
+            Stream::Unix(i) => i.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(i) => i.read(buf),
+            Stream::Unix(i) => i.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(i) => i.write(buf),
+            Stream::Unix(i) => i.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(i) => i.flush(),
+            Stream::Unix(i) => i.flush(),
+        }
+    }
+}
+
+fn write_frame(stream: &mut Stream, bytes: &[u8]) -> Result<(), ThresholdError> {
+    let mut frame = (bytes.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(bytes);
+    stream.write_all(&frame)?;
+    Ok(())
+}
+
+fn read_frame(stream: &mut Stream) -> Result<Vec<u8>, ThresholdError> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(ThresholdError::InvalidEncoding("frame is too long"));
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+//what the signer of a handshake signature commit to
+fn handshake_data(session_id: &SessionId, signer: usize, verifier: usize, signer_nonce: &[u8], verifier_nonce: &[u8]) -> Vec<u8> {
+    let mut data = session_id.to_vec();
+    data.extend_from_slice(&(signer as u32).to_be_bytes());
+    data.extend_from_slice(&(verifier as u32).to_be_bytes());
+    data.extend_from_slice(signer_nonce);
+    data.extend_from_slice(verifier_nonce);
+    data
+}
+
+//version | session id | index | nonce, then a signature of handshake_data from each side
+//out put the index of the authenticated peer, it must be expect if the peer was dialed
+//a peer that is silent past the deadline fail it, so it can't hold up the others
+fn handshake(stream: &mut Stream, rng: &mut (impl CryptoRng + RngCore), config: &DaemonConfig, expect: Option<usize>, deadline: Instant) -> Result<usize, ThresholdError> {
+    let timeout = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+    stream.set_read_timeout(Some(timeout))?;
+    let nonce: [u8; 32] = rng.gen();
+    let mut hello = vec![HANDSHAKE_VERSION];
+    hello.extend_from_slice(&config.session_id);
+    hello.extend_from_slice(&(config.index as u32).to_be_bytes());
+    hello.extend_from_slice(&nonce);
+    write_frame(stream, &hello)?;
+
+    let reply = read_frame(stream)?;
+    if reply.len() != hello.len() || reply[0] != HANDSHAKE_VERSION {
+        return Err(ThresholdError::InvalidEncoding("handshake"));
+    }
+    if reply[1..33] != config.session_id {
+        return Err(ThresholdError::InvalidEncoding("handshake for another session"));
+    }
+    let mut index = [0u8; 4];
+    index.copy_from_slice(&reply[33..37]);
+    let peer = u32::from_be_bytes(index) as usize;
+    let peer_nonce = &reply[37..];
+    let valid = match expect {
+        Some(expect) => peer == expect,
+        None => peer > config.index && peer < config.peer.len(),
+    };
+    if !valid {
+        return Err(ThresholdError::OutOfRange("peer index"));
+    }
+
+    let signature = config.identity.sign(rng, HANDSHAKE_LABEL, &handshake_data(&config.session_id, config.index, peer, &nonce, peer_nonce));
+    let mut bytes = Vec::new();
+    signature.encode(&mut bytes);
+    write_frame(stream, &bytes)?;
+
+    let bytes = read_frame(stream)?;
+    let mut input = bytes.as_slice();
+    let signature = IdentitySignature::decode(&mut input)?;
+    let data = handshake_data(&config.session_id, peer, config.index, peer_nonce, &nonce);
+    if !input.is_empty() || !signature.verify(config.peer[peer].identity, HANDSHAKE_LABEL, &data) {
+        return Err(ThresholdError::VerificationFailed { party: peer });
+    }
+    stream.set_read_timeout(None)?;
+    Ok(peer)
+}
+
+//one connection to every other party, each has a reader thread that only pass on messages
+//whose sender is the peer authenticated on that connection
+pub struct SocketTransport {
+    index : usize,
+    writer : Vec<Option<Stream>>,
+    incoming : Receiver<Vec<u8>>,
+}
+
+//only the header is read here, the payload is decoded in receive once the curve is known
+fn spawn_reader(mut stream: Stream, peer: usize, index: usize, incoming: Sender<Vec<u8>>) {
+    std::thread::spawn(move || {
+        //stop when the peer close the connection or this party is gone
+        while let Ok(bytes) = read_frame(&mut stream) {
+            let (from, to) = match route(&bytes) {
+                Ok(route) => route,
+                Err(_) => continue,
+            };
+            if from != peer || to.is_some_and(|to| to != index) {
+                continue;
+            }
+            if incoming.send(bytes).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+impl SocketTransport {
+    pub fn connect(rng: &mut (impl CryptoRng + RngCore), config: &DaemonConfig) -> Result<SocketTransport, ThresholdError> {
+        let parties = config.peer.len();
+        if config.index >= parties || parties < 2 {
+            return Err(ThresholdError::OutOfRange("index"));
+        }
+        let deadline = Instant::now() + config.timeout;
+        let listener = Listener::bind(&config.peer[config.index].address)?;
+        let mut stream: Vec<Option<Stream>> = (0..parties).map(|_| None).collect();
+
+        for (peer, i) in config.peer.iter().enumerate().take(config.index) {
+            let mut connection = loop {
+                match Stream::connect(&i.address) {
+                    Ok(connection) => break connection,
+                    //the peer isn't listening yet
+                    Err(_) if Instant::now() < deadline => std::thread::sleep(DIAL_INTERVAL),
+                    Err(_) => return Err(ThresholdError::Disconnected { party: peer }),
+                }
+            };
+            handshake(&mut connection, rng, config, Some(peer), deadline)?;
+            stream[peer] = Some(connection);
+        }
+
+        //anyone can connect to the listener, a connection that doesn't authenticate as a missing
+        //peer is dropped and the party keep waiting for the real one until the deadline
+        while stream.iter().skip(config.index + 1).any(|i| i.is_none()) {
+            let accepted = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("party {}: accept failed: {}", config.index, e);
+                    None
+                }
+            };
+            match accepted {
+                Some(mut connection) => match handshake(&mut connection, rng, config, None, deadline.min(Instant::now() + HANDSHAKE_TIMEOUT)) {
+                    //the first authenticated connection of a peer is kept
+                    Ok(peer) if stream[peer].is_some() => eprintln!("party {}: dropped a second connection of party {}", config.index, peer),
+                    Ok(peer) => stream[peer] = Some(connection),
+                    Err(e) => eprintln!("party {}: dropped a connection: {}", config.index, e),
+                },
+                None if Instant::now() < deadline => std::thread::sleep(DIAL_INTERVAL),
+                None => {
+                    let missing = stream.iter().skip(config.index + 1).position(|i| i.is_none()).unwrap_or(0);
+                    return Err(ThresholdError::Disconnected { party: config.index + 1 + missing });
+                }
+            }
+        }
+
+        let (sender, incoming) = channel();
+        let mut writer = Vec::new();
+        for (peer, i) in stream.into_iter().enumerate() {
+            match i {
+                Some(connection) => {
+                    spawn_reader(connection.try_clone()?, peer, config.index, sender.clone());
+                    writer.push(Some(connection));
+                }
+                None => writer.push(None),
+            }
+        }
+        Ok(SocketTransport {
+            index : config.index,
+            writer,
+            incoming,
+        })
+    }
+
+    fn write(&mut self, to: usize, bytes: &[u8]) -> Result<(), ThresholdError> {
+        match self.writer.get_mut(to) {
+            Some(Some(stream)) => write_frame(stream, bytes).map_err(|_| ThresholdError::Disconnected { party: to }),
+            _ => Err(ThresholdError::OutOfRange("to")),
+        }
+    }
+}
+
+//the reader threads hold a clone of each socket, so closing the writer alone wouldn't tell the peer
+impl Drop for SocketTransport {
+    fn drop(&mut self) {
+        for stream in self.writer.iter().flatten() {
+            let _ = stream.shutdown_write();
+        }
+    }
+}
+
+impl Transport for SocketTransport {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn parties(&self) -> usize {
+        self.writer.len()
+    }
+
+    fn send<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        if message.from() != self.index {
+            return Err(ThresholdError::OutOfRange("from"));
+        }
+        match message.to() {
+            Some(to) => self.write(to, &message.to_bytes()),
+            None => Err(ThresholdError::OutOfRange("to")),
+        }
+    }
+
+    fn broadcast<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        if message.from() != self.index || message.to().is_some() {
+            return Err(ThresholdError::OutOfRange("to"));
+        }
+        let bytes = message.to_bytes();
+        for to in 0..self.parties() {
+            if to != self.index {
+                self.write(to, &bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn receive<P: EcdsaCurve>(&mut self, timeout: Duration) -> Result<Option<Message<P>>, ThresholdError> {
+        match self.incoming.recv_timeout(timeout) {
+            Ok(bytes) => Message::from_bytes(&bytes).map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            //every peer closed its connection
+            Err(RecvTimeoutError::Disconnected) => Err(ThresholdError::Disconnected { party: self.index }),
+        }
+    }
+
+    //tcp and unix stream sockets don't lose or reorder data
+    fn reliable(&self) -> bool {
+        true
+    }
+}
+
+pub fn parse_u128(text: &str) -> Result<u128, ThresholdError> {
+    text.parse().map_err(|_| ThresholdError::InvalidEncoding("config number"))
+}
+
+//paillier primes and moduli are too long for u128, they are written in decimal
+pub fn parse_big(text: &str) -> Result<BigUint, ThresholdError> {
+    BigUint::parse_bytes(text.as_bytes(), 10).ok_or(ThresholdError::InvalidEncoding("config number"))
+}
+
+//a value in its wire encoding, as hex
+pub fn encode_hex<T: Encode>(value: &T) -> String {
+    let mut bytes = Vec::new();
+    value.encode(&mut bytes);
+    to_hex(&bytes)
+}
+
+pub fn decode_hex<T: Encode>(text: &str) -> Result<T, ThresholdError> {
+    let bytes = from_hex(text).ok_or(ThresholdError::InvalidEncoding("hex"))?;
+    let mut input = bytes.as_slice();
+    let value = T::decode(&mut input)?;
+    if !input.is_empty() {
+        return Err(ThresholdError::InvalidEncoding("trailing bytes"));
+    }
+    Ok(value)
+}
+
+pub fn parse_hex<const N: usize>(text: &str) -> Result<[u8; N], ThresholdError> {
+    from_hex(text)
+        .and_then(|i| i.try_into().ok())
+        .ok_or(ThresholdError::InvalidEncoding("config hex"))
+}
+
+//the hex of a repr, as long as the repr of the curve is
+fn parse_repr<R: Default + AsMut<[u8]>>(text: &str) -> Result<R, ThresholdError> {
+    let bytes = from_hex(text).ok_or(ThresholdError::InvalidEncoding("config hex"))?;
+    let mut repr = R::default();
+    if repr.as_mut().len() != bytes.len() {
+        return Err(ThresholdError::InvalidEncoding("config hex"));
+    }
+    repr.as_mut().copy_from_slice(&bytes);
+    Ok(repr)
+}
+
+pub fn parse_point_on<P: GroupEncoding>(text: &str) -> Result<P, ThresholdError> {
+    Option::from(P::from_bytes(&parse_repr(text)?))
+        .ok_or(ThresholdError::InvalidEncoding("config point"))
+}
+
+pub fn parse_scalar_on<F: PrimeField>(text: &str) -> Result<F, ThresholdError> {
+    Option::from(F::from_repr(parse_repr(text)?))
+        .ok_or(ThresholdError::InvalidEncoding("config scalar"))
+}
+
+pub fn parse_point(text: &str) -> Result<pallas::Affine, ThresholdError> {
+    parse_point_on(text)
+}
+
+pub fn parse_scalar(text: &str) -> Result<pallas::Scalar, ThresholdError> {
+    parse_scalar_on(text)
+}
+
+//tcp:host:port or unix:/path
+pub fn parse_address(text: &str) -> Result<Address, ThresholdError> {
+    if let Some(address) = text.strip_prefix("tcp:") {
+        return Ok(Address::Tcp(address.to_string()));
+    }
+    if let Some(path) = text.strip_prefix("unix:") {
+        return Ok(Address::Unix(PathBuf::from(path)));
+    }
+    Err(ThresholdError::InvalidEncoding("config address"))
+}
+
+impl DaemonConfig {
+    //one setting a line, # start a comment
+    //  session <32 bytes hex>
+    //  index <this party>
+    //  identity <secret scalar hex>
+    //  peer <index> <tcp:host:port | unix:/path> <identity point hex>   one for every party
+    //  timeout <seconds>                                                 optional
+    //  keygen <threshold>  every party deal a random polynomial, the key is the sum of the secrets
+    //  sign <digest>  with  mta_pub_n <n_0> <n_1> ...  and a key_file
+    //                   the digest is the 32 byte hash of the message in hex, or the sighash with
+    //                   bip340, mta_pub_n is decimal.
+    //                   ring_pedersen <index> <params hex> <proof hex>  one for every party, as
+    //                   paillier-keygen print them, not for bip340
+    //                   w is the signing share of the key file with every party signing, k and r
+    //                   are always hedged from it, the paillier key, the digest and the rng
+    //  curve <pallas | secp256k1 | p256>  the curve of the ceremony and its key file, optional,
+    //                   pallas if not given
+    //  scheme <ecdsa | bip340>  the signature of sign, optional, ecdsa if not given. bip340 is
+    //                   on secp256k1 and takes no mta_pub_n
+    //  reshare <new threshold>  with  key_share <scalar hex>  pub_key <point hex>
+    //                   verification_share <index> <point hex>  one for every party
+    //  threshold <t>  the old threshold, only checked against the parties
+    //  key_file <path>  an encrypted key file, keygen write it with the paillier key from
+    //                   paillier_file <path>, sign and reshare read it
+    //  nonce_store <path>  preprocessed nonce pairs of a bip340 sign, sealed with the passphrase
+    //                   of the key file. it is made on the first sign and refilled when it runs out
+    //what keygen or reshare print without a key file is a valid part of a config
+    #[cfg(test)]
+    pub fn parse(text: &str) -> Result<DaemonConfig, ThresholdError> {
+        DaemonConfig::parse_with_passphrase(text, &[])
+    }
+
+    //the passphrase of the key file, if the config has one
+    pub fn parse_with_passphrase(text: &str, passphrase: &[u8]) -> Result<DaemonConfig, ThresholdError> {
+        let mut session_id = None;
+        let mut index = None;
+        let mut identity = None;
+        let mut peer: Vec<Option<Peer>> = Vec::new();
+        let mut timeout = ROUND_TIMEOUT;
+        let mut keygen = None;
+        let mut sign = None;
+        let mut curve = SignCurve::Pallas;
+        let mut bip340 = false;
+        let mut mta_pub_n = None;
+        let mut reshare = None;
+        let mut key_lines = KeyLines { key_share : None, pub_key : None, verification_share : Vec::new() };
+        let mut threshold = None;
+        let mut key_file = None;
+        let mut paillier = None;
+        let mut nonce_store = None;
+        let mut ring_pedersen: Vec<Option<(RingPedersenParams, RingPedersenProof)>> = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let word: Vec<&str> = line.split_whitespace().collect();
+            match word.as_slice() {
+                [] => {}
+                ["session", id] => session_id = Some(parse_hex(id)?),
+                ["index", i] => index = Some(parse_u128(i)? as usize),
+                ["identity", secret] => {
+                    identity = Some(IdentityKey::from_secret(parse_scalar(secret)?)
+                        .ok_or(ThresholdError::InvalidEncoding("config identity"))?);
+                }
+                ["peer", i, address, public] => {
+                    let i = parse_u128(i)? as usize;
+                    if i >= 1 << 16 {
+                        return Err(ThresholdError::OutOfRange("peer index"));
+                    }
+                    if peer.len() <= i {
+                        peer.resize_with(i + 1, || None);
+                    }
+                    peer[i] = Some(Peer {
+                        address : parse_address(address)?,
+                        identity : parse_point(public)?,
+                    });
+                }
+                ["timeout", seconds] => timeout = Duration::from_secs(parse_u128(seconds)? as u64),
+                ["keygen", threshold] => keygen = Some(parse_u128(threshold)? as usize),
+                //a key share typed by hand is a key an operator can guess or reuse
+                ["keygen", ..] => return Err(ThresholdError::InvalidEncoding("config keygen takes only the threshold, the key is dealt at random")),
+                ["sign", digest] => sign = Some(parse_hex(digest)?),
+                ["curve", name] => curve = SignCurve::parse(name)?,
+                ["scheme", "ecdsa"] => bip340 = false,
+                ["scheme", "bip340"] => bip340 = true,
+                //a k given by hand is one copy away from being used twice, and w is the key file's
+                ["sign", ..] => return Err(ThresholdError::InvalidEncoding("config sign takes only the message, w is from the key file and k and r are hedged")),
+                ["mta_pub_n", n @ ..] => mta_pub_n = Some(n.iter().map(|i| parse_big(i)).collect::<Result<Vec<BigUint>, ThresholdError>>()?),
+                ["reshare", t] => reshare = Some(parse_u128(t)? as usize),
+                ["key_share", share] => key_lines.key_share = Some(*share),
+                ["pub_key", point] => key_lines.pub_key = Some(*point),
+                ["threshold", t] => threshold = Some(parse_u128(t)? as usize),
+                ["key_file", path] => key_file = Some(PathBuf::from(path)),
+                ["paillier_file", path] => paillier = Some(read_paillier(path)?),
+                ["nonce_store", path] => nonce_store = Some(PathBuf::from(path)),
+                ["ring_pedersen", i, params, proof] => {
+                    let i = parse_u128(i)? as usize;
+                    if i >= 1 << 16 {
+                        return Err(ThresholdError::OutOfRange("ring_pedersen index"));
+                    }
+                    if ring_pedersen.len() <= i {
+                        ring_pedersen.resize(i + 1, None);
+                    }
+                    ring_pedersen[i] = Some((decode_hex(params)?, decode_hex(proof)?));
+                }
+                ["verification_share", i, point] => {
+                    let i = parse_u128(i)? as usize;
+                    if i >= 1 << 16 {
+                        return Err(ThresholdError::OutOfRange("verification_share index"));
+                    }
+                    if key_lines.verification_share.len() <= i {
+                        key_lines.verification_share.resize(i + 1, None);
+                    }
+                    key_lines.verification_share[i] = Some(*point);
+                }
+                _ => return Err(ThresholdError::InvalidEncoding("config line")),
+            }
+        }
+
+        let peer: Vec<Peer> = peer.into_iter().collect::<Option<Vec<Peer>>>()
+            .ok_or(ThresholdError::InvalidEncoding("config peer missing"))?;
+        if threshold.is_some_and(|t| t == 0 || t > peer.len()) {
+            return Err(ThresholdError::OutOfRange("threshold"));
+        }
+        if key_file.is_some() && passphrase.is_empty() {
+            return Err(ThresholdError::InvalidEncoding("config key_file needs a passphrase"));
+        }
+        if sign.is_some() && key_file.is_none() {
+            return Err(ThresholdError::InvalidEncoding("config sign needs a key_file"));
+        }
+        if key_file.is_some() && keygen.is_some() && paillier.is_none() {
+            return Err(ThresholdError::InvalidEncoding("config key_file needs paillier_file"));
+        }
+        if bip340 && (sign.is_none() || curve != SignCurve::Secp256k1) {
+            return Err(ThresholdError::InvalidEncoding("config scheme bip340 is a sign on curve secp256k1"));
+        }
+        if nonce_store.is_some() && !bip340 {
+            return Err(ThresholdError::InvalidEncoding("config nonce_store is for a bip340 sign"));
+        }
+        let ceremony = match (keygen, sign, mta_pub_n, reshare) {
+            (Some(threshold), None, None, None) => Ceremony::Keygen { curve, threshold },
+            (None, Some(message), None, None) if bip340 => {
+                let path = key_file.as_deref().ok_or(ThresholdError::InvalidEncoding("config sign needs a key_file"))?;
+                Ceremony::SignBip340 { key : load_key(path, passphrase, peer.len(), &mut index)?, message, nonce_store }
+            }
+            (None, Some(digest), Some(mta_pub_n), None) if !bip340 => {
+                let path = key_file.as_deref().ok_or(ThresholdError::InvalidEncoding("config sign needs a key_file"))?;
+                let key = match curve {
+                    SignCurve::Pallas => SignKey::Pallas(load_key(path, passphrase, peer.len(), &mut index)?),
+                    SignCurve::Secp256k1 => SignKey::Secp256k1(load_key(path, passphrase, peer.len(), &mut index)?),
+                    SignCurve::P256 => SignKey::P256(load_key(path, passphrase, peer.len(), &mut index)?),
+                };
+                //params without a valid proof could let their owner learn our k from the range proof
+                let ring_pedersen = ring_pedersen.into_iter().collect::<Option<Vec<(RingPedersenParams, RingPedersenProof)>>>()
+                    .filter(|i| i.len() == peer.len())
+                    .ok_or(ThresholdError::InvalidEncoding("config ring_pedersen missing"))?;
+                let mut params = Vec::new();
+                for (params_i, proof) in ring_pedersen {
+                    if !params_i.verify(&proof) {
+                        return Err(ThresholdError::InvalidModulus(params_i.n_tilde));
+                    }
+                    params.push(params_i);
+                }
+                Ceremony::Sign { key, mta_pub_n, ring_pedersen : params, digest }
+            }
+            //reshare take the key from the file if there is one, else from the config lines
+            (None, None, None, Some(threshold)) => {
+                let (path, parties) = (key_file.as_deref(), peer.len());
+                let key = match curve {
+                    SignCurve::Pallas => ReshareKey::Pallas(key_lines.old_key(path, passphrase, parties, &mut index, &mut paillier)?),
+                    SignCurve::Secp256k1 => ReshareKey::Secp256k1(key_lines.old_key(path, passphrase, parties, &mut index, &mut paillier)?),
+                    SignCurve::P256 => ReshareKey::P256(key_lines.old_key(path, passphrase, parties, &mut index, &mut paillier)?),
+                };
+                Ceremony::Reshare { key, threshold }
+            }
+            _ => return Err(ThresholdError::InvalidEncoding("config needs keygen, sign with mta_pub_n, bip340 sign or reshare")),
+        };
+        let config = DaemonConfig {
+            session_id : session_id.ok_or(ThresholdError::InvalidEncoding("config session missing"))?,
+            index : index.ok_or(ThresholdError::InvalidEncoding("config index missing"))?,
+            identity : identity.ok_or(ThresholdError::InvalidEncoding("config identity missing"))?,
+            peer,
+            ceremony,
+            timeout,
+            key_file,
+            passphrase : passphrase.to_vec(),
+            paillier,
+        };
+        if config.index >= config.peer.len() {
+            return Err(ThresholdError::OutOfRange("index"));
+        }
+        if config.peer[config.index].identity != config.identity.public() {
+            return Err(ThresholdError::InvalidEncoding("config identity is not this party's peer key"));
+        }
+        Ok(config)
+    }
+}
+
+//a key file of the session's parties, its index is the config's if the config doesn't give one
+fn load_key<P: ThresholdCurve>(path: &Path, passphrase: &[u8], parties: usize, index: &mut Option<usize>) -> Result<KeyFile<P>, ThresholdError> {
+    let key = KeyFile::load(path, passphrase)?;
+    if key.parties() != parties || index.is_some_and(|i| i != key.index) {
+        return Err(ThresholdError::InvalidEncoding("key file is for another party"));
+    }
+    *index = Some(key.index);
+    Ok(key)
+}
+
+//pri_p and pri_q lines of a paillier-keygen out put
+fn read_paillier(path: &str) -> Result<(BigUint, BigUint), ThresholdError> {
+    let mut pri_p = None;
+    let mut pri_q = None;
+    for line in std::fs::read_to_string(path)?.lines() {
+        let word: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        match word.as_slice() {
+            ["pri_p", p] => pri_p = Some(parse_big(p)?),
+            ["pri_q", q] => pri_q = Some(parse_big(q)?),
+            _ => {}
+        }
+    }
+    pri_p.zip(pri_q).ok_or(ThresholdError::InvalidEncoding("paillier file needs pri_p and pri_q"))
+}
+
+//what keygen and reshare out put, the share is left out if it went into the key file
+fn key_output<P: ThresholdCurve>(config: &DaemonConfig, rng: &mut (impl CryptoRng + RngCore), threshold: usize, key_share: P::Scalar, pub_key: P, verification_share: Vec<P>) -> Result<String, ThresholdError> {
+    let mut output = format!("curve {}\nindex {}\nthreshold {}\npub_key {}\n", P::NAME, config.index, threshold, to_hex(pub_key.to_bytes().as_ref()));
+    for (i, point) in verification_share.iter().enumerate() {
+        output += &format!("verification_share {} {}\n", i, to_hex(point.to_bytes().as_ref()));
+    }
+    match (&config.key_file, &config.paillier) {
+        (Some(path), Some((pri_p, pri_q))) => {
+            let key = KeyFile {
+                index : config.index,
+                threshold,
+                share : Secret::new(key_share),
+                pub_key,
+                verification_share,
+                pri_p : Secret::new(pri_p.clone()),
+                pri_q : Secret::new(pri_q.clone()),
+            };
+            key.check()?;
+            key.save(rng, path, &config.passphrase)?;
+            output += &format!("key_file {}\n", path.display());
+        }
+        _ => output += &format!("key_share {}\n", to_hex(key_share.to_repr().as_ref())),
+    }
+    Ok(output)
+}
+
+//the key ceremony on P
+fn keygen<T: Transport, P: EcdsaCurve>(config: &DaemonConfig, mut session: Session<T, P>, rng: &mut (impl CryptoRng + RngCore), threshold: usize) -> Result<String, ThresholdError> {
+    let (key_share, pub_key, verification_share) = run_keygen(&mut session, rng, threshold)?;
+    key_output(config, rng, threshold, *key_share.expose(), pub_key, verification_share)
+}
+
+//the old key on P to the new threshold
+fn reshare<T: Transport, P: EcdsaCurve>(config: &DaemonConfig, mut session: Session<T, P>, rng: &mut (impl CryptoRng + RngCore), key: &OldKey<P>, threshold: usize) -> Result<String, ThresholdError> {
+    let (key_share, verification_share) = run_reshare(&mut session, rng, key.key_share, key.pub_key, &key.verification_share, threshold)?;
+    key_output(config, rng, threshold, key_share, key.pub_key, verification_share)
+}
+
+//the session is on the curve of the ceremony, the transport carry any
+fn open_session<T: Transport, P: EcdsaCurve>(transport: T, session_id: SessionId, timeout: Duration) -> Session<T, P> {
+    let mut session = Session::new(transport, session_id);
+    session.set_timeout(timeout);
+    session
+}
+
+//the store at path, made if there is none yet, with NONCE_BATCH new pairs once every pair is used
+fn open_nonce_store(rng: &mut (impl CryptoRng + RngCore), path: &Path, passphrase: &[u8], key_share: &Secret<k256::Scalar>) -> Result<NonceStore<k256::AffinePoint>, ThresholdError> {
+    let mut store = match path.exists() {
+        true => NonceStore::open(path, passphrase)?,
+        false => NonceStore::create(rng, path, passphrase, KdfParams::recommended())?,
+    };
+    if store.unused() == 0 {
+        store.preprocess(rng, key_share, NONCE_BATCH)?;
+    }
+    Ok(store)
+}
+
+//the signing rounds on the curve of the key file, every party of the session sign. the own share
+//and its check go to identify_abort if s doesn't verify, with the k_i * G and sigma_i * G every
+//party broadcast in round 3
+fn sign<T: Transport, P: EcdsaCurve>(mut session: Session<T, P>, rng: &mut (impl CryptoRng + RngCore), key: &KeyFile<P>, mta_pub_n: Vec<BigUint>, ring_pedersen: Vec<RingPedersenParams>, digest: &[u8; 32]) -> Result<String, ThresholdError> {
+    let signer_set: Vec<u128> = (1..=session.parties() as u128).collect();
+    let secret = key.party_secret(rng, digest, &signer_set)?;
+    let mut party = Party::new(key.index, secret, mta_pub_n, ring_pedersen)?;
+    let (commitment, s, own, round3) = run_signing(&mut session, rng, &mut party, digest)?;
+    let mut check = Vec::new();
+    own.share_check(rng, commitment).encode(&mut check);
+    let mut output = sign_output(digest, commitment, s)? + &format!("share {} {} {}\n", key.index, to_hex(own.s.to_repr().as_ref()), to_hex(&check));
+    for i in round3 {
+        let (k, sigma) = i.share_commitment();
+        output += &format!("share_commitment {} {} {}\n", i.from, to_hex(k.to_bytes().as_ref()), to_hex(sigma.to_bytes().as_ref()));
+    }
+    Ok(output)
+}
+
+//the curve first, so verify know how to read the rest. r, s and the recovery id are the standard
+//signature, low s on secp256k1, and the commitment R is kept for the pallas check and audit
+pub fn sign_output<P: EcdsaCurve>(digest: &[u8; 32], commitment: P, s: P::Scalar) -> Result<String, ThresholdError> {
+    let signature = EcdsaSignature::new(commitment, s)?;
+    Ok(format!("curve {}\ndigest {}\ncommitment {}\nr {}\ns {}\nrecovery_id {}\n", P::NAME, to_hex(digest), to_hex(commitment.to_bytes().as_ref()),
+        to_hex(signature.r.to_repr().as_ref()), to_hex(signature.s.to_repr().as_ref()), signature.recovery_id))
+}
+
+//connect, run the ceremony and out put the result one value a line. the config is taken by
+//value, the key file is dropped and wiped with it, and the party secret of a sign ceremony move
+//into the Party and is never copied
+pub fn run(config: DaemonConfig, rng: &mut (impl CryptoRng + RngCore)) -> Result<String, ThresholdError> {
+    let socket = SocketTransport::connect(rng, &config)?;
+    //key shares and paillier ciphertexts are encrypted to their receiver, broadcasts are signed and echoed
+    let identity = IdentityKey::from_secret(config.identity.secret())
+        .ok_or(ThresholdError::InvalidEncoding("config identity"))?;
+    let transport = SecureTransport::new(rng, socket, identity, config.peer.iter().map(|i| i.identity).collect())?;
+    let (session_id, timeout) = (config.session_id, config.timeout);
+    match config.ceremony {
+        Ceremony::Keygen { curve, threshold } => match curve {
+            SignCurve::Pallas => keygen::<_, pallas::Affine>(&config, open_session(transport, session_id, timeout), rng, threshold),
+            SignCurve::Secp256k1 => keygen::<_, k256::AffinePoint>(&config, open_session(transport, session_id, timeout), rng, threshold),
+            SignCurve::P256 => keygen::<_, p256::AffinePoint>(&config, open_session(transport, session_id, timeout), rng, threshold),
+        },
+        Ceremony::Sign { ref key, mta_pub_n, ring_pedersen, ref digest } => match key {
+            SignKey::Pallas(key) => sign(open_session(transport, session_id, timeout), rng, key, mta_pub_n, ring_pedersen, digest),
+            SignKey::Secp256k1(key) => sign(open_session(transport, session_id, timeout), rng, key, mta_pub_n, ring_pedersen, digest),
+            SignKey::P256(key) => sign(open_session(transport, session_id, timeout), rng, key, mta_pub_n, ring_pedersen, digest),
+        },
+        Ceremony::SignBip340 { ref key, message, ref nonce_store } => {
+            let mut store = match nonce_store {
+                Some(path) => Some(open_nonce_store(rng, path, &config.passphrase, &key.share)?),
+                None => None,
+            };
+            let signature = run_bip340_signing(&mut open_session(transport, session_id, timeout), rng, &key.share, &key.pub_key, &key.verification_share, &message, store.as_mut())?;
+            Ok(format!("curve secp256k1\nscheme bip340\nmessage {}\nsignature {}\n", to_hex(&message), to_hex(&signature)))
+        }
+        Ceremony::Reshare { ref key, threshold } => match key {
+            ReshareKey::Pallas(key) => reshare(&config, open_session(transport, session_id, timeout), rng, key, threshold),
+            ReshareKey::Secp256k1(key) => reshare(&config, open_session(transport, session_id, timeout), rng, key, threshold),
+            ReshareKey::P256(key) => reshare(&config, open_session(transport, session_id, timeout), rng, key, threshold),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::{Add, Mul};
+    use pasta_curves::group::{cofactor::CofactorCurveAffine, Curve};
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+    use crate::bip340;
+    use crate::identifiable_abort::commitment_r;
+    use crate::key_file::{test_key_files, TEST_PARAMS};
+    use crate::paillier_modulus::test_paillier_key;
+    use crate::ring_pedersen::test_ring_pedersen;
+
+    fn config_text(session_id: &SessionId, index: usize, identity: &[IdentityKey], address: &[String], ceremony: &str) -> String {
+        let mut text = format!("session {}\nindex {}\nidentity {}\ntimeout 10\n", to_hex(session_id), index, to_hex(&identity[index].secret().to_repr()));
+        for (i, (key, address)) in identity.iter().zip(address).enumerate() {
+            text += &format!("peer {} {} {}\n", i, address, to_hex(&key.public().to_bytes()));
+        }
+        text + ceremony
+    }
+
+    fn value(output: &str, name: &str) -> String {
+        output.lines().find_map(|i| i.strip_prefix(&format!("{} ", name))).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn daemon_keygen_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let dir = std::env::temp_dir();
+        let address: Vec<String> = (0..3).map(|i| format!("unix:{}/myp-{}-{}.sock", dir.display(), to_hex(&session_id[..8]), i)).collect();
+        let config: Vec<String> = (0..3).map(|i| config_text(&session_id, i, &identity, &address, "keygen 2\n")).collect();
+        //the key is never typed in
+        let typed = config[0].replace("keygen 2", "keygen 5 7 2");
+        assert_eq!(DaemonConfig::parse(&typed).err(), Some(ThresholdError::InvalidEncoding("config keygen takes only the threshold, the key is dealt at random")));
+
+        let output: Vec<Result<String, ThresholdError>> = std::thread::scope(|scope| {
+            let handle: Vec<_> = config.iter().map(|i| scope.spawn(move || run(DaemonConfig::parse(i)?, &mut rand::thread_rng()))).collect();
+            handle.into_iter().map(|i| i.join().unwrap()).collect()
+        });
+        let output: Vec<String> = output.into_iter().map(|i| i.unwrap()).collect();
+        let generator = pallas::Affine::generator();
+        for (index, i) in output.iter().enumerate() {
+            assert_eq!(value(i, "pub_key"), value(&output[0], "pub_key"));
+            let key_share = parse_scalar(&value(i, "key_share")).unwrap();
+            let verification_share = to_hex(&pallas::Affine::mul(generator, key_share).to_affine().to_bytes());
+            assert!(i.contains(&format!("verification_share {} {}\n", index, verification_share)));
+        }
+        for i in address {
+            let _ = std::fs::remove_file(i.trim_start_matches("unix:"));
+        }
+    }
+
+    //keygen into a key file on P, then reshare it to threshold 3
+    fn daemon_key_file<P: ThresholdCurve>(curve: &str){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let dir = std::env::temp_dir().join(format!("myp-key-{}", to_hex(&session_id[..8])));
+        std::fs::create_dir_all(&dir).unwrap();
+        let address: Vec<String> = (0..3).map(|i| format!("unix:{}/{}.sock", dir.display(), i)).collect();
+        let (pri_p, pri_q): (Vec<BigUint>, Vec<BigUint>) = (0..3).map(test_paillier_key).unzip();
+        let key_file: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("key{}", i))).collect();
+        let passphrase = |i: usize| format!("party {} passphrase", i);
+        let run_all = |config: Vec<String>| -> Vec<Result<String, ThresholdError>> {
+            std::thread::scope(|scope| {
+                let handle: Vec<_> = config.iter().enumerate().map(|(i, text)| {
+                    scope.spawn(move || run(DaemonConfig::parse_with_passphrase(text, passphrase(i).as_bytes())?, &mut rand::thread_rng()))
+                }).collect();
+                handle.into_iter().map(|i| i.join().unwrap()).collect()
+            })
+        };
+
+        let config: Vec<String> = (0..3).map(|i| {
+            let paillier = dir.join(format!("paillier{}", i));
+            std::fs::write(&paillier, format!("pri_p {}\npri_q {}\n", pri_p[i], pri_q[i])).unwrap();
+            let ceremony = format!("{}keygen 2\nkey_file {}\npaillier_file {}\n", curve, key_file[i].display(), paillier.display());
+            config_text(&session_id, i, &identity, &address, &ceremony)
+        }).collect();
+        let output: Vec<String> = run_all(config).into_iter().map(|i| i.unwrap()).collect();
+        //the share only went to the key file
+        assert!(output.iter().all(|i| !i.contains("key_share") && i.contains("key_file")));
+        assert_eq!(value(&output[0], "curve"), P::NAME);
+        let key: KeyFile<P> = KeyFile::load(&key_file[1], passphrase(1).as_bytes()).unwrap();
+        assert_eq!((key.index, key.threshold, key.mta_pub_n()), (1, 2, &pri_p[1] * &pri_q[1]));
+        assert_eq!(to_hex(key.pub_key.to_bytes().as_ref()), value(&output[0], "pub_key"));
+        assert!(KeyFile::<P>::load(&key_file[1], passphrase(0).as_bytes()).is_err());
+
+        //reshare to threshold 3 read the key file and write the new share back
+        let session_id: SessionId = rand::thread_rng().gen();
+        let config: Vec<String> = (0..3).map(|i| {
+            config_text(&session_id, i, &identity, &address, &format!("{}reshare 3\nkey_file {}\n", curve, key_file[i].display()))
+        }).collect();
+        for i in run_all(config) {
+            assert_eq!(value(&i.unwrap(), "pub_key"), to_hex(key.pub_key.to_bytes().as_ref()));
+        }
+        let new: KeyFile<P> = KeyFile::load(&key_file[1], passphrase(1).as_bytes()).unwrap();
+        assert_eq!((new.threshold, new.pub_key, new.pri_p.expose()), (3, key.pub_key, key.pri_p.expose()));
+        assert_ne!(new.share.expose(), key.share.expose());
+
+        //a key file can't be opened without the passphrase
+        let text = config_text(&session_id, 0, &identity, &address, &format!("reshare 3\nkey_file {}\n", key_file[0].display()));
+        assert_eq!(DaemonConfig::parse(&text).err(), Some(ThresholdError::InvalidEncoding("config key_file needs a passphrase")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn daemon_key_file_test(){
+        daemon_key_file::<pallas::Affine>("");
+        daemon_key_file::<k256::AffinePoint>("curve secp256k1\n");
+        daemon_key_file::<p256::AffinePoint>("curve p256\n");
+    }
+
+    //a sign of the message by the 3 parties, each with a key file of the dealt key on P under
+    //the passphrase "pass"
+    fn sign_config<P: ThresholdCurve>(dir: &Path, session_id: &SessionId, identity: &[IdentityKey], address: &[String], curve: &str, message: &str) -> (Vec<String>, P) {
+        std::fs::create_dir_all(dir).unwrap();
+        let key = test_key_files::<P>(&mut rand::thread_rng());
+        let mta_pub_n: Vec<BigUint> = key.iter().map(|i| i.mta_pub_n()).collect();
+        let ring_pedersen: String = (0..3).map(|i| {
+            let (params, proof) = test_ring_pedersen(i);
+            format!("ring_pedersen {} {} {}\n", i, encode_hex(&params), encode_hex(&proof))
+        }).collect();
+        let config = key.iter().map(|key| {
+            let path = dir.join(format!("key{}", key.index));
+            key.save_with(&mut rand::thread_rng(), &path, b"pass", TEST_PARAMS).unwrap();
+            let ceremony = format!("{}sign {}\nmta_pub_n {} {} {}\n{}key_file {}\n", curve, message,
+                mta_pub_n[0], mta_pub_n[1], mta_pub_n[2], ring_pedersen, path.display());
+            config_text(session_id, key.index, identity, address, &ceremony)
+        }).collect();
+        (config, key[0].pub_key)
+    }
+
+    fn run_sign(config: &[String]) -> Vec<Result<String, ThresholdError>> {
+        std::thread::scope(|scope| {
+            let handle: Vec<_> = config.iter().map(|i| scope.spawn(move || run(DaemonConfig::parse_with_passphrase(i, b"pass")?, &mut rand::thread_rng()))).collect();
+            handle.into_iter().map(|i| i.join().unwrap()).collect()
+        })
+    }
+
+    #[test]
+    fn daemon_sign_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        //free ports picked by the os
+        let address: Vec<String> = (0..3).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let dir = std::env::temp_dir().join(format!("myp-sign-{}", to_hex(&session_id[..8])));
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let (config, pub_key) = sign_config::<pallas::Affine>(&dir, &session_id, &identity, &address, "", &to_hex(&digest));
+        let sign = format!("sign {}", to_hex(&digest));
+
+        //w is the key file's and k and r are always hedged, a config can give none of them
+        let given = config[0].replace(&sign, &format!("{} 01 02 03", sign));
+        assert_eq!(DaemonConfig::parse_with_passphrase(&given, b"pass").err(), Some(ThresholdError::InvalidEncoding("config sign takes only the message, w is from the key file and k and r are hedged")));
+        let given = config[0].replace(&sign, &format!("{} 03", sign));
+        assert_eq!(DaemonConfig::parse_with_passphrase(&given, b"pass").err(), Some(ThresholdError::InvalidEncoding("config sign takes only the message, w is from the key file and k and r are hedged")));
+        //the digest is a full 32 byte hash, not a number
+        let short = config[0].replace(&sign, "sign 12345");
+        assert_eq!(DaemonConfig::parse_with_passphrase(&short, b"pass").err(), Some(ThresholdError::InvalidEncoding("config hex")));
+        let no_key = config[0].lines().filter(|i| !i.starts_with("key_file")).collect::<Vec<&str>>().join("\n");
+        assert_eq!(DaemonConfig::parse(&no_key).err(), Some(ThresholdError::InvalidEncoding("config sign needs a key_file")));
+        //a secp256k1 key file is not a pallas one
+        let other = config[0].replace(&dir.join("key0").display().to_string(), &dir.join("other0").display().to_string());
+        test_key_files::<k256::AffinePoint>(&mut rand::thread_rng())[0].save_with(&mut rand::thread_rng(), &dir.join("other0"), b"pass", TEST_PARAMS).unwrap();
+        assert_eq!(DaemonConfig::parse_with_passphrase(&other, b"pass").err(), Some(ThresholdError::InvalidEncoding("key file is for another curve")));
+        //every party's ring pedersen params are needed, and each must come with its own proof
+        let no_params = config[0].lines().filter(|i| !i.starts_with("ring_pedersen 2")).collect::<Vec<&str>>().join("\n");
+        assert_eq!(DaemonConfig::parse_with_passphrase(&no_params, b"pass").err(), Some(ThresholdError::InvalidEncoding("config ring_pedersen missing")));
+        let (params_0, _) = test_ring_pedersen(0);
+        let (_, proof_1) = test_ring_pedersen(1);
+        let line = config[0].lines().find(|i| i.starts_with("ring_pedersen 0")).unwrap();
+        let swapped = config[0].replace(line, &format!("ring_pedersen 0 {} {}", encode_hex(&params_0), encode_hex(&proof_1)));
+        assert_eq!(DaemonConfig::parse_with_passphrase(&swapped, b"pass").err(), Some(ThresholdError::InvalidModulus(params_0.n_tilde.clone())));
+
+        let output = run_sign(&config);
+        let _ = std::fs::remove_dir_all(&dir);
+        let output = match output.into_iter().collect::<Result<Vec<String>, ThresholdError>>() {
+            Ok(output) => output,
+            //x of R doesn't fit in the scalar field, too rare to care in a test
+            Err(ThresholdError::OutOfRange("r")) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+        //s * R == m * G + r * pub_key
+        let commitment = parse_point(&value(&output[0], "commitment")).unwrap();
+        let s = parse_scalar(&value(&output[0], "s")).unwrap();
+        assert!(output.iter().all(|i| value(i, "commitment") == value(&output[0], "commitment") && value(i, "s") == value(&output[0], "s")));
+        //and the round 3 k_i * G and sigma_i * G of every party for audit
+        assert_eq!(output[0].lines().filter(|i| i.starts_with("share_commitment ")).count(), 3);
+        let generator = pallas::Affine::generator();
+        let r = commitment_r(commitment).unwrap();
+        assert_eq!(parse_scalar(&value(&output[0], "r")).unwrap(), r);
+        let left = pallas::Affine::mul(commitment, s).to_affine();
+        let right = pallas::Affine::add(
+            pallas::Affine::mul(generator, pallas::Affine::digest_scalar(&digest)).to_affine(),
+            pallas::Affine::mul(pub_key, r),
+        ).to_affine();
+        assert_eq!(left, right);
+    }
+
+    //a sign of a random digest on P by 3 daemons over tcp, the pub key, the digest and every output.
+    //None if x of R doesn't fit in the scalar field, too rare to care in a test
+    fn ecdsa_daemon_sign<P: ThresholdCurve>(curve: &str) -> Option<(P, [u8; 32], Vec<String>)> {
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let address: Vec<String> = (0..3).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let dir = std::env::temp_dir().join(format!("myp-{}-{}", P::NAME, to_hex(&session_id[..8])));
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let (config, pub_key) = sign_config::<P>(&dir, &session_id, &identity, &address, curve, &to_hex(&digest));
+        let output = run_sign(&config);
+        let _ = std::fs::remove_dir_all(&dir);
+        match output.into_iter().collect::<Result<Vec<String>, ThresholdError>>() {
+            Ok(output) => {
+                assert_eq!(value(&output[0], "curve"), P::NAME);
+                assert_eq!(value(&output[0], "digest"), to_hex(&digest));
+                assert!(output.iter().all(|i| ["r", "s", "recovery_id"].iter().all(|name| value(i, name) == value(&output[0], name))));
+                //the share check for audit is on every curve
+                assert!(output.iter().all(|i| i.lines().filter(|i| i.starts_with("share_commitment ")).count() == 3 && i.contains("\nshare ")));
+                Some((pub_key, digest, output))
+            }
+            Err(ThresholdError::OutOfRange("r")) => None,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn daemon_secp256k1_sign_test(){
+        //a curve the daemon has no impl for is refused
+        let identity = [IdentityKey::generate(&mut rand::thread_rng())];
+        let unknown = config_text(&[0; 32], 0, &identity, &["tcp:127.0.0.1:1".to_string()], "curve ed448\nkeygen 1\n");
+        assert_eq!(DaemonConfig::parse(&unknown).err(), Some(ThresholdError::InvalidEncoding("config curve")));
+
+        let Some((pub_key, digest, output)) = ecdsa_daemon_sign::<k256::AffinePoint>("curve secp256k1\n") else {
+            return;
+        };
+        //the k256 verifier take r and s, s is low and the recovery id give the pub key back
+        let r: k256::Scalar = parse_scalar_on(&value(&output[0], "r")).unwrap();
+        let s: k256::Scalar = parse_scalar_on(&value(&output[0], "s")).unwrap();
+        assert!(!k256::AffinePoint::is_high(&s));
+        let verifying_key = k256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+        let signature = k256::ecdsa::Signature::from_scalars(r, s).unwrap();
+        assert!(verifying_key.verify_prehash(&digest, &signature).is_ok());
+        let recovery_id = k256::ecdsa::RecoveryId::from_byte(value(&output[0], "recovery_id").parse().unwrap()).unwrap();
+        assert_eq!(k256::ecdsa::VerifyingKey::recover_from_prehash(&digest, &signature, recovery_id).unwrap(), verifying_key);
+    }
+
+    #[test]
+    fn daemon_p256_sign_test(){
+        let Some((pub_key, digest, output)) = ecdsa_daemon_sign::<p256::AffinePoint>("curve p256\n") else {
+            return;
+        };
+        let r: p256::Scalar = parse_scalar_on(&value(&output[0], "r")).unwrap();
+        let s: p256::Scalar = parse_scalar_on(&value(&output[0], "s")).unwrap();
+        let verifying_key = p256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+        let signature = p256::ecdsa::Signature::from_scalars(r, s).unwrap();
+        assert!(verifying_key.verify_prehash(&digest, &signature).is_ok());
+        let recovery_id = k256::ecdsa::RecoveryId::from_byte(value(&output[0], "recovery_id").parse().unwrap()).unwrap();
+        assert_eq!(p256::ecdsa::VerifyingKey::recover_from_prehash(&digest, &signature, recovery_id).unwrap(), verifying_key);
+    }
+
+    #[test]
+    fn daemon_bip340_sign_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..3).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let address: Vec<String> = (0..3).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let dir = std::env::temp_dir().join(format!("myp-bip340-{}", to_hex(&session_id[..8])));
+        let message: [u8; 32] = rand::thread_rng().gen();
+        let (config, pub_key) = sign_config::<k256::AffinePoint>(&dir, &session_id, &identity, &address, "curve secp256k1\nscheme bip340\n", &to_hex(&message));
+        //bip340 has no paillier rounds, and is on secp256k1 only
+        assert_eq!(DaemonConfig::parse_with_passphrase(&config[0], b"pass").err(), Some(ThresholdError::InvalidEncoding("config needs keygen, sign with mta_pub_n, bip340 sign or reshare")));
+        let config: Vec<String> = config.iter().map(|i| i.lines().filter(|i| !i.starts_with("mta_pub_n")).collect::<Vec<&str>>().join("\n")).collect();
+        let pallas = config[0].replace("curve secp256k1", "curve pallas");
+        assert_eq!(DaemonConfig::parse_with_passphrase(&pallas, b"pass").err(), Some(ThresholdError::InvalidEncoding("config scheme bip340 is a sign on curve secp256k1")));
+        //every party take its nonce pair from its own store, made on this first sign
+        let config: Vec<String> = config.iter().enumerate().map(|(i, text)| format!("{}\nnonce_store {}\n", text, dir.join(format!("nonce{}", i)).display())).collect();
+        let ecdsa = config[0].replace("scheme bip340", "scheme ecdsa");
+        assert_eq!(DaemonConfig::parse_with_passphrase(&ecdsa, b"pass").err(), Some(ThresholdError::InvalidEncoding("config nonce_store is for a bip340 sign")));
+
+        let output = run_sign(&config);
+        let store = NonceStore::<k256::AffinePoint>::open(&dir.join("nonce0"), b"pass");
+        let _ = std::fs::remove_dir_all(&dir);
+        //the pair that signed is used, the rest of the batch is left for the next sign
+        let store = store.unwrap();
+        assert_eq!((store.unused(), store.next_unused().map(|i| i.0)), (NONCE_BATCH - 1, Some(1)));
+        let output = output.into_iter().collect::<Result<Vec<String>, ThresholdError>>().unwrap();
+        assert_eq!(value(&output[0], "scheme"), "bip340");
+        assert!(output.iter().all(|i| value(i, "signature") == value(&output[0], "signature")));
+        let signature: [u8; 64] = parse_hex(&value(&output[0], "signature")).unwrap();
+        assert_eq!(value(&output[0], "message"), to_hex(&message));
+        assert_eq!(bip340::verify(&bip340::x_only(&pub_key), &message, &signature), Ok(()));
+    }
+
+    #[test]
+    fn impostor_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let address: Vec<String> = (0..2).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let honest = config_text(&session_id, 1, &identity, &address, "keygen 2\n");
+        //party 0 run with a key that isn't the one party 1 has for it
+        let impostor = [IdentityKey::generate(&mut rand::thread_rng()), IdentityKey::from_secret(identity[1].secret()).unwrap()];
+        let impostor_config = DaemonConfig::parse(&config_text(&session_id, 0, &impostor, &address, "keygen 2\n")).unwrap();
+
+        let (_, result) = std::thread::scope(|scope| {
+            let impostor = scope.spawn(move || run(impostor_config, &mut rand::thread_rng()));
+            let honest = scope.spawn(move || run(DaemonConfig::parse(&honest)?, &mut rand::thread_rng()));
+            (impostor.join().unwrap(), honest.join().unwrap())
+        });
+        assert_eq!(result, Err(ThresholdError::VerificationFailed { party: 0 }));
+
+        assert!(DaemonConfig::parse("index 0\n").is_err());
+        assert_eq!(parse_address("udp:1.2.3.4:5"), Err(ThresholdError::InvalidEncoding("config address")));
+    }
+
+    #[test]
+    fn stray_connection_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let address: Vec<String> = (0..2).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let config: Vec<String> = (0..2).map(|i| config_text(&session_id, i, &identity, &address, "keygen 2\n")).collect();
+        let impostor = [IdentityKey::from_secret(identity[0].secret()).unwrap(), IdentityKey::generate(&mut rand::thread_rng())];
+        let impostor_config = DaemonConfig::parse(&config_text(&session_id, 1, &impostor, &address, "keygen 2\n")).unwrap();
+        let listen = parse_address(&address[0]).unwrap();
+
+        let output = std::thread::scope(|scope| {
+            let first = scope.spawn(|| run(DaemonConfig::parse(&config[0])?, &mut rand::thread_rng()));
+            //garbage, then a handshake as party 1 with the wrong key, party 0 drop both
+            let mut stray = loop {
+                match Stream::connect(&listen) {
+                    Ok(stream) => break stream,
+                    Err(_) => std::thread::sleep(DIAL_INTERVAL),
+                }
+            };
+            write_frame(&mut stray, b"hello").unwrap();
+            drop(stray);
+            let mut connection = Stream::connect(&listen).unwrap();
+            assert_eq!(handshake(&mut connection, &mut rand::thread_rng(), &impostor_config, Some(0), Instant::now() + impostor_config.timeout), Ok(0));
+            drop(connection);
+            let second = run(DaemonConfig::parse(&config[1]).unwrap(), &mut rand::thread_rng());
+            (first.join().unwrap(), second)
+        });
+        let pub_key = value(&output.0.unwrap(), "pub_key");
+        assert_eq!(pub_key, value(&output.1.unwrap(), "pub_key"));
+    }
+
+    #[test]
+    fn silent_connection_test(){
+        let session_id: SessionId = rand::thread_rng().gen();
+        let identity: Vec<IdentityKey> = (0..2).map(|_| IdentityKey::generate(&mut rand::thread_rng())).collect();
+        let address: Vec<String> = (0..2).map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("tcp:{}", listener.local_addr().unwrap())
+        }).collect();
+        let config: Vec<String> = (0..2).map(|i| config_text(&session_id, i, &identity, &address, "keygen 2\n")).collect();
+        let listen = parse_address(&address[0]).unwrap();
+
+        let start = Instant::now();
+        let output = std::thread::scope(|scope| {
+            let first = scope.spawn(|| run(DaemonConfig::parse(&config[0])?, &mut rand::thread_rng()));
+            //connect and never say anything, it stay open until party 1 is done
+            let silent = loop {
+                match Stream::connect(&listen) {
+                    Ok(stream) => break stream,
+                    Err(_) => std::thread::sleep(DIAL_INTERVAL),
+                }
+            };
+            //let party 0 take the silent one first
+            std::thread::sleep(DIAL_INTERVAL * 4);
+            let second = run(DaemonConfig::parse(&config[1]).unwrap(), &mut rand::thread_rng());
+            drop(silent);
+            (first.join().unwrap(), second)
+        });
+        let pub_key = value(&output.0.unwrap(), "pub_key");
+        assert_eq!(pub_key, value(&output.1.unwrap(), "pub_key"));
+        //the silent client cost party 0 one handshake timeout, not the whole 10 seconds
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT * 3);
+    }
+}
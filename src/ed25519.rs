@@ -0,0 +1,473 @@
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::traits::{Identity, IsIdentity};
+use curve25519_dalek::Scalar;
+use k256::elliptic_curve::subtle::{Choice, CtOption};
+use pasta_curves::group::{self, prime::{PrimeCurve, PrimeCurveAffine, PrimeGroup}, Curve, GroupEncoding};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha512};
+use myp::{Secret, ThresholdError};
+use crate::curve::ThresholdCurve;
+use crate::key_generate::{lagrange_coefficient, CollectScalarShare};
+use crate::nonce::HedgedNonce;
+
+//rfc 8032 ed25519 with the key split over the signers. the signature is R | s with
+//s * B == R + SHA-512(R | A | M) * A, so any ed25519 verifier take it.
+//every signer publish two nonce points first, R = sum(D_i + rho_i * E_i) where rho_i bind the
+//nonces to the message and to everyone's commitments, so nonces from two sessions can't be mixed
+
+//the two nonces of one signing, sign_share take it by value so it is used for one message only
+pub struct SigningNonce {
+    hiding : Secret<Scalar>,
+    binding : Secret<Scalar>,
+}
+
+//what a signer broadcast before anyone sign, index is its place in the key ceremony (from 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceCommitment {
+    pub index : u128,
+    pub hiding : EdwardsPoint,
+    pub binding : EdwardsPoint,
+}
+
+//an edwards point of the prime order subgroup, so the scalar field key ceremony of key_generate.rs
+//run on ed25519 through ThresholdCurve. the group traits are on one type for both affine and curve,
+//dalek doesn't have the split
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ed25519Point(pub EdwardsPoint);
+
+impl ThresholdCurve for Ed25519Point {
+    const NAME: &'static str = "ed25519";
+
+    fn scalar_from_wide(bytes: &[u8; 64]) -> Scalar {
+        Scalar::from_bytes_mod_order_wide(bytes)
+    }
+}
+
+impl group::Group for Ed25519Point {
+    type Scalar = Scalar;
+
+    fn random(rng: impl RngCore) -> Self {
+        Ed25519Point(EdwardsPoint::mul_base(&<Scalar as ff::Field>::random(rng)))
+    }
+
+    fn identity() -> Self {
+        Ed25519Point(<EdwardsPoint as Identity>::identity())
+    }
+
+    fn generator() -> Self {
+        Ed25519Point(curve25519_dalek::constants::ED25519_BASEPOINT_POINT)
+    }
+
+    fn is_identity(&self) -> Choice {
+        Choice::from(IsIdentity::is_identity(&self.0) as u8)
+    }
+
+    fn double(&self) -> Self {
+        Ed25519Point(self.0 + self.0)
+    }
+}
+
+impl Curve for Ed25519Point {
+    type AffineRepr = Ed25519Point;
+
+    fn to_affine(&self) -> Ed25519Point {
+        *self
+    }
+}
+
+impl PrimeGroup for Ed25519Point {}
+
+impl PrimeCurve for Ed25519Point {
+    type Affine = Ed25519Point;
+}
+
+impl PrimeCurveAffine for Ed25519Point {
+    type Scalar = Scalar;
+    type Curve = Ed25519Point;
+
+    fn identity() -> Self {
+        <Ed25519Point as group::Group>::identity()
+    }
+
+    fn generator() -> Self {
+        <Ed25519Point as group::Group>::generator()
+    }
+
+    fn is_identity(&self) -> Choice {
+        <Ed25519Point as group::Group>::is_identity(self)
+    }
+
+    fn to_curve(&self) -> Ed25519Point {
+        *self
+    }
+}
+
+//the 32 bytes compressed form, a point out of the prime order subgroup doesn't decode
+impl GroupEncoding for Ed25519Point {
+    type Repr = [u8; 32];
+
+    fn from_bytes(bytes: &[u8; 32]) -> CtOption<Self> {
+        let point = decode_point(bytes, "point").ok().filter(|i| i.is_torsion_free());
+        CtOption::new(Ed25519Point(point.unwrap_or_default()), Choice::from(point.is_some() as u8))
+    }
+
+    fn from_bytes_unchecked(bytes: &[u8; 32]) -> CtOption<Self> {
+        Self::from_bytes(bytes)
+    }
+
+    fn to_bytes(&self) -> [u8; 32] {
+        self.0.compress().to_bytes()
+    }
+}
+
+impl Add for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn add(self, other: Ed25519Point) -> Ed25519Point {
+        Ed25519Point(self.0 + other.0)
+    }
+}
+
+impl Add<&Ed25519Point> for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn add(self, other: &Ed25519Point) -> Ed25519Point {
+        self + *other
+    }
+}
+
+impl Sub for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn sub(self, other: Ed25519Point) -> Ed25519Point {
+        Ed25519Point(self.0 - other.0)
+    }
+}
+
+impl Sub<&Ed25519Point> for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn sub(self, other: &Ed25519Point) -> Ed25519Point {
+        self - *other
+    }
+}
+
+impl AddAssign for Ed25519Point {
+    fn add_assign(&mut self, other: Ed25519Point) {
+        *self = *self + other;
+    }
+}
+
+impl AddAssign<&Ed25519Point> for Ed25519Point {
+    fn add_assign(&mut self, other: &Ed25519Point) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Ed25519Point {
+    fn sub_assign(&mut self, other: Ed25519Point) {
+        *self = *self - other;
+    }
+}
+
+impl SubAssign<&Ed25519Point> for Ed25519Point {
+    fn sub_assign(&mut self, other: &Ed25519Point) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn neg(self) -> Ed25519Point {
+        Ed25519Point(-self.0)
+    }
+}
+
+impl Mul<Scalar> for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn mul(self, other: Scalar) -> Ed25519Point {
+        Ed25519Point(self.0 * other)
+    }
+}
+
+impl Mul<&Scalar> for Ed25519Point {
+    type Output = Ed25519Point;
+
+    fn mul(self, other: &Scalar) -> Ed25519Point {
+        self * *other
+    }
+}
+
+impl MulAssign<Scalar> for Ed25519Point {
+    fn mul_assign(&mut self, other: Scalar) {
+        *self = *self * other;
+    }
+}
+
+impl MulAssign<&Scalar> for Ed25519Point {
+    fn mul_assign(&mut self, other: &Scalar) {
+        *self = *self * other;
+    }
+}
+
+impl Sum for Ed25519Point {
+    fn sum<I: Iterator<Item = Ed25519Point>>(iter: I) -> Ed25519Point {
+        iter.fold(<Ed25519Point as group::Group>::identity(), |acc, i| acc + i)
+    }
+}
+
+impl<'a> Sum<&'a Ed25519Point> for Ed25519Point {
+    fn sum<I: Iterator<Item = &'a Ed25519Point>>(iter: I) -> Ed25519Point {
+        iter.fold(<Ed25519Point as group::Group>::identity(), |acc, i| acc + i)
+    }
+}
+
+//the share of the scalar field key ceremony, checked against every dealer's commitment.
+//out put the share, the group key A and x_j * B of every member
+pub fn key_share(collect: &CollectScalarShare<Ed25519Point>) -> Result<(Secret<Scalar>, EdwardsPoint, Vec<EdwardsPoint>), ThresholdError> {
+    let (share, pub_key, verification_share) = collect.collect()?;
+    Ok((share, pub_key.0, verification_share.into_iter().map(|i| i.0).collect()))
+}
+
+pub fn pub_key_share(share: &Secret<Scalar>) -> EdwardsPoint {
+    EdwardsPoint::mul_base(share.expose())
+}
+
+fn scalar_from_hash(part: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for i in part {
+        hasher.update(i);
+    }
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+//hedged from the key share, the message and the rng, so a stuck rng can't give one nonce to two messages
+pub fn commit(rng: &mut (impl CryptoRng + RngCore), pri: &Secret<Scalar>, index: u128, message: &[u8]) -> (SigningNonce, NonceCommitment) {
+    let mut drbg = HedgedNonce::new(rng, "ed25519-nonce", pri.expose().as_bytes(), message);
+    let hiding = Secret::new(Scalar::from_bytes_mod_order_wide(&drbg.wide()));
+    let binding = Secret::new(Scalar::from_bytes_mod_order_wide(&drbg.wide()));
+    let commitment = NonceCommitment {
+        index,
+        hiding : EdwardsPoint::mul_base(hiding.expose()),
+        binding : EdwardsPoint::mul_base(binding.expose()),
+    };
+    (SigningNonce { hiding, binding }, commitment)
+}
+
+//rho_i, over the message, the group key and every commitment in signer order
+fn binding_factor(index: u128, message: &[u8], commitment: &[NonceCommitment], pub_key: &EdwardsPoint) -> Scalar {
+    let mut transcript = Vec::new();
+    for i in commitment {
+        transcript.extend_from_slice(&i.index.to_be_bytes());
+        transcript.extend_from_slice(i.hiding.compress().as_bytes());
+        transcript.extend_from_slice(i.binding.compress().as_bytes());
+    }
+    let message_hash = Sha512::digest(message);
+    scalar_from_hash(&[b"myp-ed25519-binding", pub_key.compress().as_bytes(), &message_hash, &transcript, &index.to_be_bytes()])
+}
+
+//one commitment for every signer in signer_set, in the same order, and none of them small order
+fn check_commitment(signer_set: &[u128], commitment: &[NonceCommitment]) -> Result<(), ThresholdError> {
+    if commitment.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: commitment.len() });
+    }
+    for (party, (index, i)) in signer_set.iter().zip(commitment).enumerate() {
+        if i.index != *index || i.hiding.is_small_order() || i.binding.is_small_order() {
+            return Err(ThresholdError::VerificationFailed { party });
+        }
+    }
+    Ok(())
+}
+
+//R and rho_i of every signer
+fn group_commitment(message: &[u8], commitment: &[NonceCommitment], pub_key: &EdwardsPoint) -> (EdwardsPoint, Vec<Scalar>) {
+    let mut r = EdwardsPoint::identity();
+    let mut rho = Vec::new();
+    for i in commitment {
+        let rho_i = binding_factor(i.index, message, commitment, pub_key);
+        r += i.hiding + i.binding * rho_i;
+        rho.push(rho_i);
+    }
+    (r, rho)
+}
+
+//the rfc 8032 challenge, SHA-512(R | A | M) mod l
+pub fn challenge(r: &EdwardsPoint, pub_key: &EdwardsPoint, message: &[u8]) -> Scalar {
+    scalar_from_hash(&[r.compress().as_bytes(), pub_key.compress().as_bytes(), message])
+}
+
+//s_i = d_i + rho_i * e_i + c * lambda_i * x_i, pri is the share from the ceremony without lambda
+pub fn sign_share(
+    nonce : SigningNonce,
+    pri : &Secret<Scalar>,
+    index : u128,
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    pub_key : &EdwardsPoint,
+) -> Result<Scalar, ThresholdError> {
+    check_commitment(signer_set, commitment)?;
+    let party = signer_set.iter().position(|i| *i == index).ok_or(ThresholdError::OutOfRange("index"))?;
+    //the aggregator must have passed on this signer's own commitment, else the nonce sign for another R
+    if commitment[party].hiding != EdwardsPoint::mul_base(nonce.hiding.expose())
+        || commitment[party].binding != EdwardsPoint::mul_base(nonce.binding.expose()) {
+        return Err(ThresholdError::VerificationFailed { party });
+    }
+    let (r, rho) = group_commitment(message, commitment, pub_key);
+    let c = challenge(&r, pub_key, message);
+    let lambda: Scalar = lagrange_coefficient(index, signer_set)?;
+    Ok(nonce.hiding.expose() + rho[party] * nonce.binding.expose() + c * lambda * pri.expose())
+}
+
+//verification_shares[i] = x_i * B of signer_set[i], s_i * B == D_i + rho_i * E_i + c * lambda_i * X_i
+pub fn verify_share(
+    index : u128,
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    s : &Scalar,
+    verification_shares : &[EdwardsPoint],
+    pub_key : &EdwardsPoint,
+) -> Result<(), ThresholdError> {
+    check_commitment(signer_set, commitment)?;
+    if verification_shares.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: verification_shares.len() });
+    }
+    let party = signer_set.iter().position(|i| *i == index).ok_or(ThresholdError::OutOfRange("index"))?;
+    let (r, rho) = group_commitment(message, commitment, pub_key);
+    let c = challenge(&r, pub_key, message);
+    let lambda: Scalar = lagrange_coefficient(index, signer_set)?;
+    let right = commitment[party].hiding + commitment[party].binding * rho[party] + verification_shares[party] * (c * lambda);
+    if EdwardsPoint::mul_base(s) != right {
+        return Err(ThresholdError::VerificationFailed { party });
+    }
+    Ok(())
+}
+
+//every share is checked before they are added, a bad one is blamed by its place in signer_set
+pub fn combine(
+    signer_set : &[u128],
+    message : &[u8],
+    commitment : &[NonceCommitment],
+    s : &[Scalar],
+    verification_shares : &[EdwardsPoint],
+    pub_key : &EdwardsPoint,
+) -> Result<[u8; 64], ThresholdError> {
+    if s.len() != signer_set.len() {
+        return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found: s.len() });
+    }
+    let mut s_sum = Scalar::ZERO;
+    for (index, s_i) in signer_set.iter().zip(s) {
+        verify_share(*index, signer_set, message, commitment, s_i, verification_shares, pub_key)?;
+        s_sum += s_i;
+    }
+    let (r, _) = group_commitment(message, commitment, pub_key);
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(r.compress().as_bytes());
+    signature[32..].copy_from_slice(s_sum.as_bytes());
+    Ok(signature)
+}
+
+//a point must decode and encode back to the same bytes, so a signature has one encoding only
+fn decode_point(bytes: &[u8], what: &'static str) -> Result<EdwardsPoint, ThresholdError> {
+    let compressed = CompressedEdwardsY::from_slice(bytes).map_err(|_| ThresholdError::InvalidEncoding(what))?;
+    let point = compressed.decompress().ok_or(ThresholdError::InvalidEncoding(what))?;
+    if point.compress() != compressed {
+        return Err(ThresholdError::InvalidEncoding(what));
+    }
+    Ok(point)
+}
+
+//the strict rfc 8032 check: canonical A, R and s, A not small order and s * B == R + c * A
+pub fn verify(pub_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> Result<(), ThresholdError> {
+    let a = decode_point(pub_key, "public key")?;
+    if a.is_small_order() {
+        return Err(ThresholdError::InvalidPoint);
+    }
+    let r = decode_point(&signature[..32], "R")?;
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    let s: Scalar = Option::from(Scalar::from_canonical_bytes(s)).ok_or(ThresholdError::InvalidEncoding("s"))?;
+    let c = challenge(&r, &a, message);
+    if !(EdwardsPoint::mul_base(&s) - r - a * c).is_identity() {
+        return Err(ThresholdError::InvalidSignature);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+    use crate::key_generate::DealScalarShare;
+
+    #[test]
+    fn ed25519_test(){
+        //3 of 5 from the scalar field key ceremony, signers 1, 3 and 4
+        let mut rng = rand::thread_rng();
+        let deal = DealScalarShare { output_max : 5, output_min : 3 };
+        let (dealt, commitment): (Vec<Secret<Vec<Scalar>>>, Vec<Vec<Ed25519Point>>) = (0..5).map(|_| deal.deal(&mut rng).unwrap()).unzip();
+        let mut share = Vec::new();
+        let mut all_verification_shares = Vec::new();
+        let mut pub_key = EdwardsPoint::default();
+        for i in 1..=5u128 {
+            let (own, key, verification_share) = key_share(&CollectScalarShare {
+                key_share : Secret::new(dealt.iter().map(|j| j.expose()[i as usize - 1]).collect()),
+                commitment : commitment.clone(),
+                self_num : i,
+            }).unwrap();
+            share.push(own);
+            all_verification_shares = verification_share;
+            pub_key = key;
+        }
+
+        let signer_set = [1, 3, 4];
+        let verification_shares: Vec<EdwardsPoint> = signer_set.iter().map(|i| all_verification_shares[*i as usize - 1]).collect();
+        assert!(signer_set.iter().all(|i| verification_shares.contains(&pub_key_share(&share[*i as usize - 1]))));
+        let message = b"threshold ed25519";
+        //the point encoding take the group key back, and a point of order 2 is refused
+        assert_eq!(Option::from(Ed25519Point::from_bytes(&Ed25519Point(pub_key).to_bytes())), Some(Ed25519Point(pub_key)));
+        let mut order_two = [0xff; 32];
+        order_two[0] = 0xec;
+        order_two[31] = 0x7f;
+        assert!(bool::from(Ed25519Point::from_bytes(&order_two).is_none()));
+        let (nonce, commitment): (Vec<SigningNonce>, Vec<NonceCommitment>) = signer_set.iter()
+            .map(|i| commit(&mut rng, &share[*i as usize - 1], *i, message)).unzip();
+        let s: Vec<Scalar> = nonce.into_iter().zip(&signer_set)
+            .map(|(n, i)| sign_share(n, &share[*i as usize - 1], *i, &signer_set, message, &commitment, &pub_key).unwrap()).collect();
+        let signature = combine(&signer_set, message, &commitment, &s, &verification_shares, &pub_key).unwrap();
+
+        //ed25519-dalek take it as a normal signature, strict or not
+        let verifying_key = VerifyingKey::from_bytes(pub_key.compress().as_bytes()).unwrap();
+        let dalek = Signature::from_bytes(&signature);
+        assert!(verifying_key.verify(message, &dalek).is_ok());
+        assert!(verifying_key.verify_strict(message, &dalek).is_ok());
+        assert!(verifying_key.verify(b"another message", &dalek).is_err());
+        assert_eq!(verify(pub_key.compress().as_bytes(), message, &signature), Ok(()));
+
+        //s + l is the same value but not canonical
+        let mut bad = signature;
+        let l = [0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10];
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let sum = bad[32 + i] as u16 + l[i] as u16 + carry;
+            bad[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(verify(pub_key.compress().as_bytes(), message, &bad), Err(ThresholdError::InvalidEncoding("s")));
+
+        //a wrong share is blamed by its place in signer_set
+        let mut bad_s = s.clone();
+        bad_s[1] += Scalar::ONE;
+        assert_eq!(combine(&signer_set, message, &commitment, &bad_s, &verification_shares, &pub_key), Err(ThresholdError::VerificationFailed { party: 1 }));
+
+        //a nonce only sign for its own commitment
+        let (nonce, _) = commit(&mut rng, &share[0], 1, message);
+        assert_eq!(sign_share(nonce, &share[0], 1, &signer_set, message, &commitment, &pub_key), Err(ThresholdError::VerificationFailed { party: 0 }));
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use num_bigint::BigUint;
+
+//every public step of the protocol return this instead of panic, so the caller can report
+//which check failed and which party is to blame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThresholdError {
+    //a paillier or ring pedersen modulus that is too small, not a valid p * q or fail its proof
+    InvalidModulus(BigUint),
+    //a value is outside the range the step needs, e.g. k * r >= n
+    OutOfRange(&'static str),
+    //gcd != 1, or a scalar is zero
+    NonInvertible,
+    //the per-party inputs don't have the same number of players
+    LengthMismatch { expected: usize, found: usize },
+    //the identity where a real point is needed
+    InvalidPoint,
+    //party is the index of the player in the input vec
+    VerificationFailed { party: usize },
+    //the group check failed but every single share is consistent, so nobody can be blamed
+    //without opening the mta transcript
+    Unattributable,
+    //a party round is called before the one it needs, or twice
+    UnexpectedRound(usize),
+    //a wire message that is truncated, has trailing bytes or a non canonical scalar or point
+    InvalidEncoding(&'static str),
+    //the channel to this party is closed
+    Disconnected { party: usize },
+    //a round ran out of time, missing is every party it heard nothing from
+    Timeout { missing: Vec<usize> },
+    //a socket or file operation failed
+    Io(std::io::ErrorKind),
+    //s * R != m * G + r * pub_key for the whole signature
+    InvalidSignature,
+    //a preprocessed nonce that already signed once, it never sign again
+    NonceConsumed(u128),
+}
+
+impl fmt::Display for ThresholdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThresholdError::InvalidModulus(n) => write!(f, "invalid modulus of {} bits", n.bits()),
+            ThresholdError::OutOfRange(what) => write!(f, "{} is out of range", what),
+            ThresholdError::NonInvertible => write!(f, "value has no inverse"),
+            ThresholdError::LengthMismatch { expected, found } => {
+                write!(f, "expected input for {} parties, found {}", expected, found)
+            }
+            ThresholdError::InvalidPoint => write!(f, "point is the identity"),
+            ThresholdError::VerificationFailed { party } => {
+                write!(f, "verification failed for party {}", party)
+            }
+            ThresholdError::Unattributable => write!(f, "group check failed without a party to blame"),
+            ThresholdError::UnexpectedRound(round) => write!(f, "round {} is not the next one", round),
+            ThresholdError::InvalidEncoding(what) => write!(f, "invalid encoding: {}", what),
+            ThresholdError::Disconnected { party } => write!(f, "party {} is disconnected", party),
+            ThresholdError::Timeout { missing } => write!(f, "timed out waiting for parties {:?}", missing),
+            ThresholdError::Io(kind) => write!(f, "io error: {}", kind),
+            ThresholdError::InvalidSignature => write!(f, "signature doesn't verify"),
+            ThresholdError::NonceConsumed(id) => write!(f, "nonce {} is already used", id),
+        }
+    }
+}
+
+impl std::error::Error for ThresholdError {}
+
+impl From<std::io::Error> for ThresholdError {
+    fn from(e: std::io::Error) -> ThresholdError {
+        ThresholdError::Io(e.kind())
+    }
+}
@@ -8,15 +8,20 @@ use pasta_curves::group::{Curve, Group};
 use halo2_proofs::{
     circuit::{Layouter, SimpleFloorPlanner, Value, Chip},
     dev::MockProver,
-    plonk::{Circuit, ConstraintSystem, Error},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
 };
-use halo2_gadgets::utilities::{lookup_range_check::LookupRangeCheckConfig, UtilitiesInstructions};
+use halo2_gadgets::utilities::lookup_range_check::LookupRangeCheckConfig;
 use halo2_gadgets::ecc::*;
 use halo2_gadgets::sinsemilla::chip::{SinsemillaChip, SinsemillaConfig};
 use lazy_static::lazy_static;
 use pasta_curves::pallas;
 use halo2_gadgets::sinsemilla::{HashDomains, CommitDomains};
 use halo2_gadgets::sinsemilla;
+use myp::{Secret, ThresholdError};
+use rand::{CryptoRng, RngCore};
+use crate::key_generate::lagrange_coefficient;
+use crate::nonce::HedgedNonce;
+use crate::curve::ThresholdCurve;
 use std::collections::hash_map::DefaultHasher;
 use halo2_gadgets::ecc::{
     chip::{
@@ -180,11 +185,52 @@ impl CommitDomains<pallas::Affine, TestFixedBases, TestHashDomain> for TestCommi
     }
 }
 
+//e = low + high with low = e >> 1 and high = low + (e & 1), both below 2^254 < p so each is a base
+//field element. the verifier compute them from e and give them as the public input of mul_scalar
+pub(crate) fn split_scalar(e: pallas::Scalar) -> Option<[pallas::Base; 2]> {
+    let mut low = e.to_repr();
+    let bit = low[0] & 1;
+    for i in 0..32 {
+        low[i] = (low[i] >> 1) | low.get(i + 1).map_or(0, |next| next << 7);
+    }
+    let low = Option::<pallas::Base>::from(pallas::Base::from_repr(low))?;
+    Some([low, low + pallas::Base::from(bit as u64)])
+}
+
+//e * point for any pallas scalar e. the circuit field is the base field p < q, so an e >= p
+//can't be loaded as it is. the two halves of split_scalar(e) are copied from the instance column at
+//row and row + 1, so the prover can't pick them, and each goes through ScalarVar::from_base whose
+//running sum decomposition range check it
+pub(crate) fn mul_scalar<F: FixedPoints<pallas::Affine>>(
+    chip : &EccChip<F>,
+    mut layouter : impl Layouter<pallas::Base>,
+    point : &NonIdentityPoint<pallas::Affine, EccChip<F>>,
+    instance : Column<Instance>,
+    row : usize,
+) -> Result<Point<pallas::Affine, EccChip<F>>, Error>
+where
+    F::Base : FixedPoint<pallas::Affine, FixedScalarKind = BaseFieldElem>,
+    F::FullScalar : FixedPoint<pallas::Affine, FixedScalarKind = FullScalar>,
+    F::ShortScalar : FixedPoint<pallas::Affine, FixedScalarKind = ShortScalar>,
+{
+    let column = chip.config().advices[0];
+    let mut part = Vec::new();
+    for (i, name) in ["low half of e", "high half of e"].into_iter().enumerate() {
+        let base = layouter.assign_region(|| name, |mut region| {
+            region.assign_advice_from_instance(|| name, instance, row + i, column, 0)
+        })?;
+        let scalar = ScalarVar::from_base(chip.clone(), layouter.namespace(|| name), &base)?;
+        let (product, _) = NonIdentityPoint::mul(point, layouter.namespace(|| name), scalar)?;
+        part.push(product);
+    }
+    part[0].add(layouter.namespace(|| "low * point + high * point"), &part[1])
+}
+
+//the public input is split_scalar(e)
 #[derive(Default)]
 struct Eddsa {
     commitment : pallas::Affine,
     pub_key : pallas::Affine,
-    e : pallas::Scalar,
     s : pallas::Scalar,
 }
 
@@ -193,6 +239,7 @@ impl Circuit<pallas::Base> for Eddsa {
     type Config = (
         EccConfig<TestFixedBases>,
         SinsemillaConfig<TestHashDomain, TestCommitDomain, TestFixedBases>,
+        Column<Instance>,
     );
     type FloorPlanner = SimpleFloorPlanner;
 
@@ -244,7 +291,9 @@ impl Circuit<pallas::Base> for Eddsa {
             lookup,
             range_check,
         );
-        (ecc_config, configs)
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (ecc_config, configs, instance)
     }
 
     fn synthesize(
@@ -253,7 +302,6 @@ impl Circuit<pallas::Base> for Eddsa {
         mut layouter: impl Layouter<pallas::Base>,
     ) -> Result<(), Error> {
         let chip = EccChip::construct(config.0.clone());
-        let column  = chip.config().advices[0];     
         SinsemillaChip::<TestHashDomain, TestCommitDomain, TestFixedBases>::load(
             config.1.clone(),
             &mut layouter,
@@ -265,23 +313,12 @@ impl Circuit<pallas::Base> for Eddsa {
             Value::known(self.pub_key),
         )?;
 
-        let fp = pallas::Base::from_repr(self.e.to_repr()).unwrap();
-        let base = chip.load_private(
-            layouter.namespace(|| "e"), 
-            column, 
-            Value::known(fp),
-        )?;
-
-        let scalar = ScalarVar::from_base(
-            chip.clone(), 
-            layouter.namespace(|| "e as scalar"), 
-            &base,
-        )?;
-
-        let (epub,_) = NonIdentityPoint::mul(
-            &pub_key, 
-            layouter.namespace(|| "e * pub key"), 
-            scalar,
+        let epub = mul_scalar(
+            &chip,
+            layouter.namespace(|| "e * pub key"),
+            &pub_key,
+            config.2,
+            0,
         )?;
 
         let p3 = Point::new(
@@ -435,11 +472,11 @@ impl Circuit<pallas::Base> for VerifyKey {
     }
 }
 
+//the public input is split_scalar(e)
 #[derive(Default)]
 struct MyCircuit {
     commitment : [pallas::Affine;3],
     pub_key : [pallas::Affine;3],
-    e : pallas::Scalar,
     s : [pallas::Scalar;3],
 }
 
@@ -448,6 +485,7 @@ impl Circuit<pallas::Base> for MyCircuit {
     type Config = (
         EccConfig<TestFixedBases>,
         SinsemillaConfig<TestHashDomain, TestCommitDomain, TestFixedBases>,
+        Column<Instance>,
     );
     type FloorPlanner = SimpleFloorPlanner;
 
@@ -499,7 +537,9 @@ impl Circuit<pallas::Base> for MyCircuit {
             lookup,
             range_check,
         );
-        (ecc_config, configs)
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (ecc_config, configs, instance)
     }
 
     fn synthesize(
@@ -508,7 +548,6 @@ impl Circuit<pallas::Base> for MyCircuit {
         mut layouter: impl Layouter<pallas::Base>,
     ) -> Result<(), Error> {
         let chip = EccChip::construct(config.0.clone());
-        let column  = chip.config().advices[0];     
         SinsemillaChip::<TestHashDomain, TestCommitDomain, TestFixedBases>::load(
             config.1.clone(),
             &mut layouter,
@@ -548,23 +587,12 @@ impl Circuit<pallas::Base> for MyCircuit {
             &pub_key2, 
         )?;
 
-        let fp = pallas::Base::from_repr(self.e.to_repr()).unwrap();
-        let base = chip.load_private(
-            layouter.namespace(|| "e"), 
-            column, 
-            Value::known(fp),
-        )?;
-
-        let scalar = ScalarVar::from_base(
-            chip.clone(), 
-            layouter.namespace(|| "e as scalar"), 
-            &base,
-        )?;
-
-        let (epub,_) = NonIdentityPoint::mul(
-            &pub_key, 
-            layouter.namespace(|| "e * pub key"), 
-            scalar,
+        let epub = mul_scalar(
+            &chip,
+            layouter.namespace(|| "e * pub key"),
+            &pub_key,
+            config.2,
+            0,
         )?;
 
         let input_commitment0 = Value::known(self.commitment[0]);
@@ -631,25 +659,94 @@ impl Circuit<pallas::Base> for MyCircuit {
     
 }
 
-pub fn pre_compute(
-    pri : pallas::Scalar,
-    input_r : pallas::Scalar,
-    message : u128,
-) -> (pallas::Affine, pallas::Scalar){
-    let affine_generator = pallas::Affine::generator();
-    let r = pallas::Affine::mul(affine_generator, input_r).to_affine();
+//e, the hash_num every player mul to his key share
+pub fn message_challenge<F: PrimeField>(message : u128) -> F{
     let mut hasher = DefaultHasher::new();
     hasher.write_u128(message);
     let hash_value = hasher.finish() as u128;
-    let temp = pallas::Scalar::mul(&pri, &pallas::Scalar::from_u128(hash_value));
-    let s = pallas::Scalar::add(&input_r, &temp);
+    F::from_u128(hash_value)
+}
+
+//the schnorr below run on any ThresholdCurve, the circuits above only on pallas
+//the nonce is taken by value, so it is wiped as soon as s is made and can't be used for a second message
+pub fn pre_compute<C: ThresholdCurve>(
+    pri : &Secret<C::Scalar>,
+    input_r : Secret<C::Scalar>,
+    message : u128,
+) -> (C, C::Scalar){
+    let r = (C::generator() * input_r.expose()).to_affine();
+    let s = *input_r.expose() + *pri.expose() * message_challenge::<C::Scalar>(message);
     (r, s)
 }
 
+//pre_compute with a nonce hedged from the key share, the message and the rng,
+//so a stuck rng can't give one nonce to two messages
+pub fn hedged_pre_compute<C: ThresholdCurve>(
+    rng : &mut (impl CryptoRng + RngCore),
+    pri : &Secret<C::Scalar>,
+    message : u128,
+) -> (C, C::Scalar){
+    let input_r = HedgedNonce::new(rng, "eddsa-nonce", pri.expose().to_repr().as_ref(), &message.to_be_bytes()).scalar_on::<C>();
+    pre_compute::<C>(pri, input_r, message)
+}
+
+//verification_shares[i] = p(signer_set[i]) * G, the pub key share from CollectOutputKeyShare,
+//a partial from signer index must satisfy s_i * G == R_i + e * lambda_i * X_i
+pub fn verify_partial<C: ThresholdCurve>(
+    index : u128,
+    signer_set : &[u128],
+    commitment : C,
+    s : C::Scalar,
+    verification_shares : &[C],
+    challenge : C::Scalar,
+) -> Result<(), ThresholdError>{
+    if signer_set.len() != verification_shares.len() {
+        return Err(ThresholdError::LengthMismatch {
+            expected: signer_set.len(),
+            found: verification_shares.len(),
+        });
+    }
+    let party = signer_set.iter().position(|i| *i == index)
+        .ok_or(ThresholdError::OutOfRange("index"))?;
+    let lambda: C::Scalar = lagrange_coefficient(index, signer_set)?;
+    let weight = lambda * challenge;
+
+    let left = C::generator() * s;
+    let right = verification_shares[party] * weight + commitment;
+    if left != right {
+        return Err(ThresholdError::VerificationFailed { party });
+    }
+    Ok(())
+}
+
+//the aggregator check every partial before it add them, a bad one is blamed by its place in signer_set
+pub fn combine_partial<C: ThresholdCurve>(
+    signer_set : &[u128],
+    commitment : &[C],
+    s : &[C::Scalar],
+    verification_shares : &[C],
+    challenge : C::Scalar,
+) -> Result<(C, C::Scalar), ThresholdError>{
+    for found in [commitment.len(), s.len()] {
+        if found != signer_set.len() {
+            return Err(ThresholdError::LengthMismatch { expected: signer_set.len(), found });
+        }
+    }
+    let mut commitment_sum = C::Curve::identity();
+    let mut s_sum = C::Scalar::ZERO;
+    for ((index, r_i), s_i) in signer_set.iter().zip(commitment).zip(s) {
+        verify_partial(*index, signer_set, *r_i, *s_i, verification_shares, challenge)?;
+        commitment_sum += *r_i;
+        s_sum += s_i;
+    }
+    Ok((commitment_sum.to_affine(), s_sum))
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
-    use crate::{generate_random_u128_in_range,Input,CalculatePubKey,CalculatePriKey,CollectOutputKeyShare};
+    use crate::generate_random_u128_in_range;
+    use crate::key_generate::{Input,CalculatePubKey,CalculatePriKey,CollectOutputKeyShare};
     #[test]
 fn eddsa_test() {
     //there are 5 player join teh key generation
@@ -660,44 +757,44 @@ fn eddsa_test() {
     let player5 = generate_random_u128_in_range(1, std::u64::MAX as u128);
 
     let input1 = Input{
-        key_share : player1,
-        rand_num : 379278,
+        key_share : Secret::new(player1),
+        rand_num : Secret::new(379278),
         output_max : 5,
         output_min : 3,
     };
-    let result1 = input1.output_key_share();
+    let result1 = input1.output_key_share().unwrap();
 
     let input2 = Input{
-        key_share : player2,
-        rand_num : 4812738974,
+        key_share : Secret::new(player2),
+        rand_num : Secret::new(4812738974),
         output_max : 5,
         output_min : 3,
     };
-    let result2 = input2.output_key_share();
+    let result2 = input2.output_key_share().unwrap();
 
     let input3 = Input{
-        key_share : player3,
-        rand_num : 43217,
+        key_share : Secret::new(player3),
+        rand_num : Secret::new(43217),
         output_max : 5,
         output_min : 3,
     };
-    let result3 = input3.output_key_share();
+    let result3 = input3.output_key_share().unwrap();
 
     let input4 = Input{
-        key_share : player4,
-        rand_num : 12343432,
+        key_share : Secret::new(player4),
+        rand_num : Secret::new(12343432),
         output_max : 5,
         output_min : 3,
     };
-    let result4 = input4.output_key_share();
+    let result4 = input4.output_key_share().unwrap();
 
     let input5 = Input{
-        key_share : player5,
-        rand_num : 1234546,
+        key_share : Secret::new(player5),
+        rand_num : Secret::new(1234546),
         output_max : 5,
         output_min : 3,
     };
-    let result5 = input5.output_key_share();
+    let result5 = input5.output_key_share().unwrap();
 
     let mut user_vec = Vec::new();
     user_vec.extend(result1);
@@ -707,43 +804,43 @@ fn eddsa_test() {
     user_vec.extend(result5);
 
     let user1 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
+        key_share : Secret::new(user_vec.clone()),
         member : 5,
         self_num : 1,
     };
-    let (user1_prikey_share_a, user1_pubkey_share) = user1.collect();
+    let (user1_prikey_share_a, user1_pubkey_share) = user1.collect().unwrap();
     let calculate_user1_prikey_share = CalculatePriKey {
         self_coefficient : 1,
         coefficient : [2,3],
         pri_key : user1_prikey_share_a,
     };
-    let user1_prikey_share = calculate_user1_prikey_share.calculate();
+    let user1_prikey_share = calculate_user1_prikey_share.calculate().unwrap();
     
     let user2 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
+        key_share : Secret::new(user_vec.clone()),
         member : 5,
         self_num : 2,
     };
-    let (user2_prikey_share_a, user2_pubkey_share) = user2.collect();
+    let (user2_prikey_share_a, user2_pubkey_share) = user2.collect().unwrap();
     let calculate_user2_prikey_share = CalculatePriKey {
         self_coefficient : 2,
         coefficient : [1,3],
         pri_key : user2_prikey_share_a,
     };
-    let user2_prikey_share = calculate_user2_prikey_share.calculate();
+    let user2_prikey_share = calculate_user2_prikey_share.calculate().unwrap();
 
     let user3 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
+        key_share : Secret::new(user_vec.clone()),
         member : 5,
         self_num : 3,
     };
-    let (user3_prikey_share_a, user3_pubkey_share) = user3.collect();
+    let (user3_prikey_share_a, user3_pubkey_share) = user3.collect().unwrap();
     let calculate_user3_prikey_share = CalculatePriKey {
         self_coefficient : 3,
         coefficient : [1,2],
         pri_key : user3_prikey_share_a,
     };
-    let user3_prikey_share = calculate_user3_prikey_share.calculate();
+    let user3_prikey_share = calculate_user3_prikey_share.calculate().unwrap();
 
     let pub_key_calaulate = CalculatePubKey {
         degree : 3,
@@ -751,52 +848,77 @@ fn eddsa_test() {
         pub_key : [user1_pubkey_share, user2_pubkey_share, user3_pubkey_share].to_vec(),
     };
     //they make the public key
-    let pub_key = pub_key_calaulate.calculate();
+    let pub_key = pub_key_calaulate.calculate().unwrap();
     //message is the thing they want to vote
     let message = generate_random_u128_in_range(1, std::u64::MAX as u128);
     let generator = pallas::Affine::generator();
     
-    let (r1, s1) = pre_compute(
-        user1_prikey_share, 
-        pallas::Scalar::random(rand::rngs::OsRng),
+    let (r1, s1) = hedged_pre_compute::<pallas::Affine>(
+        &mut rand::rngs::OsRng,
+        &user1_prikey_share,
         message,
     );
-    let user1_pubkey = generator.mul(user1_prikey_share).to_affine();
+    let user1_pubkey = generator.mul(user1_prikey_share.expose()).to_affine();
 
-    let (r2, s2) = pre_compute(
-        user2_prikey_share, 
-        pallas::Scalar::random(rand::rngs::OsRng),
+    let (r2, s2) = hedged_pre_compute::<pallas::Affine>(
+        &mut rand::rngs::OsRng,
+        &user2_prikey_share,
         message,
     );
-    let user2_pubkey = generator.mul(user2_prikey_share).to_affine();
+    let user2_pubkey = generator.mul(user2_prikey_share.expose()).to_affine();
 
-    let (r3, s3) = pre_compute(
-        user3_prikey_share, 
-        pallas::Scalar::random(rand::rngs::OsRng),
+    let (r3, s3) = pre_compute::<pallas::Affine>(
+        &user3_prikey_share,
+        Secret::new(pallas::Scalar::random(rand::rngs::OsRng)),
         message,
     );
-    let user3_pubkey = generator.mul(user3_prikey_share).to_affine();
+    let user3_pubkey = generator.mul(user3_prikey_share.expose()).to_affine();
     
-    let mut hasher = DefaultHasher::new();
-    hasher.write_u128(message);
-    let challange = hasher.finish() as u128;
+    let challange = message_challenge(message);
     //this is just for make sure the user1_prikey_share add together is as our expect 
     //it doesn't exist in the real project 
     let check1 = player1 + player2 + player3 + player4 + player5;
-    let check2 = user1_prikey_share + user2_prikey_share + user3_prikey_share;
+    let check2 = user1_prikey_share.expose() + user2_prikey_share.expose() + user3_prikey_share.expose();
     let check3 = pallas::Scalar::from_u128(check1);
     let pri_key_equal = pallas::Scalar::eq(&check2, &check3);
     assert_eq!(pri_key_equal,true);
 
+    //the aggregator check every partial with the unweighted pub key share before combine them
+    let signer_set = [1, 2, 3];
+    let verification_shares = [user1_pubkey_share, user2_pubkey_share, user3_pubkey_share];
+    let (commitment, s) = combine_partial(
+        &signer_set,
+        &[r1, r2, r3],
+        &[s1, s2, s3],
+        &verification_shares,
+        challange,
+    ).unwrap();
+    let left = generator.mul(s).to_affine();
+    let right = pallas::Affine::add(commitment, pub_key.mul(challange)).to_affine();
+    assert_eq!(left, right);
+
+    //user2 send a wrong s, he is blamed before anything is combined
+    let bad_s2 = pallas::Scalar::add(&s2, &pallas::Scalar::one());
+    assert_eq!(
+        verify_partial(2, &signer_set, r2, bad_s2, &verification_shares, challange),
+        Err(ThresholdError::VerificationFailed { party: 1 }),
+    );
+    assert_eq!(
+        combine_partial(&signer_set, &[r1, r2, r3], &[s1, bad_s2, s3], &verification_shares, challange),
+        Err(ThresholdError::VerificationFailed { party: 1 }),
+    );
+    //user1_pubkey already has lambda in it, as a verification share lambda would be used twice
+    assert!(verify_partial(1, &signer_set, r1, s1, &[user1_pubkey, user2_pubkey, user3_pubkey], challange).is_err());
+
     //here we use eddsa to verify singal signature from user1 to user3
+    let public = split_scalar(challange).unwrap().to_vec();
     let k1 = 13;
     let circuit1 = Eddsa{
         s : s1,
         pub_key : user1_pubkey,
         commitment : r1,
-        e : pallas::Scalar::from_u128(challange),
     };
-    let prover1 = MockProver::run(k1, &circuit1, vec![]).unwrap();
+    let prover1 = MockProver::run(k1, &circuit1, vec![public.clone()]).unwrap();
     assert_eq!(prover1.verify(), Ok(()),"User1 had been hack");
 
     let k2 = 13;
@@ -804,9 +926,8 @@ fn eddsa_test() {
         s : s2,
         pub_key : user2_pubkey,
         commitment : r2,
-        e : pallas::Scalar::from_u128(challange),
     };
-    let prover2 = MockProver::run(k2, &circuit2, vec![]).unwrap();
+    let prover2 = MockProver::run(k2, &circuit2, vec![public.clone()]).unwrap();
     assert_eq!(prover2.verify(), Ok(()),"User2 had been hack");
 
     let k3 = 13;
@@ -814,9 +935,8 @@ fn eddsa_test() {
         s : s3,
         pub_key : user3_pubkey,
         commitment : r3,
-        e : pallas::Scalar::from_u128(challange),
     };
-    let prover3 = MockProver::run(k3, &circuit3, vec![]).unwrap();
+    let prover3 = MockProver::run(k3, &circuit3, vec![public.clone()]).unwrap();
     assert_eq!(prover3.verify(), Ok(()),"User3 had been hack");
 
     let k4 = 13;
@@ -832,9 +952,39 @@ fn eddsa_test() {
         s : [s1,s2,s3],
         pub_key : [user1_pubkey, user2_pubkey, user3_pubkey],
         commitment : [r1,r2,r3],
-        e : pallas::Scalar::from_u128(challange),
     };
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
+    let prover = MockProver::run(k, &circuit, vec![public.clone()]).unwrap();
     assert_eq!(prover.verify(), Ok(()))
 }
+
+#[test]
+fn big_challenge_test() {
+    //q - 1 is above the base field modulus p, it used to fail from_repr before it reached the circuit
+    let e = -pallas::Scalar::one();
+    assert!(bool::from(pallas::Base::from_repr(e.to_repr()).is_none()));
+    let mut rng = rand::thread_rng();
+    let x = pallas::Scalar::random(&mut rng);
+    let k = pallas::Scalar::random(&mut rng);
+    let generator = pallas::Affine::generator();
+    let circuit = Eddsa{
+        s : k + e * x,
+        pub_key : generator.mul(x).to_affine(),
+        commitment : generator.mul(k).to_affine(),
+    };
+    let public = split_scalar(e).unwrap().to_vec();
+    let prover = MockProver::run(13, &circuit, vec![public.clone()]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+
+    //a split that doesn't add up to e is rejected, the halves are public so the prover can't swap them
+    let [low, high] = split_scalar(e).unwrap();
+    let prover = MockProver::run(13, &circuit, vec![vec![low, high + pallas::Base::one()]]).unwrap();
+    assert!(prover.verify().is_err());
+    let prover = MockProver::run(13, &circuit, vec![vec![low - pallas::Base::one(), high]]).unwrap();
+    assert!(prover.verify().is_err());
+
+    //and a wrong s is still caught with such an e
+    let circuit = Eddsa{ s : circuit.s + pallas::Scalar::one(), ..circuit };
+    let prover = MockProver::run(13, &circuit, vec![public]).unwrap();
+    assert!(prover.verify().is_err());
+}
 }
\ No newline at end of file
@@ -0,0 +1,237 @@
+use ff::Field;
+use pasta_curves::arithmetic::{Coordinates, CurveAffine};
+use pasta_curves::group::{ff::PrimeField, Curve, Group};
+use pasta_curves::pallas;
+use sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+use myp::ThresholdError;
+use crate::curve::EcdsaCurve;
+use crate::make_signature::SignatureShare;
+
+//if the ecdsa signature s = sum(s_i) fails, every party move k_i and sigma_i onto the commitment
+//R = k^-1 * G, so sum(k_i * R) == G, sum(sigma_i * R) == pub_key and an honest share has
+//s_i * R == m * (k_i * R) + r * (sigma_i * R), the party whose share doesn't fit is the one to blame.
+//k_i * G and sigma_i * G are the ones the party broadcast in round 3, before any s_i was out, so it
+//can't fit a new k_i or sigma_i to a wrong s_i afterward
+
+//chaum-pedersen, log_g(p) == log_h(q) without showing the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DleqProof<P: EcdsaCurve = pallas::Affine> {
+    pub a1 : P,
+    pub a2 : P,
+    pub z : P::Scalar,
+}
+
+fn challenge<P: EcdsaCurve>(points: &[P]) -> P::Scalar {
+    let mut hasher = Sha256::new();
+    hasher.update("identifiable-abort".as_bytes());
+    hasher.update(P::NAME.as_bytes());
+    for i in points {
+        hasher.update(i.to_bytes());
+    }
+    let hash: [u8; 32] = hasher.finalize().into();
+    let mut e = [0u8; 16];
+    e.copy_from_slice(&hash[..16]);
+    P::Scalar::from_u128(u128::from_be_bytes(e))
+}
+
+impl<P: EcdsaCurve> DleqProof<P> {
+    fn prove(rng: &mut (impl CryptoRng + RngCore), secret: &P::Scalar, g: P, h: P) -> DleqProof<P> {
+        let p = (g * secret).to_affine();
+        let q = (h * secret).to_affine();
+        let a = P::Scalar::random(&mut *rng);
+        let a1 = (g * a).to_affine();
+        let a2 = (h * a).to_affine();
+        let e = challenge(&[g, h, p, q, a1, a2]);
+        DleqProof {
+            a1,
+            a2,
+            z : a + e * secret,
+        }
+    }
+
+    //z * g == a1 + e * p and z * h == a2 + e * q
+    pub fn verify(&self, g: P, h: P, p: P, q: P) -> bool {
+        let e = challenge(&[g, h, p, q, self.a1, self.a2]);
+        (g * self.z).to_affine() == (p * e + self.a1).to_affine()
+            && (h * self.z).to_affine() == (q * e + self.a2).to_affine()
+    }
+}
+
+//what party i publish in the abort phase, k_commitment = k_i * G and sigma_commitment = sigma_i * G
+//must be the ones of its round 3 message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShareCheck<P: EcdsaCurve = pallas::Affine> {
+    pub k_commitment : P,
+    pub sigma_commitment : P,
+    pub k_point : P,
+    pub sigma_point : P,
+    pub k_proof : DleqProof<P>,
+    pub sigma_proof : DleqProof<P>,
+}
+
+impl<P: EcdsaCurve> ShareCheck<P> {
+    pub fn verify(&self, commitment: P) -> bool {
+        let generator = P::generator();
+        self.k_proof.verify(generator, commitment, self.k_commitment, self.k_point)
+            && self.sigma_proof.verify(generator, commitment, self.sigma_commitment, self.sigma_point)
+    }
+}
+
+impl<F: PrimeField> SignatureShare<F> {
+    pub fn share_check<P: EcdsaCurve<Scalar = F>>(&self, rng: &mut (impl CryptoRng + RngCore), commitment: P) -> ShareCheck<P> {
+        let generator = P::generator();
+        ShareCheck {
+            k_commitment : (generator * self.k).to_affine(),
+            sigma_commitment : (generator * self.sigma).to_affine(),
+            k_point : (commitment * self.k).to_affine(),
+            sigma_point : (commitment * self.sigma).to_affine(),
+            k_proof : DleqProof::prove(rng, &self.k, generator, commitment),
+            sigma_proof : DleqProof::prove(rng, &self.sigma, generator, commitment),
+        }
+    }
+}
+
+//r is the x coordinate of the commitment, as in MyCircuit
+pub fn commitment_r(commitment: pallas::Affine) -> Result<pallas::Scalar, ThresholdError> {
+    let coordinates: Coordinates<pallas::Affine> = Option::from(commitment.coordinates())
+        .ok_or(ThresholdError::InvalidPoint)?;
+    Option::from(pallas::Scalar::from_repr(coordinates.x().to_repr()))
+        .ok_or(ThresholdError::OutOfRange("r"))
+}
+
+//empty if s = sum(s_i) is a valid signature on the digest, else the index of every party with a
+//bad share. share_commitment is (k_i * G, sigma_i * G) of every party's round 3 message, a party
+//whose share check doesn't open them or whose share doesn't fit is a cheater, every party is checked
+pub fn identify_abort<P: EcdsaCurve>(
+    digest : &[u8; 32],
+    commitment : P,
+    pub_key : P,
+    share_commitment : &[(P, P)],
+    share : &[P::Scalar],
+    check : &[ShareCheck<P>],
+) -> Result<Vec<usize>, ThresholdError> {
+    for len in [check.len(), share_commitment.len()] {
+        if share.len() != len {
+            return Err(ThresholdError::LengthMismatch { expected: share.len(), found: len });
+        }
+    }
+    let (r, _) = commitment.ecdsa_r()?;
+    let m = P::digest_scalar(digest);
+
+    //s * R == m * G + r * pub_key
+    let s: P::Scalar = share.iter().sum();
+    if (commitment * s).to_affine() == (P::generator() * m + pub_key * r).to_affine() {
+        return Ok(Vec::new());
+    }
+
+    let mut cheater = Vec::new();
+    for (party, ((s_i, i), committed)) in share.iter().zip(check).zip(share_commitment).enumerate() {
+        if (i.k_commitment, i.sigma_commitment) != *committed || !i.verify(commitment) {
+            cheater.push(party);
+            continue;
+        }
+        if (commitment * s_i).to_affine() != (i.k_point * m + i.sigma_point * r).to_affine() {
+            cheater.push(party);
+        }
+    }
+    if !cheater.is_empty() {
+        return Ok(cheater);
+    }
+    //no share is off on its own, so k_i * R or sigma_i * R don't add up to G and pub_key. then a
+    //round 3 commitment isn't the k_i or sigma_i that party put into the mta, only the replay of
+    //CommitmentAudit tell whose
+    let k_sum = check.iter().fold(P::Curve::identity(), |acc, i| acc + i.k_point);
+    let sigma_sum = check.iter().fold(P::Curve::identity(), |acc, i| acc + i.sigma_point);
+    if k_sum.to_affine() != P::generator() || sigma_sum.to_affine() != pub_key {
+        return Err(ThresholdError::Unattributable);
+    }
+    //both sums right and every share fit give s * R == m * G + r * pub_key, s would have verified
+    Err(ThresholdError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::Mul;
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use rand::Rng;
+    use num_bigint::BigUint;
+    use crate::make_signature::MakeSignature;
+    use crate::paillier_modulus::test_paillier_key;
+    use myp::Secret;
+    #[test]
+    fn identify_abort_test(){
+        let (p, q): (Vec<BigUint>, Vec<BigUint>) = (0..3).map(test_paillier_key).unzip();
+        let n = p.iter().zip(&q).map(|(p, q)| p * q).collect();
+        let k: Vec<pallas::Scalar> = (0..3).map(|_| pallas::Scalar::random(rand::thread_rng())).collect();
+        let w: Vec<pallas::Scalar> = (0..3).map(|_| pallas::Scalar::random(rand::thread_rng())).collect();
+
+        //R = k^-1 * G as make_commitment gives it
+        let generator = pallas::Affine::generator();
+        let k_sum: pallas::Scalar = k.iter().sum();
+        let k_inv: pallas::Scalar = Option::from(k_sum.invert()).unwrap();
+        let commitment = pallas::Affine::mul(generator, k_inv).to_affine();
+        let pub_key = pallas::Affine::mul(generator, w.iter().sum::<pallas::Scalar>()).to_affine();
+        let r = match commitment_r(commitment) {
+            Ok(r) => r,
+            //x of R doesn't fit in the scalar field, too rare to care in a test
+            Err(_) => return,
+        };
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let m = pallas::Affine::digest_scalar(&digest);
+
+        let instence = MakeSignature{
+            selfk : Secret::new(k),
+            mta_pub_n : n,
+            selfw : Secret::new(w),
+            pri_p : Secret::new(p),
+            pri_q : Secret::new(q),
+            message : m,
+            r,
+        };
+        let mut rng = rand::thread_rng();
+        let result = instence.make_signature_share(&mut rng).unwrap();
+        let mut share: Vec<pallas::Scalar> = result.iter().map(|i| i.s).collect();
+        let check: Vec<ShareCheck> = result.iter().map(|i| i.share_check(&mut rng, commitment)).collect();
+        //k_i * G and sigma_i * G as round 3 broadcast them
+        let mut round3: Vec<(pallas::Affine, pallas::Affine)> = result.iter().map(|i| {
+            (pallas::Affine::mul(generator, i.k).to_affine(), pallas::Affine::mul(generator, i.sigma).to_affine())
+        }).collect();
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &share, &check), Ok(Vec::new()));
+        assert!(identify_abort(&digest, commitment, pub_key, &round3[..2], &share, &check).is_err());
+
+        //party 1 send a wrong s_1
+        share[1] = pallas::Scalar::add(&share[1], &pallas::Scalar::one());
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &share, &check), Ok([1].to_vec()));
+
+        //party 1 also lie about k_1 * R, the proof doesn't match its k_1 * G
+        let mut bad_check = check.clone();
+        bad_check[1].k_point = generator;
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &share, &bad_check), Ok([1].to_vec()));
+
+        //party 1 pick a sigma that fit the wrong s_1, it isn't the sigma_1 of round 3
+        let r_inv: pallas::Scalar = Option::from(r.invert()).unwrap();
+        let fake_sigma = pallas::Scalar::mul(
+            &pallas::Scalar::sub(&share[1], &pallas::Scalar::mul(&result[1].k, &m)),
+            &r_inv,
+        );
+        let fake = SignatureShare {
+            s : share[1],
+            k : result[1].k,
+            sigma : fake_sigma,
+        };
+        bad_check[1] = fake.share_check(&mut rng, commitment);
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &share, &bad_check), Ok([1].to_vec()));
+
+        //party 2 send a wrong s_2 too, both are found and not only the first
+        let mut two = share.clone();
+        two[2] = pallas::Scalar::add(&two[2], &pallas::Scalar::one());
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &two, &bad_check), Ok([1, 2].to_vec()));
+
+        //had party 1 broadcast that sigma in round 3 already, every share fit but the sigma_i * R no
+        //longer add up to pub_key, and only the mta replay tell who
+        round3[1].1 = bad_check[1].sigma_commitment;
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &round3, &share, &bad_check), Err(ThresholdError::Unattributable));
+    }
+}
@@ -0,0 +1,106 @@
+use std::ops::{Add, Mul};
+use ff::Field;
+use pasta_curves::group::{cofactor::CofactorCurveAffine, ff::PrimeField, Curve, GroupEncoding};
+use pasta_curves::pallas;
+use sha2::{Digest, Sha256};
+use rand::{CryptoRng, RngCore};
+use myp::Secret;
+use crate::nonce::HedgedNonce;
+
+//a party's long term key, the other parties know the public point before any session and
+//use it to check who is on the other end of a connection
+pub struct IdentityKey {
+    secret : Secret<pallas::Scalar>,
+}
+
+//schnorr, s * G == commitment + e * public
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentitySignature {
+    pub commitment : pallas::Affine,
+    pub s : pallas::Scalar,
+}
+
+fn challenge(label: &str, commitment: pallas::Affine, public: pallas::Affine, data: &[u8]) -> pallas::Scalar {
+    let mut hasher = Sha256::new();
+    hasher.update(label.as_bytes());
+    hasher.update(commitment.to_bytes());
+    hasher.update(public.to_bytes());
+    hasher.update(data);
+    let hash: [u8; 32] = hasher.finalize().into();
+    let mut e = [0u8; 16];
+    e.copy_from_slice(&hash[..16]);
+    pallas::Scalar::from_u128(u128::from_be_bytes(e))
+}
+
+impl IdentityKey {
+    pub fn generate(rng: &mut (impl CryptoRng + RngCore)) -> IdentityKey {
+        IdentityKey {
+            secret : Secret::new(pallas::Scalar::random(rng)),
+        }
+    }
+
+    //None for zero, it has no public point
+    pub fn from_secret(secret: pallas::Scalar) -> Option<IdentityKey> {
+        if secret == pallas::Scalar::zero() {
+            return None;
+        }
+        Some(IdentityKey { secret : Secret::new(secret) })
+    }
+
+    pub fn secret(&self) -> pallas::Scalar {
+        *self.secret.expose()
+    }
+
+    pub fn public(&self) -> pallas::Affine {
+        pallas::Affine::mul(pallas::Affine::generator(), self.secret.expose()).to_affine()
+    }
+
+    //label keep a signature for one use from being replayed for another,
+    //the nonce is hedged so a stuck rng can't sign two data with the same one
+    pub fn sign(&self, rng: &mut (impl CryptoRng + RngCore), label: &str, data: &[u8]) -> IdentitySignature {
+        let a = HedgedNonce::new(rng, label, &self.secret.expose().to_repr(), data).scalar();
+        let commitment = pallas::Affine::mul(pallas::Affine::generator(), a.expose()).to_affine();
+        let e = challenge(label, commitment, self.public(), data);
+        IdentitySignature {
+            commitment,
+            s : pallas::Scalar::add(a.expose(), &pallas::Scalar::mul(&e, self.secret.expose())),
+        }
+    }
+}
+
+impl IdentitySignature {
+    pub fn verify(&self, public: pallas::Affine, label: &str, data: &[u8]) -> bool {
+        if bool::from(public.is_identity()) {
+            return false;
+        }
+        let e = challenge(label, self.commitment, public, data);
+        let left = pallas::Affine::mul(pallas::Affine::generator(), self.s).to_affine();
+        let right = pallas::Affine::add(self.commitment, pallas::Affine::mul(public, e)).to_affine();
+        left == right
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    #[test]
+    fn identity_test(){
+        let mut rng = rand::thread_rng();
+        let key = IdentityKey::generate(&mut rng);
+        let other = IdentityKey::generate(&mut rng);
+        let signature = key.sign(&mut rng, "identity-test", b"hello");
+        assert!(signature.verify(key.public(), "identity-test", b"hello"));
+        assert!(!signature.verify(key.public(), "identity-test", b"hellp"));
+        assert!(!signature.verify(key.public(), "other-label", b"hello"));
+        assert!(!signature.verify(other.public(), "identity-test", b"hello"));
+        assert!(IdentityKey::from_secret(pallas::Scalar::zero()).is_none());
+
+        //a seeded rng give the same key and signature again
+        use rand::{rngs::StdRng, SeedableRng};
+        let key = IdentityKey::generate(&mut StdRng::seed_from_u64(3));
+        assert_eq!(key.public(), IdentityKey::generate(&mut StdRng::seed_from_u64(3)).public());
+        assert_eq!(key.sign(&mut StdRng::seed_from_u64(4), "identity-test", b"hello"), key.sign(&mut StdRng::seed_from_u64(4), "identity-test", b"hello"));
+        //the same rng state on other data still give another nonce
+        assert_ne!(key.sign(&mut StdRng::seed_from_u64(4), "identity-test", b"hello").commitment, key.sign(&mut StdRng::seed_from_u64(4), "identity-test", b"hellp").commitment);
+    }
+}
@@ -0,0 +1,348 @@
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload as AeadPayload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use num_bigint::BigUint;
+use rand::{CryptoRng, Rng, RngCore};
+use pasta_curves::group::{Curve, Group};
+use pasta_curves::pallas;
+use myp::{Secret, ThresholdError};
+use zeroize::Zeroize;
+use crate::key_generate::lagrange_coefficient;
+use crate::curve::{EcdsaCurve, ThresholdCurve};
+use crate::message::{decode_point, decode_scalar, encode_point, encode_scalar, Encode};
+use crate::paillier_modulus::PAILLIER_MODULUS_BITS;
+use crate::party::PartySecret;
+
+//a party's key material between ceremonies, encrypted with a key derived from a passphrase
+//  "MYPKEY" | version u8 | argon2id m_cost u32 | t_cost u32 | p_cost u32 | salt 16 | nonce 12 | ciphertext
+//the header is the associated data, so changing the kdf params or the version break the tag
+//the plain text start with the name of the curve the share is on
+pub const KEY_FILE_VERSION: u8 = 3;
+const KEY_FILE_MAGIC: &[u8; 6] = b"MYPKEY";
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 6 + 1 + 12 + SALT_LEN + NONCE_LEN;
+//a file asking for more memory than this is refused before the kdf runs, in KiB
+const MAX_M_COST: u32 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    //KiB
+    pub m_cost : u32,
+    pub t_cost : u32,
+    pub p_cost : u32,
+}
+
+impl KdfParams {
+    //the owasp argon2id minimum, 19 MiB and two passes
+    pub fn recommended() -> KdfParams {
+        KdfParams {
+            m_cost : 19 * 1024,
+            t_cost : 2,
+            p_cost : 1,
+        }
+    }
+}
+
+//what keygen or reshare leave a party with, plus its paillier key for the signing mta
+pub struct KeyFile<P: ThresholdCurve = pallas::Affine> {
+    pub index : usize,
+    pub threshold : usize,
+    //p(index + 1)
+    pub share : Secret<P::Scalar>,
+    pub pub_key : P,
+    //p(j + 1) * G of every party j, its length is the number of parties
+    pub verification_share : Vec<P>,
+    pub pri_p : Secret<BigUint>,
+    pub pri_q : Secret<BigUint>,
+}
+
+//write to a temp file only the owner can read, sync it, rename it over the old one and sync the
+//directory so the rename itself survive a crash. the nonce store is written the same way
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), ThresholdError> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    if let Some(dir) = path.parent().filter(|i| !i.as_os_str().is_empty()) {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+//the nonce store is sealed with the same kdf and aead
+pub fn cipher(passphrase: &[u8], salt: &[u8], params: KdfParams) -> Result<ChaCha20Poly1305, ThresholdError> {
+    if params.m_cost > MAX_M_COST {
+        return Err(ThresholdError::OutOfRange("kdf m_cost"));
+    }
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|_| ThresholdError::OutOfRange("kdf params"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|_| ThresholdError::OutOfRange("kdf params"))?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    key.zeroize();
+    Ok(cipher)
+}
+
+impl<P: ThresholdCurve> KeyFile<P> {
+    pub fn parties(&self) -> usize {
+        self.verification_share.len()
+    }
+
+    pub fn mta_pub_n(&self) -> BigUint {
+        self.pri_p.expose() * self.pri_q.expose()
+    }
+
+    //the share match its verification share and the first threshold of them give the pub key
+    pub fn check(&self) -> Result<(), ThresholdError> {
+        let parties = self.parties();
+        if self.index >= parties {
+            return Err(ThresholdError::OutOfRange("index"));
+        }
+        if self.threshold == 0 || self.threshold > parties {
+            return Err(ThresholdError::OutOfRange("threshold"));
+        }
+        if self.mta_pub_n().bits() < PAILLIER_MODULUS_BITS || self.pri_p.expose().bits() < 2 || self.pri_q.expose().bits() < 2 {
+            return Err(ThresholdError::InvalidModulus(self.mta_pub_n()));
+        }
+        if (P::generator() * *self.share.expose()).to_affine() != self.verification_share[self.index] {
+            return Err(ThresholdError::VerificationFailed { party: self.index });
+        }
+        let signer_set: Vec<u128> = (1..=self.threshold as u128).collect();
+        let mut pub_key = P::Curve::identity();
+        for (i, point) in self.verification_share.iter().take(self.threshold).enumerate() {
+            let lambda: P::Scalar = lagrange_coefficient((i + 1) as u128, &signer_set)?;
+            pub_key += *point * lambda;
+        }
+        if pub_key.to_affine() != self.pub_key {
+            return Err(ThresholdError::Unattributable);
+        }
+        Ok(())
+    }
+
+    //lambda_i * share, this party's additive part of the key when signer_set (indices + 1) sign
+    pub fn signing_share(&self, signer_set: &[u128]) -> Result<P::Scalar, ThresholdError> {
+        if signer_set.len() < self.threshold {
+            return Err(ThresholdError::LengthMismatch { expected: self.threshold, found: signer_set.len() });
+        }
+        let lambda: P::Scalar = lagrange_coefficient((self.index + 1) as u128, signer_set)?;
+        Ok(lambda * *self.share.expose())
+    }
+
+    fn encode_plain(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        P::NAME.as_bytes().to_vec().encode(&mut out);
+        (self.index as u32).encode(&mut out);
+        (self.threshold as u32).encode(&mut out);
+        encode_scalar(self.share.expose(), &mut out);
+        encode_point(&self.pub_key, &mut out);
+        (self.verification_share.len() as u32).encode(&mut out);
+        for i in &self.verification_share {
+            encode_point(i, &mut out);
+        }
+        self.pri_p.expose().encode(&mut out);
+        self.pri_q.expose().encode(&mut out);
+        out
+    }
+
+    fn decode_plain(bytes: &[u8]) -> Result<KeyFile<P>, ThresholdError> {
+        let mut input = bytes;
+        if Vec::<u8>::decode(&mut input)? != P::NAME.as_bytes() {
+            return Err(ThresholdError::InvalidEncoding("key file is for another curve"));
+        }
+        let index = u32::decode(&mut input)? as usize;
+        let threshold = u32::decode(&mut input)? as usize;
+        let share = decode_scalar(&mut input)?;
+        let pub_key = decode_point(&mut input)?;
+        let mut verification_share = Vec::new();
+        for _ in 0..u32::decode(&mut input)? {
+            verification_share.push(decode_point(&mut input)?);
+        }
+        let key = KeyFile {
+            index,
+            threshold,
+            share : Secret::new(share),
+            pub_key,
+            verification_share,
+            pri_p : Secret::new(BigUint::decode(&mut input)?),
+            pri_q : Secret::new(BigUint::decode(&mut input)?),
+        };
+        if !input.is_empty() {
+            return Err(ThresholdError::InvalidEncoding("trailing bytes"));
+        }
+        Ok(key)
+    }
+
+    pub fn encrypt_with(&self, rng: &mut (impl CryptoRng + RngCore), passphrase: &[u8], params: KdfParams) -> Result<Vec<u8>, ThresholdError> {
+        let salt: [u8; SALT_LEN] = rng.gen();
+        let nonce: [u8; NONCE_LEN] = rng.gen();
+        let mut header = KEY_FILE_MAGIC.to_vec();
+        KEY_FILE_VERSION.encode(&mut header);
+        params.m_cost.encode(&mut header);
+        params.t_cost.encode(&mut header);
+        params.p_cost.encode(&mut header);
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+        let mut plain_text = self.encode_plain();
+        let ciphertext = cipher(passphrase, &salt, params)?
+            .encrypt(Nonce::from_slice(&nonce), AeadPayload { msg : &plain_text, aad : &header });
+        plain_text.zeroize();
+        let ciphertext = ciphertext.map_err(|_| ThresholdError::InvalidEncoding("key file is too long to encrypt"))?;
+        let mut out = header;
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    //a wrong passphrase and a changed byte look the same, the tag doesn't match
+    pub fn decrypt(bytes: &[u8], passphrase: &[u8]) -> Result<KeyFile<P>, ThresholdError> {
+        if bytes.len() < HEADER_LEN || &bytes[..6] != KEY_FILE_MAGIC {
+            return Err(ThresholdError::InvalidEncoding("not a key file"));
+        }
+        if bytes[6] != KEY_FILE_VERSION {
+            return Err(ThresholdError::InvalidEncoding("unknown key file version"));
+        }
+        let (header, ciphertext) = bytes.split_at(HEADER_LEN);
+        let mut input = &header[7..];
+        let params = KdfParams {
+            m_cost : u32::decode(&mut input)?,
+            t_cost : u32::decode(&mut input)?,
+            p_cost : u32::decode(&mut input)?,
+        };
+        let (salt, nonce) = input.split_at(SALT_LEN);
+        let mut plain_text = cipher(passphrase, salt, params)?
+            .decrypt(Nonce::from_slice(nonce), AeadPayload { msg : ciphertext, aad : header })
+            .map_err(|_| ThresholdError::InvalidEncoding("wrong passphrase or damaged key file"))?;
+        let key = KeyFile::decode_plain(&plain_text);
+        plain_text.zeroize();
+        let key = key?;
+        key.check()?;
+        Ok(key)
+    }
+
+    //only the owner can read it, and a crash while writing leave the old file in place
+    pub fn save(&self, rng: &mut (impl CryptoRng + RngCore), path: &Path, passphrase: &[u8]) -> Result<(), ThresholdError> {
+        self.save_with(rng, path, passphrase, KdfParams::recommended())
+    }
+
+    pub fn save_with(&self, rng: &mut (impl CryptoRng + RngCore), path: &Path, passphrase: &[u8], params: KdfParams) -> Result<(), ThresholdError> {
+        write_atomic(path, &self.encrypt_with(rng, passphrase, params)?)
+    }
+
+    pub fn load(path: &Path, passphrase: &[u8]) -> Result<KeyFile<P>, ThresholdError> {
+        KeyFile::decrypt(&std::fs::read(path)?, passphrase)
+    }
+}
+
+impl<P: EcdsaCurve> KeyFile<P> {
+    //w is this party's signing share when signer_set (indices + 1) sign, k and r are hedged from it,
+    //the paillier key and the message. nothing of the secret come from the caller
+    pub fn party_secret(&self, rng: &mut (impl CryptoRng + RngCore), digest: &[u8; 32], signer_set: &[u128]) -> Result<PartySecret<P>, ThresholdError> {
+        let w = Secret::new(self.signing_share(signer_set)?);
+        Ok(PartySecret::hedged(rng, digest, w, Secret::new(self.pri_p.expose().clone()), Secret::new(self.pri_q.expose().clone())))
+    }
+}
+
+//fast enough for a test, never for a real file
+#[cfg(test)]
+pub const TEST_PARAMS: KdfParams = KdfParams { m_cost : 64, t_cost : 1, p_cost : 1 };
+
+//a random key dealt to 3 parties with threshold 2 and the fixed paillier keys, for the tests
+#[cfg(test)]
+pub fn test_key_files<P: ThresholdCurve>(rng: &mut (impl CryptoRng + RngCore)) -> Vec<KeyFile<P>> {
+    let secret = <P::Scalar as ff::Field>::random(&mut *rng);
+    let slope = <P::Scalar as ff::Field>::random(&mut *rng);
+    let share: Vec<P::Scalar> = (1..=3u64).map(|i| secret + slope * P::Scalar::from(i)).collect();
+    let verification_share: Vec<P> = share.iter().map(|i| (P::generator() * *i).to_affine()).collect();
+    let pub_key = (P::generator() * secret).to_affine();
+    share.into_iter().enumerate().map(|(index, share)| {
+        let (pri_p, pri_q) = crate::paillier_modulus::test_paillier_key(index);
+        KeyFile {
+            index,
+            threshold : 2,
+            share : Secret::new(share),
+            pub_key,
+            verification_share : verification_share.clone(),
+            pri_p : Secret::new(pri_p),
+            pri_q : Secret::new(pri_q),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::Mul;
+    use ff::Field;
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use crate::transport::{run_keygen, simulate, Faults};
+    use crate::paillier_modulus::test_paillier_key;
+
+    #[test]
+    fn key_file_test(){
+        let result = simulate(3, Faults::none(), |session| run_keygen(session, &mut rand::thread_rng(), 2));
+        let (pri_p, pri_q) = test_paillier_key(0);
+        let key: Vec<KeyFile> = result.into_iter().enumerate().map(|(index, i)| {
+            let (share, pub_key, verification_share) = i.unwrap();
+            KeyFile {
+                index,
+                threshold : 2,
+                share,
+                pub_key,
+                verification_share,
+                pri_p : Secret::new(pri_p.clone()),
+                pri_q : Secret::new(pri_q.clone()),
+            }
+        }).collect();
+
+        let bytes = key[1].encrypt_with(&mut rand::thread_rng(), b"correct horse", TEST_PARAMS).unwrap();
+        let opened: KeyFile = KeyFile::decrypt(&bytes, b"correct horse").unwrap();
+        assert_eq!(opened.encode_plain(), key[1].encode_plain());
+        assert_eq!(opened.mta_pub_n(), &pri_p * &pri_q);
+        assert_eq!(KeyFile::<pallas::Affine>::decrypt(&bytes, b"wrong horse").err(), Some(ThresholdError::InvalidEncoding("wrong passphrase or damaged key file")));
+        //the kdf params are covered by the tag
+        let mut bad = bytes.clone();
+        bad[10] ^= 1;
+        assert!(KeyFile::<pallas::Affine>::decrypt(&bad, b"correct horse").is_err());
+        let mut bad = bytes.clone();
+        bad[6] = 1;
+        assert_eq!(KeyFile::<pallas::Affine>::decrypt(&bad, b"correct horse").err(), Some(ThresholdError::InvalidEncoding("unknown key file version")));
+
+        //any two signing shares add up to the key
+        let signer_set = [1, 3];
+        let w = pallas::Scalar::add(&key[0].signing_share(&signer_set).unwrap(), &key[2].signing_share(&signer_set).unwrap());
+        assert_eq!(pallas::Affine::mul(pallas::Affine::generator(), w).to_affine(), key[0].pub_key);
+        assert!(key[0].signing_share(&[1]).is_err());
+
+        //the party secret's w is the signing share, k and r are new for every message
+        let secret = key[0].party_secret(&mut rand::thread_rng(), &[5; 32], &signer_set).unwrap();
+        assert_eq!(*secret.w.expose(), key[0].signing_share(&signer_set).unwrap());
+        let again = key[0].party_secret(&mut rand::thread_rng(), &[5; 32], &signer_set).unwrap();
+        assert_ne!(secret.k.expose(), again.k.expose());
+        assert!(key[0].party_secret(&mut rand::thread_rng(), &[5; 32], &[1]).is_err());
+
+        //a share that doesn't match its verification share is refused when loaded
+        let wrong = KeyFile {
+            share : Secret::new(pallas::Scalar::random(rand::thread_rng())),
+            verification_share : key[0].verification_share.clone(),
+            pri_p : Secret::new(pri_p.clone()),
+            pri_q : Secret::new(pri_q.clone()),
+            ..key[0]
+        };
+        let bytes = wrong.encrypt_with(&mut rand::thread_rng(), b"pass", TEST_PARAMS).unwrap();
+        assert_eq!(KeyFile::<pallas::Affine>::decrypt(&bytes, b"pass").err(), Some(ThresholdError::VerificationFailed { party: 0 }));
+
+        //the file know its curve, a secp256k1 share is not opened as pallas
+        let key = test_key_files::<k256::AffinePoint>(&mut rand::thread_rng());
+        let bytes = key[2].encrypt_with(&mut rand::thread_rng(), b"pass", TEST_PARAMS).unwrap();
+        let opened = KeyFile::<k256::AffinePoint>::decrypt(&bytes, b"pass").unwrap();
+        assert_eq!((opened.index, opened.share.expose(), opened.pub_key), (2, key[2].share.expose(), key[2].pub_key));
+        assert_eq!(KeyFile::<pallas::Affine>::decrypt(&bytes, b"pass").err(), Some(ThresholdError::InvalidEncoding("key file is for another curve")));
+    }
+}
@@ -1,128 +1,259 @@
-use std::ops::{Add, Mul};
 use halo2_proofs::arithmetic::Field;
-use rand::Rng;
 
-use pasta_curves::{group::{cofactor::CofactorCurveAffine, ff::PrimeField, Curve}, pallas};
+use pasta_curves::group::{ff::PrimeField, Curve, Group};
+#[cfg(test)]
+use pasta_curves::pallas;
+use myp::{Secret, ThresholdError};
+use rand::{CryptoRng, RngCore};
+use crate::curve::ThresholdCurve;
 //p(i) = u + ir + (ir)**2 + ...
-fn generate_random_u128_in_range(min: u128, max: u128) -> u128 {
-    let mut rng = rand::thread_rng();
-    let random_value = rng.gen_range(min..=max);
-    random_value
-}
+//the integer walk through of the math, only the tests run it, run_keygen deal scalars below
 
+#[cfg(test)]
 pub struct Input {
-    pub key_share : u128,
-    pub rand_num : u128,
+    pub key_share : Secret<u128>,
+    pub rand_num : Secret<u128>,
     pub output_max : usize,
     pub output_min : usize,
 }
 
+#[cfg(test)]
 impl Input{
-    pub fn output_key_share(&self) -> Vec<u128>{
-        let mut output_key_share = vec![self.key_share; self.output_max];
+    pub fn output_key_share(&self) -> Result<Vec<u128>, ThresholdError>{
+        if self.output_min == 0 || self.output_min > self.output_max {
+            return Err(ThresholdError::OutOfRange("output_min"));
+        }
+        let (key_share, rand_num) = (*self.key_share.expose(), *self.rand_num.expose());
+        let mut output_key_share = vec![key_share; self.output_max];
 
         for i in 0..self.output_max{
             for j in 0..(self.output_min-1){
-                output_key_share[i] += ((i+1) as u128* self.rand_num).pow((j as u32)+1);
+                let term = ((i+1) as u128).checked_mul(rand_num)
+                    .and_then(|x| x.checked_pow((j as u32)+1))
+                    .ok_or(ThresholdError::OutOfRange("rand_num"))?;
+                output_key_share[i] = output_key_share[i].checked_add(term)
+                    .ok_or(ThresholdError::OutOfRange("key_share"))?;
             }
         }
-        output_key_share
+        Ok(output_key_share)
     }
 }
 
+#[cfg(test)]
 pub struct CollectOutputKeyShare {
-    pub key_share : Vec<u128>,
+    pub key_share : Secret<Vec<u128>>,
     pub member : u128,
     pub self_num : u128,
 }
 
 //member is all the player join the signature
+#[cfg(test)]
 impl CollectOutputKeyShare {
-    pub fn collect(&self) -> (u128, pallas::Affine) {
+    pub fn collect(&self) -> Result<(Secret<u128>, pallas::Affine), ThresholdError> {
+        if self.member == 0 || self.self_num == 0 || self.self_num > self.member {
+            return Err(ThresholdError::OutOfRange("self_num"));
+        }
         let mut self_key_share :u128 = 0;
         let member = self.member;
         let mut count =0;
-        for i in &self.key_share{
+        for i in self.key_share.expose(){
             count += 1;
             if count == self.self_num || ((count+member-self.self_num))%member==0{
-                self_key_share += i;
+                self_key_share = self_key_share.checked_add(*i)
+                    .ok_or(ThresholdError::OutOfRange("key_share"))?;
             }
             
         }
-        let key_fq = pallas::Scalar::from_u128(self_key_share);
-        let generator = pallas::Affine::generator();
-        let result = pallas::Affine::mul(generator, key_fq).to_affine();
+        let self_key_share = Secret::new(self_key_share);
+        let key_fq = pallas::Scalar::from_u128(*self_key_share.expose());
+        let result = (pallas::Point::generator() * key_fq).to_affine();
 
-        (self_key_share, result)
+        Ok((self_key_share, result))
     }
 }
 
-pub struct CalculatePubKey {
+#[cfg(test)]
+pub struct CalculatePubKey<P = pallas::Affine> {
     pub degree : u128,
     pub coefficient : Vec<u128>,
-    pub pub_key : Vec<pallas::Affine>,
+    pub pub_key : Vec<P>,
 }
 
-impl CalculatePubKey {
-    pub fn calculate(&self) -> pallas::Affine{
-        let thousandfq = pallas::Scalar::from_u128(1000);
+#[cfg(test)]
+impl<P: ThresholdCurve> CalculatePubKey<P> {
+    pub fn calculate(&self) -> Result<P, ThresholdError>{
+        if self.coefficient.len() != self.pub_key.len() {
+            return Err(ThresholdError::LengthMismatch {
+                expected: self.coefficient.len(),
+                found: self.pub_key.len(),
+            });
+        }
+        if self.degree > self.coefficient.len() as u128 {
+            return Err(ThresholdError::LengthMismatch {
+                expected: self.degree as usize,
+                found: self.coefficient.len(),
+            });
+        }
         let mut num = 0;
-        let mut result = pallas::Affine::identity();
+        let mut result = P::Curve::identity();
         while num < self.degree {
-            let index: usize = num.try_into().unwrap();
+            let index = num as usize;
             let mut iter = 0;
-            let mut ans = self.pub_key[index];
+            let mut ans = self.pub_key[index].to_curve();
             for i in self.coefficient.iter(){                
                 if iter != num {
                     let self_coefficient :u128 = self.coefficient[index];
-                    let mut dev: u128 = 1000;
-                    let mutn = pallas::Scalar::from_u128(i.clone());
-                    ans = pallas::Affine::mul(ans, &mutn).to_affine();
-                    dev  =  dev + i - self_coefficient;
-                    let dev2 = pallas::Scalar::from_u128(dev);
-                    let dev3 = pallas::Scalar::sub(&dev2, &thousandfq);
-                    let dev4 = pallas::Scalar::invert(&dev3).unwrap();
-                    ans = pallas::Affine::mul(ans, dev4).to_affine();
+                    let mutn = P::Scalar::from_u128(*i);
+                    ans *= mutn;
+                    let dev = mutn - P::Scalar::from_u128(self_coefficient);
+                    let dev_inv: P::Scalar = Option::from(dev.invert())
+                        .ok_or(ThresholdError::NonInvertible)?;
+                    ans *= dev_inv;
                 }
                 iter+=1;
             }
-            result = pallas::Affine::add(result, ans).to_affine();
+            result += ans;
             num+=1;
         }   
-        result
+        Ok(result.to_affine())
     }
 }
 
+#[cfg(test)]
 pub struct CalculatePriKey {
     pub self_coefficient : u128,
     pub coefficient : [u128;2],
-    pub pri_key : u128,
+    pub pri_key : Secret<u128>,
 }
 
+#[cfg(test)]
 impl CalculatePriKey {
-    pub fn calculate(&self) -> pallas::Scalar{
-        let mut ans =  self.pri_key;
-        ans *= self.coefficient[0];
-        ans *= self.coefficient[1];
-        let mut result = pallas::Scalar::from_u128(ans);
-        let mut dev1 = pallas::Scalar::from_u128(self.self_coefficient);
+    pub fn calculate(&self) -> Result<Secret<pallas::Scalar>, ThresholdError>{
         let a = pallas::Scalar::from_u128(self.coefficient[0]);
-        dev1 = pallas::Scalar::sub(&dev1, &a);
-        let mut dev = pallas::Scalar::from_u128(self.self_coefficient);
         let b = pallas::Scalar::from_u128(self.coefficient[1]);
-        dev = pallas::Scalar::sub(&dev, &b);
-        dev = pallas::Scalar::mul(&dev, &dev1);
-        dev = pallas::Scalar::invert(&dev).unwrap();
+        let mut result = pallas::Scalar::from_u128(*self.pri_key.expose());
+        result *= a;
+        result *= b;
+        let mut dev1 = pallas::Scalar::from_u128(self.self_coefficient);
+        dev1 -= a;
+        let mut dev = pallas::Scalar::from_u128(self.self_coefficient);
+        dev -= b;
+        dev *= dev1;
+        //two players with the same coefficient
+        let dev: pallas::Scalar = Option::from(dev.invert())
+            .ok_or(ThresholdError::NonInvertible)?;
+
+        result *= dev;
+        Ok(Secret::new(result))
+    }
+}
+
+//the key ceremony on the scalar field of a curve, what run_keygen and the signers of ed25519.rs and
+//bip340.rs take their shares from. every party deal a random secret with a polynomial of degree output_min - 1
+//and publish its coefficients * G, so the key is uniform and a share off the polynomial is caught
+//p(1) .. p(output_max) of one dealer and the commitment to its coefficients
+pub type ScalarDeal<P> = (Secret<Vec<<P as pasta_curves::group::prime::PrimeCurveAffine>::Scalar>>, Vec<P>);
+//the share, the group public key and share * G of every member
+pub type ScalarKeyShare<P> = (Secret<<P as pasta_curves::group::prime::PrimeCurveAffine>::Scalar>, P, Vec<P>);
+
+pub struct DealScalarShare {
+    pub output_max : usize,
+    pub output_min : usize,
+}
 
-        result = pallas::Scalar::mul(&result, &dev);
-        result
+impl DealScalarShare {
+    //p(1) .. p(output_max), and the commitment to every coefficient for the others
+    pub fn deal<P: ThresholdCurve>(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<ScalarDeal<P>, ThresholdError> {
+        if self.output_min == 0 || self.output_min > self.output_max {
+            return Err(ThresholdError::OutOfRange("output_min"));
+        }
+        let coefficient: Secret<Vec<P::Scalar>> = Secret::new((0..self.output_min).map(|_| P::Scalar::random(&mut *rng)).collect());
+        let commitment = coefficient.expose().iter().map(|i| (P::generator() * i).to_affine()).collect();
+        //p(x) with horner
+        let share = (1..=self.output_max as u64).map(|x| {
+            coefficient.expose().iter().rev().fold(P::Scalar::ZERO, |acc, i| acc * P::Scalar::from(x) + i)
+        }).collect();
+        Ok((Secret::new(share), commitment))
     }
 }
 
+//key_share[i] is p_i(self_num) from dealer i and commitment[i] is what dealer i published,
+//every party of the ceremony is a dealer
+pub struct CollectScalarShare<P: ThresholdCurve> {
+    pub key_share : Secret<Vec<P::Scalar>>,
+    pub commitment : Vec<Vec<P>>,
+    pub self_num : u128,
+}
+
+impl<P: ThresholdCurve> CollectScalarShare<P> {
+    //the share, the group public key and share * G of every member, a dealer whose share
+    //doesn't fit its commitment is blamed by its place
+    pub fn collect(&self) -> Result<ScalarKeyShare<P>, ThresholdError> {
+        let member = self.commitment.len();
+        if self.self_num == 0 || self.self_num > member as u128 {
+            return Err(ThresholdError::OutOfRange("self_num"));
+        }
+        if self.key_share.expose().len() != member {
+            return Err(ThresholdError::LengthMismatch { expected: member, found: self.key_share.expose().len() });
+        }
+        //p_i(x) * G == sum(x^j * a_ij * G)
+        let commitment_at = |i: usize, x: u128| {
+            let x = P::Scalar::from_u128(x);
+            self.commitment[i].iter().rev().fold(P::Curve::identity(), |acc, j| acc * x + j)
+        };
+        let degree = self.commitment[0].len();
+        let mut share = Secret::new(P::Scalar::ZERO);
+        for (i, key_share) in self.key_share.expose().iter().enumerate() {
+            if self.commitment[i].len() != degree || P::generator() * key_share != commitment_at(i, self.self_num) {
+                return Err(ThresholdError::VerificationFailed { party : i });
+            }
+            share = Secret::new(*share.expose() + key_share);
+        }
+        let pub_key = self.commitment.iter().map(|i| i[0]).fold(P::Curve::identity(), |acc, i| acc + i);
+        let verification_share = (1..=member as u128).map(|j| {
+            (0..member).map(|i| commitment_at(i, j)).sum::<P::Curve>().to_affine()
+        }).collect();
+        Ok((share, pub_key.to_affine(), verification_share))
+    }
+}
+
+//the scalar key ceremony of member parties in one process, ScalarKeyShare of every party in order
+#[cfg(test)]
+pub fn test_scalar_keys<P: ThresholdCurve>(rng: &mut (impl CryptoRng + RngCore), member: usize, threshold: usize) -> Vec<ScalarKeyShare<P>> {
+    let deal: Vec<ScalarDeal<P>> = (0..member).map(|_| DealScalarShare { output_max : member, output_min : threshold }.deal(rng).unwrap()).collect();
+    (0..member).map(|j| CollectScalarShare {
+        key_share : Secret::new(deal.iter().map(|i| i.0.expose()[j]).collect()),
+        commitment : deal.iter().map(|i| i.1.clone()).collect(),
+        self_num : (j + 1) as u128,
+    }.collect().unwrap()).collect()
+}
+
+//lambda_i = prod(j / (j - i)) over the other signers, so sum(lambda_i * p(i)) = p(0)
+//in the scalar field of whatever curve the shares are on
+pub fn lagrange_coefficient<F: PrimeField>(index: u128, signer_set: &[u128]) -> Result<F, ThresholdError> {
+    if !signer_set.contains(&index) {
+        return Err(ThresholdError::OutOfRange("index"));
+    }
+    let i = F::from_u128(index);
+    let mut result = F::ONE;
+    for j in signer_set {
+        if *j == index {
+            continue;
+        }
+        let j = F::from_u128(*j);
+        let dev: F = Option::from((j - i).invert())
+            .ok_or(ThresholdError::NonInvertible)?;
+        result *= j * dev;
+    }
+    Ok(result)
+}
 
 #[cfg(test)]
 mod tests{
     use super::*;
+    use std::ops::Mul;
+    use pasta_curves::group::prime::PrimeCurveAffine;
+    use crate::generate_random_u128_in_range;
     #[test]
     fn key_generate_test() {
         let key_share1 = generate_random_u128_in_range(1, std::u64::MAX as u128);
@@ -131,44 +262,44 @@ mod tests{
         let key_share4 = generate_random_u128_in_range(1, std::u64::MAX as u128);
         let key_share5 = generate_random_u128_in_range(1, std::u64::MAX as u128);
         let input1 = Input{
-            key_share : key_share1,
-            rand_num : 379278,
+            key_share : Secret::new(key_share1),
+            rand_num : Secret::new(379278),
             output_max : 5,
             output_min : 3,
         };
-        let result1 = input1.output_key_share();
+        let result1 = input1.output_key_share().unwrap();
     
         let input2 = Input{
-            key_share : key_share2,
-            rand_num : 4812738974,
+            key_share : Secret::new(key_share2),
+            rand_num : Secret::new(4812738974),
             output_max : 5,
             output_min : 3,
         };
-        let result2 = input2.output_key_share();
+        let result2 = input2.output_key_share().unwrap();
     
         let input3 = Input{
-            key_share : key_share3,
-            rand_num : 43217,
+            key_share : Secret::new(key_share3),
+            rand_num : Secret::new(43217),
             output_max : 5,
             output_min : 3,
         };
-        let result3 = input3.output_key_share();
+        let result3 = input3.output_key_share().unwrap();
     
         let input4 = Input{
-            key_share : key_share4,
-            rand_num : 745,
+            key_share : Secret::new(key_share4),
+            rand_num : Secret::new(745),
             output_max : 5,
             output_min : 3,
         };
-        let result4 = input4.output_key_share();
+        let result4 = input4.output_key_share().unwrap();
     
         let input5 = Input{
-            key_share : key_share5,
-            rand_num : 542,
+            key_share : Secret::new(key_share5),
+            rand_num : Secret::new(542),
             output_max : 5,
             output_min : 3,
         };
-        let result5 = input5.output_key_share();
+        let result5 = input5.output_key_share().unwrap();
     
         let mut user_vec = Vec::new();
         user_vec.extend(result1);
@@ -178,32 +309,32 @@ mod tests{
         user_vec.extend(result5);
 
         let user1 = CollectOutputKeyShare{
-            key_share : user_vec.clone(),
+            key_share : Secret::new(user_vec.clone()),
             member : 5,
             self_num : 1,
         };
-        let (user1_prikey_share, user1_pubket_share) = user1.collect();
+        let (user1_prikey_share, user1_pubket_share) = user1.collect().unwrap();
         
         let user2 = CollectOutputKeyShare{
-            key_share : user_vec.clone(),
+            key_share : Secret::new(user_vec.clone()),
             member : 5,
             self_num : 2,
         };
-        let (user2_prikey_share, user2_pubket_share) = user2.collect();
+        let (user2_prikey_share, user2_pubket_share) = user2.collect().unwrap();
     
         let user3 = CollectOutputKeyShare{
-            key_share : user_vec.clone(),
+            key_share : Secret::new(user_vec.clone()),
             member : 5,
             self_num : 3,
         };
-        let (user3_prikey_share, user3_pubket_share) = user3.collect();
+        let (user3_prikey_share, user3_pubket_share) = user3.collect().unwrap();
     
         let pub_key = CalculatePubKey {
             degree : 3,
             coefficient : [1,2,3].to_vec(),
             pub_key : [user1_pubket_share, user2_pubket_share, user3_pubket_share].to_vec(),
         };
-        let result = pub_key.calculate();
+        let result = pub_key.calculate().unwrap();
         let prik = key_share1 + key_share2 + key_share3 + key_share4 + key_share5;
         let check = pallas::Scalar::from_u128(prik);
         let generator = pallas::Affine::generator();
@@ -212,4 +343,59 @@ mod tests{
   
         assert_eq!(ans, true)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn key_generate_error_test() {
+        let input = Input{
+            key_share : Secret::new(1),
+            rand_num : Secret::new(379278),
+            output_max : 2,
+            output_min : 3,
+        };
+        assert_eq!(input.output_key_share(), Err(ThresholdError::OutOfRange("output_min")));
+
+        //two players with the same coefficient can't interpolate
+        let pri_key = CalculatePriKey {
+            self_coefficient : 1,
+            coefficient : [1,3],
+            pri_key : Secret::new(5),
+        };
+        assert_eq!(pri_key.calculate().err(), Some(ThresholdError::NonInvertible));
+
+        let generator = pallas::Affine::generator();
+        let pub_key = CalculatePubKey {
+            degree : 3,
+            coefficient : [1,2,3].to_vec(),
+            pub_key : [generator, generator].to_vec(),
+        };
+        assert_eq!(pub_key.calculate(), Err(ThresholdError::LengthMismatch { expected: 3, found: 2 }));
+    }
+
+    #[test]
+    fn scalar_key_generate_test() {
+        //3 of 4 on secp256k1, any 3 of the shares give the key
+        let mut rng = rand::thread_rng();
+        let deal = DealScalarShare { output_max : 4, output_min : 3 };
+        let (share, commitment): (Vec<Secret<Vec<k256::Scalar>>>, Vec<Vec<k256::AffinePoint>>) = (0..4).map(|_| deal.deal(&mut rng).unwrap()).unzip();
+        let column = |j: usize| Secret::new(share.iter().map(|i| i.expose()[j]).collect::<Vec<k256::Scalar>>());
+        let output: Vec<(Secret<k256::Scalar>, k256::AffinePoint, Vec<k256::AffinePoint>)> = (0..4).map(|j| CollectScalarShare {
+            key_share : column(j),
+            commitment : commitment.clone(),
+            self_num : j as u128 + 1,
+        }.collect().unwrap()).collect();
+        let pub_key = output[0].1;
+        let signer_set = [1, 3, 4];
+        let key: k256::Scalar = signer_set.iter().map(|i| lagrange_coefficient::<k256::Scalar>(*i, &signer_set).unwrap() * output[*i as usize - 1].0.expose()).sum();
+        assert_eq!((k256::AffinePoint::generator() * key).to_affine(), pub_key);
+        for (j, (share, key, verification_share)) in output.iter().enumerate() {
+            assert_eq!(*key, pub_key);
+            assert_eq!(verification_share[j], (k256::AffinePoint::generator() * share.expose()).to_affine());
+        }
+
+        //dealer 2 send party 1 a share off its polynomial
+        let mut bad = column(0).expose().clone();
+        bad[2] += k256::Scalar::ONE;
+        assert_eq!(CollectScalarShare { key_share : Secret::new(bad), commitment : commitment.clone(), self_num : 1 }.collect().err(), Some(ThresholdError::VerificationFailed { party : 2 }));
+        assert_eq!(DealScalarShare { output_max : 2, output_min : 3 }.deal::<k256::AffinePoint>(&mut rng).err(), Some(ThresholdError::OutOfRange("output_min")));
+    }
+}
@@ -1,118 +1,377 @@
-use rand::Rng;
+use num_bigint::{BigInt, BigUint, RandBigInt};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use rand::{CryptoRng, Rng, RngCore};
+use pasta_curves::{pallas, vesta};
+use zeroize::Zeroize;
 
-pub fn generate_random_u128_in_range(min: u128, max: u128) -> u128 {
-    let mut rng = rand::thread_rng();
-    let random_value = rng.gen_range(min..=max);
-    random_value
+pub mod error;
+pub use error::ThresholdError;
+
+//how a secret is overwritten before its memory is given back
+pub trait Wipe {
+    fn wipe(&mut self);
 }
 
-pub fn pow_and_mod(mut base: u128, mut exponent: u128, modulus: u128) -> u128{
-    if exponent == 0 {
-        return 1;
+impl Wipe for u128 {
+    fn wipe(&mut self) {
+        self.zeroize();
     }
+}
+
+impl Wipe for BigUint {
+    fn wipe(&mut self) {
+        //the digits in use are overwritten with zeros in place, then the value is 0
+        let zero = vec![0u32; self.bits().div_ceil(32) as usize];
+        self.assign_from_slice(&zero);
+    }
+}
 
-    while exponent & 1 == 0 {
-        base = base.clone() * base;
-        base = base % modulus;
-        exponent >>= 1;
+impl Wipe for pallas::Scalar {
+    fn wipe(&mut self) {
+        //a scalar is four u64 limbs, all zero is the zero scalar
+        unsafe { zeroize::zeroize_flat_type(self as *mut pallas::Scalar) }
     }
-    if exponent == 1 {
-        base = base % modulus;
+}
+
+impl Wipe for vesta::Scalar {
+    fn wipe(&mut self) {
+        unsafe { zeroize::zeroize_flat_type(self as *mut vesta::Scalar) }
     }
+}
 
-    let mut acc = base.clone();
+impl Wipe for k256::Scalar {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
 
-    while exponent > 1 {
-        exponent >>= 1;
-        base = base.clone() * base;
-        base = base % modulus;
-        if exponent & 1 == 1 {
-            acc = acc * base.clone();
-            acc = acc % modulus;
+impl Wipe for p256::Scalar {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Wipe for curve25519_dalek::Scalar {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<T: Wipe> Wipe for Vec<T> {
+    fn wipe(&mut self) {
+        for i in self.iter_mut() {
+            i.wipe();
         }
+        self.clear();
     }
-    acc
 }
 
-pub fn invmod(a: u128, p: u128) -> u128 {
-    if a == 0 {
-        panic!("0 has no inverse mod {}", p);
+//a key share, nonce or paillier factor, it is wiped when dropped. there is no Debug and no Clone,
+//so it can't end up in a log or in a copy that outlive it, expose() is the only way to read it
+pub struct Secret<T: Wipe>(T);
+
+impl<T: Wipe> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
     }
-    let mut r = a;
-    let mut d = 1;
 
-    for _ in 0..p {
-        d = ((p / r + 1) * d) % p;
-        r = (d * a) % p;
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Wipe> From<T> for Secret<T> {
+    fn from(value: T) -> Secret<T> {
+        Secret(value)
+    }
+}
+
+impl<T: Wipe> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.wipe();
+    }
+}
+
+//every randomized step take the rng from its caller, a seeded one make a run reproducible
+//uniform in min..=max
+pub fn random_in_range(rng: &mut (impl CryptoRng + RngCore), min: u128, max: u128) -> u128 {
+    rng.gen_range(min..=max)
+}
 
-        if r == 1 {
-            break;
+//uniform in Z*_n, the randomness of a paillier cipher, n must be > 1
+pub fn random_unit(rng: &mut (impl CryptoRng + RngCore), n: &BigUint) -> BigUint {
+    loop {
+        let r = rng.gen_biguint_range(&BigUint::one(), n);
+        if r.gcd(n).is_one() {
+            return r;
         }
     }
+}
+
+//x + y mod m for x, y < m, without overflow and without branch on the value
+fn add_mod(x: u128, y: u128, modulus: u128) -> u128 {
+    let (sum, carry) = x.overflowing_add(y);
+    let (reduced, borrow) = sum.overflowing_sub(modulus);
+    let mask = 0u128.wrapping_sub((carry | !borrow) as u128);
+    (reduced & mask) | (sum & !mask)
+}
+
+//a * b mod m with double and add over all 128 bits of b, so it works for any modulus < 2^128
+pub fn mul_mod(a: u128, b: u128, modulus: u128) -> u128 {
+    let a = a % modulus;
+    let mut acc = 0;
+    for i in (0..128).rev() {
+        acc = add_mod(acc, acc, modulus);
+        let mask = 0u128.wrapping_sub((b >> i) & 1);
+        acc = add_mod(acc, a & mask, modulus);
+    }
+    acc
+}
+
+fn swap_mask(x: &mut u128, y: &mut u128, mask: u128) {
+    let t = (*x ^ *y) & mask;
+    *x ^= t;
+    *y ^= t;
+}
+
+//montgomery ladder, always 128 steps with the same operations whatever the exponent is
+pub fn pow_and_mod(base: u128, exponent: u128, modulus: u128) -> u128{
+    let mut r0 = 1 % modulus;
+    let mut r1 = base % modulus;
+    for i in (0..128).rev() {
+        let mask = 0u128.wrapping_sub((exponent >> i) & 1);
+        swap_mask(&mut r0, &mut r1, mask);
+        r1 = mul_mod(r0, r1, modulus);
+        r0 = mul_mod(r0, r0, modulus);
+        swap_mask(&mut r0, &mut r1, mask);
+    }
+    r0
+}
+
+//a - b mod m for a, b < m
+fn sub_mod(a: u128, b: u128, modulus: u128) -> u128 {
+    if a >= b { a - b } else { modulus - (b - a) }
+}
 
-    if r != 1 {
-        panic!("{} has no inverse mod {}", a, p);
+//extended euclid, None if gcd(a, modulus) != 1. the bezout coefficient is kept mod the modulus
+//instead of signed, so the whole u128 range work
+pub fn mod_inverse(a: u128, modulus: u128) -> Option<u128> {
+    if modulus == 0 {
+        return None;
     }
+    let (mut old_r, mut r) = (a % modulus, modulus);
+    let (mut old_s, mut s) = (1 % modulus, 0u128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, sub_mod(old_s, mul_mod(quotient, s, modulus), modulus));
+    }
+    if old_r != 1 {
+        return None;
+    }
+    Some(old_s)
+}
 
-    d
+pub fn invmod(a: u128, p: u128) -> Result<u128, ThresholdError> {
+    mod_inverse(a, p).ok_or(ThresholdError::NonInvertible)
+}
+
+//the paillier code is on big integers, a real n is 2048 bits. num-bigint is not constant time
+fn paillier_modulus_square(mta_pub_n: &BigUint) -> Result<BigUint, ThresholdError> {
+    if *mta_pub_n < BigUint::from(2u32) {
+        return Err(ThresholdError::InvalidModulus(mta_pub_n.clone()));
+    }
+    Ok(mta_pub_n * mta_pub_n)
+}
+
+//a^-1 mod modulus, None if gcd(a, modulus) != 1
+pub fn big_mod_inverse(a: &BigUint, modulus: &BigUint) -> Option<BigUint> {
+    if modulus.is_zero() {
+        return None;
+    }
+    let modulus = BigInt::from(modulus.clone());
+    let result = BigInt::from(a.clone()).mod_floor(&modulus).extended_gcd(&modulus);
+    if !result.gcd.is_one() {
+        return None;
+    }
+    result.x.mod_floor(&modulus).to_biguint()
+}
+
+//(n + 1)^m = 1 + m * n mod n^2
+fn paillier_g_pow(mta_pub_n: &BigUint, message: &BigUint, n2: &BigUint) -> BigUint {
+    (BigUint::one() + (message % mta_pub_n) * mta_pub_n) % n2
 }
 
 pub struct Encrypt {
-    pub mta_pub_n : u128,
-    pub rand : u128,
-    pub message : u128,
+    pub mta_pub_n : BigUint,
+    pub rand : BigUint,
+    pub message : BigUint,
 }
 
 impl Encrypt{
-    pub fn encrypt(self) -> u128{
-        let x = pow_and_mod(self.rand, self.mta_pub_n, self.mta_pub_n*self.mta_pub_n);
-        let cipher = (pow_and_mod(self.mta_pub_n + 1, self.message, self.mta_pub_n*self.mta_pub_n) * x) % (self.mta_pub_n*self.mta_pub_n);
-        cipher
+    pub fn encrypt(self) -> Result<BigUint, ThresholdError>{
+        let n2 = paillier_modulus_square(&self.mta_pub_n)?;
+        if self.message >= self.mta_pub_n {
+            return Err(ThresholdError::OutOfRange("message"));
+        }
+        let x = self.rand.modpow(&self.mta_pub_n, &n2);
+        Ok(paillier_g_pow(&self.mta_pub_n, &self.message, &n2) * x % n2)
     }
 }
 
 pub struct EncryptAddMut {
-    pub mta_pub_n : u128,
-    pub cipher : u128,
-    pub add_num : u128,
-    pub mut_num : u128,
-    pub rand : u128,
+    pub mta_pub_n : BigUint,
+    pub cipher : BigUint,
+    pub add_num : BigUint,
+    pub mut_num : BigUint,
+    pub rand : BigUint,
 }
 
 impl EncryptAddMut {
-    pub fn mut_and_add(&self) -> u128{
-        let mut cipher = pow_and_mod(self.cipher, self.mut_num, self.mta_pub_n*self.mta_pub_n);
-
-        let value1 = pow_and_mod(self.mta_pub_n + 1, self.add_num, self.mta_pub_n*self.mta_pub_n);
-        let value2 = pow_and_mod(self.rand , self.mta_pub_n, self.mta_pub_n*self.mta_pub_n);
-        let add_value = (value1 * value2) % (self.mta_pub_n*self.mta_pub_n);
-        cipher = (add_value * cipher) % (self.mta_pub_n*self.mta_pub_n);
+    pub fn mut_and_add(&self) -> Result<BigUint, ThresholdError>{
+        let n2 = paillier_modulus_square(&self.mta_pub_n)?;
+        if self.cipher >= n2 {
+            return Err(ThresholdError::OutOfRange("cipher"));
+        }
+        let cipher = self.cipher.modpow(&self.mut_num, &n2);
 
-        cipher
+        let value1 = paillier_g_pow(&self.mta_pub_n, &self.add_num, &n2);
+        let value2 = self.rand.modpow(&self.mta_pub_n, &n2);
+        let add_value = value1 * value2 % &n2;
+        Ok(add_value * cipher % n2)
     }
 }
 
 pub struct Decrypt {
-    pub pri_p : u128,
-    pub pri_q : u128,
-    pub cipher : u128,
+    pub pri_p : Secret<BigUint>,
+    pub pri_q : Secret<BigUint>,
+    pub cipher : BigUint,
 }
 
 impl Decrypt{
-    pub fn decrypt(self) -> u128{
-        let mta_pubkey_n = self.pri_p * self.pri_q;
-        let g = (self.pri_p * self.pri_q) + 1;
-        let lcm = num_integer::lcm(self.pri_p - 1, self.pri_q - 1);
-        let gs = pow_and_mod(g, lcm, mta_pubkey_n * mta_pubkey_n);
-        let l = (gs-1)/mta_pubkey_n;
-        let u = invmod(l, mta_pubkey_n);
+    pub fn decrypt(self) -> Result<BigUint, ThresholdError>{
+        let (pri_p, pri_q) = (self.pri_p.expose(), self.pri_q.expose());
+        let mta_pubkey_n = pri_p * pri_q;
+        if *pri_p < BigUint::from(2u32) || *pri_q < BigUint::from(2u32) {
+            return Err(ThresholdError::InvalidModulus(mta_pubkey_n));
+        }
+        let n2 = paillier_modulus_square(&mta_pubkey_n)?;
+        if self.cipher.is_zero() || self.cipher >= n2 {
+            return Err(ThresholdError::OutOfRange("cipher"));
+        }
+        let lcm = Secret::new((pri_p - 1u32).lcm(&(pri_q - 1u32)));
+        //g = n + 1, so L(g^lcm mod n^2) = lcm mod n
+        let u = Secret::new(big_mod_inverse(lcm.expose(), &mta_pubkey_n).ok_or(ThresholdError::NonInvertible)?);
 
-        let cs = pow_and_mod(self.cipher, lcm, mta_pubkey_n * mta_pubkey_n);
-        let cl = (cs-1)/mta_pubkey_n;
-        let plein_text = (cl * u) % mta_pubkey_n;
-        plein_text
+        //cipher is a multiple of n, it is not a unit mod n*n and c^lcm = 0
+        let cs = self.cipher.modpow(lcm.expose(), &n2);
+        if cs.is_zero() {
+            return Err(ThresholdError::NonInvertible);
+        }
+        let cl = (cs - 1u32) / &mta_pubkey_n;
+        Ok(cl * u.expose() % mta_pubkey_n)
     }
 }
 
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn pow_and_mod_test(){
+        assert_eq!(pow_and_mod(5, 0, 1), 0);
+        assert_eq!(pow_and_mod(5, 3, 1), 0);
+        assert_eq!(pow_and_mod(0, 0, 7), 1);
+
+        //modulus above 2^64, base * base overflow u128 here
+        for _ in 0..20 {
+            let modulus = random_in_range(&mut rand::thread_rng(), 1 << 64, u128::MAX);
+            let base = random_in_range(&mut rand::thread_rng(), 0, u128::MAX);
+            let exponent = random_in_range(&mut rand::thread_rng(), 0, u128::MAX);
+            let expect = BigUint::from(base).modpow(&BigUint::from(exponent), &BigUint::from(modulus));
+            assert_eq!(BigUint::from(pow_and_mod(base, exponent, modulus)), expect);
+            let expect = BigUint::from(base) * BigUint::from(exponent) % BigUint::from(modulus);
+            assert_eq!(BigUint::from(mul_mod(base, exponent, modulus)), expect);
+        }
+    }
+
+    #[test]
+    fn mod_inverse_test(){
+        assert_eq!(mod_inverse(3, 7), Some(5));
+        assert_eq!(mod_inverse(6, 9), None);
+        assert_eq!(mod_inverse(5, 0), None);
+        //above 2^127, where a signed coefficient doesn't fit
+        for _ in 0..20 {
+            let modulus = random_in_range(&mut rand::thread_rng(), 1 << 127, u128::MAX) | 1;
+            let a = random_in_range(&mut rand::thread_rng(), 1, modulus - 1);
+            if let Some(inverse) = mod_inverse(a, modulus) {
+                assert_eq!(mul_mod(a, inverse, modulus), 1);
+            }
+        }
+        let n = (u64::MAX - 58) as u128;
+        let w = n * n - 2;
+        assert_eq!(mul_mod(w, mod_inverse(w, n * n).unwrap(), n * n), 1);
+    }
 
+    #[test]
+    fn paillier_test(){
+        let mut rng = rand::thread_rng();
+        let (p, q) = (BigUint::from(35023u32), BigUint::from(46099u32));
+        let n = &p * &q;
+        let decrypt = |cipher: BigUint| Decrypt {
+            pri_p : Secret::new(p.clone()),
+            pri_q : Secret::new(q.clone()),
+            cipher,
+        }.decrypt();
+        let cipher = Encrypt {
+            mta_pub_n : n.clone(),
+            rand : random_unit(&mut rng, &n),
+            message : BigUint::from(1234u32),
+        }.encrypt().unwrap();
+        assert_eq!(decrypt(cipher.clone()), Ok(BigUint::from(1234u32)));
+        //1234 * 56 + 78
+        let cipher = EncryptAddMut {
+            mta_pub_n : n.clone(),
+            cipher,
+            add_num : BigUint::from(78u32),
+            mut_num : BigUint::from(56u32),
+            rand : random_unit(&mut rng, &n),
+        }.mut_and_add().unwrap();
+        assert_eq!(decrypt(cipher), Ok(BigUint::from(1234u32 * 56 + 78)));
+        assert_eq!(decrypt(&n * &n), Err(ThresholdError::OutOfRange("cipher")));
+        assert_eq!(decrypt(n.clone()), Err(ThresholdError::NonInvertible));
 
+        let mut big = n.clone();
+        big.wipe();
+        assert!(big.is_zero());
+    }
+
+    #[test]
+    fn secret_test(){
+        let mut scalar = pallas::Scalar::from(7u64);
+        scalar.wipe();
+        assert_eq!(scalar, pallas::Scalar::zero());
+        let mut share = vec![random_in_range(&mut rand::thread_rng(), 1, u128::MAX); 3];
+        share.wipe();
+        assert!(share.is_empty());
+        let secret = Secret::new(5u128);
+        assert_eq!(*secret.expose(), 5);
+    }
+
+    #[test]
+    fn random_test(){
+        use rand::{rngs::StdRng, SeedableRng};
+        //the same seed give the same values
+        let mut rng = StdRng::seed_from_u64(7);
+        let n = BigUint::from(35023u32 * 46099);
+        let first: Vec<BigUint> = (0..8).map(|_| random_unit(&mut rng, &n)).collect();
+        let mut rng = StdRng::seed_from_u64(7);
+        let second: Vec<BigUint> = (0..8).map(|_| random_unit(&mut rng, &n)).collect();
+        assert_eq!(first, second);
+        for i in first {
+            assert!(!i.is_zero() && !(&i % 35023u32).is_zero() && !(&i % 46099u32).is_zero());
+        }
+        assert_eq!(random_in_range(&mut rng, 5, 5), 5);
+    }
+}
@@ -1,16 +1,15 @@
 use std::fmt::Debug;
-use std::ops::{Add, Mul};
+use std::ops::Mul;
 use ff::Field;
-use pasta_curves::arithmetic::CurveAffine;
 use pasta_curves::group::cofactor::CofactorCurveAffine;
 use pasta_curves::group::ff::PrimeField;
 use pasta_curves::group::{Curve, Group};
 use halo2_proofs::{
-    circuit::{Layouter, SimpleFloorPlanner, Value, Chip},
+    circuit::{Layouter, SimpleFloorPlanner, Value},
     dev::MockProver,
-    plonk::{Circuit, ConstraintSystem, Error},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
 };
-use halo2_gadgets::utilities::{lookup_range_check::LookupRangeCheckConfig, UtilitiesInstructions};
+use halo2_gadgets::utilities::lookup_range_check::LookupRangeCheckConfig;
 use halo2_gadgets::ecc::*;
 use halo2_gadgets::sinsemilla::chip::{SinsemillaChip, SinsemillaConfig};
 use lazy_static::lazy_static;
@@ -18,17 +17,39 @@ use pasta_curves::pallas;
 use halo2_gadgets::sinsemilla::{HashDomains, CommitDomains};
 use halo2_gadgets::sinsemilla;
 
-use num_integer::gcd;
 mod key_generate;
-use key_generate::*;
 mod tool;
 use tool::*;
+//the whole mta in one call, the parties run party.rs instead. it stay for the tests and for
+//CommitmentAudit, which replay it from what the parties open
+#[allow(dead_code)]
 mod make_commitment;
-use make_commitment::*;
+//SignatureShare is what the parties use, MakeSignature and MakeSignature2 sign in one call like
+//make_commitment and only the tests run them since the cli replaced the demo
+#[allow(dead_code)]
 mod make_signature;
-use make_signature::*;
+//main only use mul_scalar and split_scalar, the schnorr and the circuits are only run by the tests
+#[allow(dead_code)]
 mod group_eddsa;
 mod paillier_verify;
+mod ring_pedersen;
+mod paillier_modulus;
+mod identifiable_abort;
+mod party;
+mod message;
+mod transport;
+mod identity;
+mod daemon;
+mod channel;
+mod cli;
+mod key_file;
+mod nonce;
+mod nonce_store;
+mod curve;
+//a library for now, no command of the cli or the daemon sign ed25519 yet
+#[allow(dead_code)]
+mod ed25519;
+mod bip340;
 
 use halo2_gadgets::ecc::{
     chip::{
@@ -192,9 +213,9 @@ impl FixedPoints<pallas::Affine> for TestFixedBases {
         }
     }
     
+//the public input is split_scalar(r * s_inv)
 #[derive(Default)]
 struct MyCircuit {
-    input_r : pallas::Scalar,
     input_s : pallas::Scalar,
     commitment : pallas::Affine,
     message : pallas::Scalar,
@@ -206,6 +227,7 @@ impl Circuit<pallas::Base> for MyCircuit {
     type Config = (
         EccConfig<TestFixedBases>,
         SinsemillaConfig<TestHashDomain, TestCommitDomain, TestFixedBases>,
+        Column<Instance>,
     );
     type FloorPlanner = SimpleFloorPlanner;
 
@@ -257,7 +279,9 @@ impl Circuit<pallas::Base> for MyCircuit {
             lookup,
             range_check,
         );
-        (ecc_config, configs)
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (ecc_config, configs, instance)
     }
 
     fn synthesize(
@@ -266,14 +290,13 @@ impl Circuit<pallas::Base> for MyCircuit {
         mut layouter: impl Layouter<pallas::Base>,
     ) -> Result<(), Error> {
         let chip = EccChip::construct(config.0.clone());
-        let column  = chip.config().advices[0];     
         SinsemillaChip::<TestHashDomain, TestCommitDomain, TestFixedBases>::load(
             config.1.clone(),
             &mut layouter,
         )?;
         let affine_generator = pallas::Affine::generator();
 
-        let s_inv = pallas::Scalar::invert(&self.input_s).unwrap();
+        let s_inv = Option::from(pallas::Scalar::invert(&self.input_s)).ok_or(Error::Synthesis)?;
         let s_m_inv = pallas::Scalar::mul(&s_inv, &self.message);
         let scalar1 = pallas::Affine::mul(affine_generator, s_m_inv).to_affine();
         
@@ -295,23 +318,13 @@ impl Circuit<pallas::Base> for MyCircuit {
             Value::known(self.commitment),
         )?;
      
-        let s_r_inv = pallas::Scalar::mul(&s_inv, &self.input_r);
-        let s_r_inv_fp = pallas::Base::from_repr(s_r_inv.to_repr()).unwrap();
-        let base = chip.load_private(
-            layouter.namespace(|| "r * s_inv"), 
-            column, 
-            Value::known(s_r_inv_fp),
-        )?;
-        let scalar2 = ScalarVar::from_base(
-            chip.clone(), 
-            layouter.namespace(|| "r * s_inv"), 
-            &base,
-        )?;
-
-        let (p2,_) = NonIdentityPoint::mul(
-            &pub_key, 
-            layouter.namespace(|| "r * s_inv * G"), 
-            scalar2,
+        //r * s_inv is the public input
+        let p2 = group_eddsa::mul_scalar(
+            &chip,
+            layouter.namespace(|| "r * s_inv * G"),
+            &pub_key,
+            config.2,
+            0,
         )?;
 
         let p3 = Point::add(
@@ -330,236 +343,42 @@ impl Circuit<pallas::Base> for MyCircuit {
     }
 }
 
-fn main() {
-    let pallas_generator = pallas::Affine::generator();
-
-    //first, the player generate a public key, and they hold the key share
-    let key_share1 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    let key_share2 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    let key_share3 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    let key_share4 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    let key_share5 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    let input1 = Input{
-        key_share : key_share1,
-        rand_num : 379278,
-        output_max : 5,
-        output_min : 3,
-    };
-    let result1 = input1.output_key_share();
-    
-    let input2 = Input{
-        key_share : key_share2,
-        rand_num : 4812738974,
-        output_max : 5,
-        output_min : 3,
-    };
-    let result2 = input2.output_key_share();
-    
-    let input3 = Input{
-        key_share : key_share3,
-        rand_num : 43217,
-        output_max : 5,
-        output_min : 3,
-    };
-    let result3 = input3.output_key_share();
-    
-    let input4 = Input{
-        key_share : key_share4,
-        rand_num : 745,
-        output_max : 5,
-        output_min : 3,
-    };
-    let result4 = input4.output_key_share();
-    
-    let input5 = Input{
-        key_share : key_share5,
-        rand_num : 542,
-        output_max : 5,
-        output_min : 3,
-    };
-    let result5 = input5.output_key_share();
-    
-    let mut user_vec = Vec::new();
-    user_vec.extend(result1);
-    user_vec.extend(result2);
-    user_vec.extend(result3);
-    user_vec.extend(result4);
-    user_vec.extend(result5);
-
-    let user1 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
-        member : 5,
-        self_num : 1,
-    };
-    let (user1_prikey_share, user1_pubkey_share) = user1.collect();
-        
-    let user2 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
-        member : 5,
-        self_num : 2,
-    };
-    let (user2_prikey_share, user2_pubkey_share) = user2.collect();
-    
-    let user3 = CollectOutputKeyShare{
-        key_share : user_vec.clone(),
-        member : 5,
-        self_num : 3,
-    };
-    let (user3_prikey_share, user3_pubkey_share) = user3.collect();
-    
-    let pub_key_pre = CalculatePubKey {
-        degree : 3,
-        coefficient : [1,2,3].to_vec(),
-        pub_key : [user1_pubkey_share, user2_pubkey_share, user3_pubkey_share].to_vec(),
-    };
-
-    let user1_prikey_calculate= CalculatePriKey {
-        self_coefficient : 1,
-        coefficient : [2,3],
-        pri_key : user1_prikey_share,
-    };
-    let user1_prikey_share = user1_prikey_calculate.calculate();
-
-    let user2_prikey_calculate= CalculatePriKey {
-        self_coefficient : 2,
-        coefficient : [1,3],
-        pri_key : user2_prikey_share,
-    };
-    let user2_prikey_share = user2_prikey_calculate.calculate();
-
-    let user3_prikey_calculate= CalculatePriKey {
-        self_coefficient : 3,
-        coefficient : [1,2],
-        pri_key : user3_prikey_share,
-    };
-    let user3_prikey_share = user3_prikey_calculate.calculate();
-
-    //here we just check public key had been generate as we expect, it doesn't exist in the real project
-    let sum_key_share = pallas::Scalar::add(&user1_prikey_share, &user2_prikey_share);
-    let sum_key_share = pallas::Scalar::add(&sum_key_share, &user3_prikey_share);
-    let pri_key = key_share1 + key_share2 + key_share3 + key_share4 + key_share5;
-    let pri_key2 = pallas::Scalar::from_u128(pri_key);
-    let pri_key3 = pallas::Affine::mul(pallas_generator, &pri_key2).to_affine();
-    //only use pub_key in the next step
-    let pub_key = pub_key_pre.calculate();
-    let check1 = pallas::Affine::eq(&pri_key3, &pub_key);
-    assert_eq!(check1, true);
-    let check2 = pallas::Scalar::eq(&pri_key2, &sum_key_share);
-    assert_eq!(check2, true);
-
-    //then we move on to make the commitment for ecdsa
-    let allice_selfk = 564;
-    let allice_selfr = 345;
-    let allice_mta_pri_p = 35023;//should in u64
-    let allice_mta_pri_q = 46093;
-    let allice_mta_pub_n = allice_mta_pri_p * allice_mta_pri_q;
-    let mut gcd_check = gcd(allice_mta_pub_n, (allice_mta_pri_p-1)*(allice_mta_pri_q-1));
-    if gcd_check!= 1{
-        panic!("gcd !=1");
-    }
-    if (allice_selfk * allice_selfr) >= allice_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
-    }
-    assert!((allice_selfk*allice_selfr) < allice_mta_pub_n);
-
-    let bob_selfk = 687;
-    let bob_selfr = 466;
-    let bob_mta_pri_p = 37889;//should in u64
-    let bob_mta_pri_q = 55259;
-    let bob_mta_pub_n = bob_mta_pri_p * bob_mta_pri_q;
-    gcd_check = gcd(bob_mta_pub_n, (bob_mta_pri_p-1)*(bob_mta_pri_q-1));
-    if gcd_check!= 1{
-        panic!("gcd !=1");
-    }
-    if (bob_selfk * bob_selfr) >= bob_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
-    }
-    assert!((bob_selfk*bob_selfr) < bob_mta_pub_n);
-
-    let chris_selfk = 745;
-    let chris_selfr = 531;
-    let chris_mta_pri_p = 64663;//should in u64
-    let chris_mta_pri_q = 38113;
-    let chris_mta_pub_n = chris_mta_pri_p * chris_mta_pri_q;
-    gcd_check = gcd(chris_mta_pub_n, (chris_mta_pri_p-1)*(chris_mta_pri_q-1));
-    if gcd_check!= 1{
-        panic!("gcd !=1");
-    }
-    if (chris_selfk * chris_selfr) >= chris_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
-    }
-    assert!((chris_selfk*chris_selfr) < chris_mta_pub_n);
-
-    let selfk_vec = [allice_selfk, bob_selfk, chris_selfk].to_vec();
-    let selfr_vec = [allice_selfr, bob_selfr, chris_selfr].to_vec();
-    let mta_pub_n_vec = [allice_mta_pub_n, bob_mta_pub_n, chris_mta_pub_n].to_vec();
-    let mta_pri_p_vec = [allice_mta_pri_p, bob_mta_pri_p, chris_mta_pri_p].to_vec();
-    let mta_pri_q_vec = [allice_mta_pri_q, bob_mta_pri_q, chris_mta_pri_q].to_vec();
-
-    let (sharding_commitment, verify_point)= make_commitment(
-        selfk_vec.clone(),
-        selfr_vec.clone(),
-        mta_pub_n_vec.clone(),
-        mta_pri_p_vec.clone(),
-        mta_pri_q_vec.clone(),
-    );
-    
-    let mut commitment = pallas::Scalar::zero();
-    for i in &sharding_commitment{
-        commitment = pallas::Scalar::add(&commitment, i);
-    }
-
-    let commitment2 = pallas::Scalar::invert(&commitment).unwrap();
-    let mut commitment3 = pallas::Affine::identity();
-    for i in &verify_point{
-        commitment3 = pallas::Affine::add(commitment3, *i).to_affine();
-    };
-    let commitment4 = pallas::Affine::mul(commitment3, commitment2).to_affine();
-    let r = pallas::Affine::coordinates(&commitment4).unwrap().x().clone();
-
-    let message = generate_random_u128_in_range(1, std::u64::MAX as u128);
-    //k should use mta to calaulate as before, but as a demo, we just want to make sure it will function 
-    let k = allice_selfk + bob_selfk + chris_selfk;
-    let three = pallas::Scalar::from_u128(3);
-    let three_inv = pallas::Scalar::invert(&three).unwrap();
-    let message2 = pallas::Scalar::from_u128(message);
-    let message_div_3 = pallas::Scalar::mul(&message2, &three_inv);
-    let make_signature1 = MakeSignature2{
-        message : message_div_3,
-        k : k,
-        r : pallas::Scalar::from_repr(r.to_repr()).unwrap(),
-        w : user1_prikey_share,
-    };
-    let sharding_signature1 = make_signature1.make_signature2();
-
-    let make_signature2 = MakeSignature2{
-        message : message_div_3,
-        k : k,
-        r : pallas::Scalar::from_repr(r.to_repr()).unwrap(),
-        w : user2_prikey_share,
+//the signature check s_inv * (m + x * r) * G == R again in the circuit, for `myp verify --circuit`
+fn verify_in_circuit(
+    input_r : pallas::Scalar,
+    input_s : pallas::Scalar,
+    commitment : pallas::Affine,
+    message : pallas::Scalar,
+    pub_key : pallas::Affine,
+) -> bool {
+    let s_r_inv = match Option::from(pallas::Scalar::invert(&input_s)) {
+        Some(s_inv) => pallas::Scalar::mul(&s_inv, &input_r),
+        None => return false,
     };
-    let sharding_signature2 = make_signature2.make_signature2();
-
-    let make_signature3 = MakeSignature2{
-        message : message_div_3,
-        k : k,
-        r : pallas::Scalar::from_repr(r.to_repr()).unwrap(),
-        w : user3_prikey_share,
+    let public = match group_eddsa::split_scalar(s_r_inv) {
+        Some(public) => public.to_vec(),
+        None => return false,
     };
-    let sharding_signature3 = make_signature3.make_signature2();
-
-    let signature = pallas::Scalar::add(&sharding_signature1, &sharding_signature2);
-    let signature = pallas::Scalar::add(&signature, &sharding_signature3);
-
-    let k = 17;
     let circuit = MyCircuit {
-        input_r : pallas::Scalar::from_repr(r.to_repr()).unwrap(),
-        input_s : signature,
-        commitment : commitment4,
-        message : pallas::Scalar::from_u128(message),
-        pub_key :  pub_key,
+        input_s,
+        commitment,
+        message,
+        pub_key,
     };
+    match MockProver::run(17, &circuit, vec![public]) {
+        Ok(prover) => prover.verify().is_ok(),
+        Err(_) => false,
+    }
+}
 
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
-    assert_eq!(prover.verify(), Ok(()))
-}
\ No newline at end of file
+fn main() {
+    //every ceremony step is a subcommand reading its config and key material from files, see myp help
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::run(&args) {
+        Ok(output) => print!("{}", output),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
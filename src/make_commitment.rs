@@ -1,78 +1,76 @@
 use std::ops::Mul;
-use pasta_curves::{group::{cofactor::CofactorCurveAffine, ff::PrimeField, Curve}, pallas};
+use num_bigint::{BigUint, RandBigInt};
+use pasta_curves::{group::{cofactor::CofactorCurveAffine, Curve}, pallas};
 
 use crate::*;
-use myp::{Encrypt,EncryptAddMut,Decrypt};
+use crate::curve::{big_to_scalar, scalar_to_big};
+use crate::party::{check_mta_modulus, mta_mask_bound};
+use myp::{Encrypt,EncryptAddMut,Decrypt,Secret,ThresholdError};
+use rand::{CryptoRng, RngCore};
 
 //send cipher k
-struct FirstStep{
-    selfk : Vec<u128>,
-    mta_pub_n : Vec<u128>,
+struct FirstStep<'a>{
+    selfk : &'a [pallas::Scalar],
+    mta_pub_n : &'a [BigUint],
 }
 
-impl FirstStep {
-    fn encrypt_k(&self) -> Vec<u128>{
+impl FirstStep<'_> {
+    fn encrypt_k(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<(Vec<BigUint>, Vec<BigUint>), ThresholdError>{
         let mut random_r = Vec::new();
-        let mut iter = 0;
-        for i in self.mta_pub_n.clone(){
-            random_r.push(generate_random_u128_in_range(1, i));
-            if i > std::u64::MAX as u128{
-                panic!("pub_n should < u64")
-            }
+        for i in self.mta_pub_n{
+            random_r.push(random_unit(rng, i));
         }
 
         let mut encrypt_instance = Vec::new();
-        for (i, j) in self.selfk.iter().zip(&self.mta_pub_n) {
-            if i > j{
-                panic!("pub_n should < u64")
-            }
+        for ((i, j), rand) in self.selfk.iter().zip(self.mta_pub_n).zip(&random_r) {
             encrypt_instance.push(Encrypt {
-                mta_pub_n: *j,
-                rand: random_r[iter],
-                message: *i,
+                mta_pub_n: j.clone(),
+                rand: rand.clone(),
+                message: scalar_to_big(i),
             });
-            iter+=1;
         }
 
         let mut cipher_k = Vec::new();
         for i in encrypt_instance {
-            cipher_k.push(i.encrypt());
+            cipher_k.push(i.encrypt()?);
         }
 
-        cipher_k 
+        Ok((cipher_k, random_r))
     }
 }
 
+//the mta ciphers, the masks and the paillier randomness of every pair
+type MtaCipher = (Vec<BigUint>, Vec<BigUint>, Vec<BigUint>);
+
 //recive cipherk and generate cipher(k2*r1+c1) and keep -c1 as key
-struct SecondStep{
-    others_mta_pub_n : Vec<u128>,
-    others_cipher_k : Vec<u128>,
-    selfr : Vec<u128>,
-    selfk : Vec<u128>,
+struct SecondStep<'a>{
+    others_mta_pub_n : &'a [BigUint],
+    others_cipher_k : &'a [BigUint],
+    selfr : &'a [pallas::Scalar],
 }
 
-impl SecondStep {
-    fn cipher_k(&self) -> (Vec<u128>, Vec<u128>){
-        // let random_unm = generate_random_u128_in_range(1,  self.mta_pub_n);
+impl SecondStep<'_> {
+    fn cipher_k(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<MtaCipher, ThresholdError>{
+        let bound = mta_mask_bound::<pallas::Scalar>();
         let mut add_num_neg = Vec::new();
         let mut random_num2 = Vec::new();
         for _ in self.others_mta_pub_n.iter(){
-            for ((i, j), k) in self.others_mta_pub_n.iter().zip(&self.selfr).zip(&self.selfk){
-                add_num_neg.push(generate_random_u128_in_range(1, *j * *k));
-                random_num2.push(generate_random_u128_in_range(1, *i));
+            for i in self.others_mta_pub_n.iter(){
+                add_num_neg.push(rng.gen_biguint_below(&bound));
+                random_num2.push(random_unit(rng, i));
             }
         }
         
         let mut iter : usize = 0;
         let mut encrypt_instance = Vec::new();
-        for k in &self.selfr {
-            for (i, j) in self.others_mta_pub_n.iter().zip(&self.others_cipher_k) {
+        for k in self.selfr {
+            for (i, j) in self.others_mta_pub_n.iter().zip(self.others_cipher_k) {
                 encrypt_instance.push(EncryptAddMut {
-                    mta_pub_n : *i,
-                    cipher : *j,
-                    add_num : add_num_neg[iter],
-                    mut_num : *k,
-                    rand : random_num2[iter],
+                    mta_pub_n : i.clone(),
+                    cipher : j.clone(),
+                    add_num : add_num_neg[iter].clone(),
+                    mut_num : scalar_to_big(k),
+                    rand : random_num2[iter].clone(),
                 });
                 iter+=1;
             }
@@ -81,79 +79,67 @@ impl SecondStep {
         let mut cipher_k2w1_plus_rand = Vec::new();
      
         for i in encrypt_instance{
-            cipher_k2w1_plus_rand.push(i.mut_and_add());
+            cipher_k2w1_plus_rand.push(i.mut_and_add()?);
         }
         
-        (cipher_k2w1_plus_rand,  add_num_neg)
+        Ok((cipher_k2w1_plus_rand,  add_num_neg, random_num2))
     }
 }
 
 //decrypt cipher get k1*r2+c2
-struct ThirdStep{
-    input_p : Vec<u128>,
-    input_q : Vec<u128>,
-    cipher : Vec<u128>,
+struct ThirdStep<'a>{
+    input_p : &'a [BigUint],
+    input_q : &'a [BigUint],
+    cipher : &'a [BigUint],
 }
 
-impl ThirdStep {
-    fn decrypt_cipher(&self) -> Vec<u128>{
-        let mut pri_keyp = Vec::new();
-        let mut pri_keyq = Vec::new();
-        for (p, q) in self.input_p.iter().zip(&self.input_q){
-            pri_keyp.push(*p);
-            pri_keyq.push(*q);
-        }
-
+impl ThirdStep<'_> {
+    fn decrypt_cipher(&self) -> Result<Vec<BigUint>, ThresholdError>{
         let mut iter= 0;
         let mut encrypt_instance = Vec::new();
         
-        for i in &self.cipher {
+        for i in self.cipher {
             encrypt_instance.push( Decrypt{
-                pri_p : pri_keyp[iter],
-                pri_q : pri_keyq[iter],
-                cipher : *i,
+                pri_p : Secret::new(self.input_p[iter].clone()),
+                pri_q : Secret::new(self.input_q[iter].clone()),
+                cipher : i.clone(),
             });
             iter+=1;
-            if iter == pri_keyp.len(){
+            if iter == self.input_p.len(){
                 iter=0;
             }
         }
 
         let mut plain_text = Vec::new();
         for i in encrypt_instance{
-            plain_text.push(i.decrypt());
+            plain_text.push(i.decrypt()?);
         }
       
-        plain_text
+        Ok(plain_text)
     }
 }
 
-struct FourthStep{
-    plain_text : Vec<u128>,
-    selfk : Vec<u128>,
-    selfr : Vec<u128>,
-    add_num_neg : Vec<u128>,
+struct FourthStep<'a>{
+    plain_text : &'a [BigUint],
+    selfk : &'a [pallas::Scalar],
+    selfr : &'a [pallas::Scalar],
+    add_num_neg : &'a [BigUint],
 }
 
-impl FourthStep {
+impl FourthStep<'_> {
     fn combine(&self) -> (Vec<pallas::Scalar>, Vec<pallas::Affine>){
         let mut sharding_commitment = Vec::new();
         //k1r1
-        let mut ifq : pallas::Scalar;
-        let mut jfq : pallas::Scalar;
-        for (i ,j) in self.selfk.iter().zip(&self.selfr){
-            ifq = pallas::Scalar::from_u128(*i);
-            jfq = pallas::Scalar::from_u128(*j);
-            sharding_commitment.push(pallas::Scalar::mul(&ifq, &jfq));
+        for (i ,j) in self.selfk.iter().zip(self.selfr){
+            sharding_commitment.push(pallas::Scalar::mul(i, j));
         }
         
         //k1r1 + plentext - add_num_neg = (k1 + k2 + ....) * (w1 +w2 + ....)
         let mut player = 0;
         let mut raw = 0;
-        for i in &self.plain_text{
-            ifq = pallas::Scalar::from_u128(*i);
+        for i in self.plain_text{
             if player != raw{
-                sharding_commitment[player] = pallas::Scalar::add(&sharding_commitment[player], &ifq);
+                sharding_commitment[player] = pallas::Scalar::add(&sharding_commitment[player], &big_to_scalar(i));
             }
             player+=1;
             if player == self.selfk.len(){
@@ -164,10 +150,9 @@ impl FourthStep {
 
         player = 0;
         raw = 0;
-        for j in &self.add_num_neg{
-            jfq = pallas::Scalar::from_u128(*j);
+        for j in self.add_num_neg{
             if player != raw{
-                sharding_commitment[raw] = pallas::Scalar::sub(&sharding_commitment[raw], &jfq);
+                sharding_commitment[raw] = pallas::Scalar::sub(&sharding_commitment[raw], &big_to_scalar(j));
             }
             player+=1;
             if player == self.selfk.len(){
@@ -178,122 +163,310 @@ impl FourthStep {
         
         let mut verify_point = Vec::new();
         let affine_generator = pallas::Affine::generator();
-        #[allow(unused_assignments)]
-        let mut selfr_fq = pallas::Scalar::zero();
-        for i in &self.selfr{
-            selfr_fq = pallas::Scalar::from_u128(*i);
-            verify_point.push(pallas::Affine::mul(affine_generator, selfr_fq).to_affine());
+        for i in self.selfr{
+            verify_point.push(pallas::Affine::mul(affine_generator, i).to_affine());
         }
     
         (sharding_commitment, verify_point)
     }
 }
 
+//everything the parties sent or used in make_commitment, entry a * n + b of the n * n vecs is
+//party a's mta with party b: cipher of k_b * r_a + add_num_neg made by a, decrypted by b
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MtaTranscript{
+    pub cipher_k : Vec<BigUint>,
+    pub encrypt_rand : Vec<BigUint>,
+    pub cipher_mta : Vec<BigUint>,
+    pub add_num_neg : Vec<BigUint>,
+    pub mta_rand : Vec<BigUint>,
+    pub plain_text : Vec<BigUint>,
+    pub sharding_commitment : Vec<pallas::Scalar>,
+    pub verify_point : Vec<pallas::Affine>,
+}
+
 pub fn make_commitment(
-    selfk_vec : Vec<u128>,
-    selfr_vec : Vec<u128>,
-    mta_pub_n_vec : Vec<u128>,
-    mta_pri_p_vec : Vec<u128>,
-    mta_pri_q_vec : Vec<u128>,
-) -> (Vec<pallas::Scalar>, Vec<pasta_curves::EpAffine>){
-    //k * r should < n , because it will mod n
-    for ((i, j), k) in selfk_vec.iter().zip(&selfr_vec).zip(&mta_pub_n_vec){
-        if *i * * j >= *k{
-            panic!("k * r should < n , because it will mod n");
+    rng : &mut (impl CryptoRng + RngCore),
+    selfk_vec : &Secret<Vec<pallas::Scalar>>,
+    selfr_vec : &Secret<Vec<pallas::Scalar>>,
+    mta_pub_n_vec : &[BigUint],
+    mta_pri_p_vec : &Secret<Vec<BigUint>>,
+    mta_pri_q_vec : &Secret<Vec<BigUint>>,
+) -> Result<(Vec<pallas::Scalar>, Vec<pasta_curves::EpAffine>), ThresholdError>{
+    let transcript = make_commitment_transcript(
+        rng,
+        selfk_vec,
+        selfr_vec,
+        mta_pub_n_vec,
+        mta_pri_p_vec,
+        mta_pri_q_vec,
+    )?;
+    Ok((transcript.sharding_commitment, transcript.verify_point))
+}
+
+//the secrets are only borrowed, every step reads them in place instead of holding a copy
+pub fn make_commitment_transcript(
+    rng : &mut (impl CryptoRng + RngCore),
+    selfk_vec : &Secret<Vec<pallas::Scalar>>,
+    selfr_vec : &Secret<Vec<pallas::Scalar>>,
+    mta_pub_n_vec : &[BigUint],
+    mta_pri_p_vec : &Secret<Vec<BigUint>>,
+    mta_pri_q_vec : &Secret<Vec<BigUint>>,
+) -> Result<MtaTranscript, ThresholdError>{
+    let (selfk_vec, selfr_vec) = (selfk_vec.expose(), selfr_vec.expose());
+    let (mta_pri_p_vec, mta_pri_q_vec) = (mta_pri_p_vec.expose(), mta_pri_q_vec.expose());
+    let member = selfk_vec.len();
+    for found in [selfr_vec.len(), mta_pub_n_vec.len(), mta_pri_p_vec.len(), mta_pri_q_vec.len()] {
+        if found != member {
+            return Err(ThresholdError::LengthMismatch { expected: member, found });
         }
     }
+    //k * r + mask should < n , because it will mod n
+    for i in mta_pub_n_vec {
+        check_mta_modulus::<pallas::Scalar>(i)?;
+    }
 
     let step_1 = FirstStep{
-        selfk : selfk_vec.clone(),
-        mta_pub_n : mta_pub_n_vec.clone(),
+        selfk : selfk_vec,
+        mta_pub_n : mta_pub_n_vec,
     };
-    let cipher_k = step_1.encrypt_k();
+    let (cipher_k, encrypt_rand) = step_1.encrypt_k(rng)?;
 
     let step_2 = SecondStep{
         others_mta_pub_n : mta_pub_n_vec,
-        others_cipher_k : cipher_k,
-        selfr : selfr_vec.clone(),
-        selfk : selfk_vec.clone(),
+        others_cipher_k : &cipher_k,
+        selfr : selfr_vec,
     };
-    let (cipher_k2r1_plus_c1, neg_num) = step_2.cipher_k();
+    let (cipher_k2r1_plus_c1, neg_num, mta_rand) = step_2.cipher_k(rng)?;
  
     let step_3 = ThirdStep{
         input_p : mta_pri_p_vec,
         input_q : mta_pri_q_vec,
-        cipher : cipher_k2r1_plus_c1,
+        cipher : &cipher_k2r1_plus_c1,
     };
-    let plain_text = step_3.decrypt_cipher();
+    let plain_text = step_3.decrypt_cipher()?;
 
     let step_4 = FourthStep{
-        plain_text : plain_text,
+        plain_text : &plain_text,
         selfk : selfk_vec,
         selfr : selfr_vec,
-        add_num_neg : neg_num,
+        add_num_neg : &neg_num,
     };
     let (sharding_commitment, verify_point) = step_4.combine();
 
-    (sharding_commitment, verify_point)
+    Ok(MtaTranscript{
+        cipher_k,
+        encrypt_rand,
+        cipher_mta : cipher_k2r1_plus_c1,
+        add_num_neg : neg_num,
+        mta_rand,
+        plain_text,
+        sharding_commitment,
+        verify_point,
+    })
+}
+
+//when sum(sharding_commitment) doesn't give back G every party open k_i, r_i and the randomness
+//it used, then anyone can replay the four steps with the public paillier keys and find who lied
+pub struct CommitmentAudit{
+    pub selfk : Vec<pallas::Scalar>,
+    pub selfr : Vec<pallas::Scalar>,
+    pub mta_pub_n : Vec<BigUint>,
+    pub transcript : MtaTranscript,
+}
+
+impl CommitmentAudit{
+    //the index of every party with an inconsistent cipher, mask, plain text or commitment
+    pub fn audit(&self) -> Result<Vec<usize>, ThresholdError>{
+        let member = self.selfk.len();
+        let transcript = &self.transcript;
+        for found in [self.selfr.len(), self.mta_pub_n.len(), transcript.cipher_k.len(),
+            transcript.encrypt_rand.len(), transcript.sharding_commitment.len(), transcript.verify_point.len()] {
+            if found != member {
+                return Err(ThresholdError::LengthMismatch { expected: member, found });
+            }
+        }
+        for found in [transcript.cipher_mta.len(), transcript.add_num_neg.len(),
+            transcript.mta_rand.len(), transcript.plain_text.len()] {
+            if found != member * member {
+                return Err(ThresholdError::LengthMismatch { expected: member * member, found });
+            }
+        }
+
+        //a modulus this small could wrap an honest k_b * r_a + mask, that is a parameter error
+        //and not something to blame a party for
+        for i in &self.mta_pub_n {
+            check_mta_modulus::<pallas::Scalar>(i)?;
+        }
+        let bound = mta_mask_bound::<pallas::Scalar>();
+
+        let mut cheater = vec![false; member];
+        //first step, b encrypt k_b with its own key
+        for (b, lied) in cheater.iter_mut().enumerate() {
+            let cipher = Encrypt {
+                mta_pub_n : self.mta_pub_n[b].clone(),
+                rand : transcript.encrypt_rand[b].clone(),
+                message : scalar_to_big(&self.selfk[b]),
+            }.encrypt();
+            if cipher.as_ref() != Ok(&transcript.cipher_k[b]) {
+                *lied = true;
+            }
+        }
+
+        //second and third step, a mul r_a and add its mask to b's cipher, b decrypt it
+        for a in 0..member {
+            for b in 0..member {
+                if a == b {
+                    continue;
+                }
+                let t = a * member + b;
+                let cipher = EncryptAddMut {
+                    mta_pub_n : self.mta_pub_n[b].clone(),
+                    cipher : transcript.cipher_k[b].clone(),
+                    add_num : transcript.add_num_neg[t].clone(),
+                    mut_num : scalar_to_big(&self.selfr[a]),
+                    rand : transcript.mta_rand[t].clone(),
+                }.mut_and_add();
+                //a mask below the bound can't wrap mod n_b, so the plain text is k_b * r_a + mask
+                let plain = scalar_to_big(&self.selfk[b]) * scalar_to_big(&self.selfr[a]) + &transcript.add_num_neg[t];
+                if cipher.as_ref() != Ok(&transcript.cipher_mta[t]) || transcript.add_num_neg[t] >= bound {
+                    cheater[a] = true;
+                } else if plain != transcript.plain_text[t] {
+                    cheater[b] = true;
+                }
+            }
+        }
+
+        //fourth step with the opened values, every party's share and r_i * G
+        let step_4 = FourthStep{
+            plain_text : &transcript.plain_text,
+            selfk : &self.selfk,
+            selfr : &self.selfr,
+            add_num_neg : &transcript.add_num_neg,
+        };
+        let (sharding_commitment, verify_point) = step_4.combine();
+        for i in 0..member {
+            if sharding_commitment[i] != transcript.sharding_commitment[i]
+                || verify_point[i] != transcript.verify_point[i] {
+                cheater[i] = true;
+            }
+        }
+
+        Ok((0..member).filter(|i| cheater[*i]).collect())
+    }
 }
 
 #[cfg(test)]
 mod tests{
     use super::*;
-    #[test]
-    fn text(){
-    //k * r should < n , because it will mod n
-    let allice_selfk = 564;
-    let allice_selfr = 345;
-    let allice_mta_pri_p = 37057;
-    let allice_mta_pri_q = 55021;
-    let allice_mta_pub_n = allice_mta_pri_p * allice_mta_pri_q;
-    if (allice_selfk * allice_selfr) >= allice_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
-    }
-    assert!((allice_selfk*allice_selfr) < allice_mta_pub_n);
-
-    let bob_selfk = 687;
-    let bob_selfr = 466;
-    let bob_mta_pri_p = 45497;
-    let bob_mta_pri_q = 61363;
-    let bob_mta_pub_n = bob_mta_pri_p * bob_mta_pri_q;
-    if (bob_selfk * bob_selfr) >= bob_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
-    }
-    assert!((bob_selfk*bob_selfr) < bob_mta_pub_n);
-
-    let chris_selfk = 745;
-    let chris_selfr = 531;
-    let chris_mta_pri_p = 58237;
-    let chris_mta_pri_q = 50129;
-    let chris_mta_pub_n = chris_mta_pri_p * chris_mta_pri_q;
-    if (chris_selfk * chris_selfr) >= chris_mta_pub_n {
-        panic!("k * r should < n , because it will mod n");
+    use ff::Field;
+    use crate::paillier_modulus::test_paillier_key;
+
+    //k, r and the paillier keys of three parties
+    fn setup() -> (Vec<pallas::Scalar>, Vec<pallas::Scalar>, Vec<BigUint>, Vec<BigUint>) {
+        let mut rng = rand::thread_rng();
+        let selfk = (0..3).map(|_| pallas::Scalar::random(&mut rng)).collect();
+        let selfr = (0..3).map(|_| pallas::Scalar::random(&mut rng)).collect();
+        let (pri_p, pri_q) = (0..3).map(test_paillier_key).unzip();
+        (selfk, selfr, pri_p, pri_q)
     }
-    assert!((chris_selfk*chris_selfr) < chris_mta_pub_n);
 
-    let selfk_vec = [allice_selfk, bob_selfk, chris_selfk].to_vec();
-    let selfr_vec = [allice_selfr, bob_selfr, chris_selfr].to_vec();
-    let mta_pub_n_vec = [allice_mta_pub_n, bob_mta_pub_n, chris_mta_pub_n].to_vec();
-    let mta_pri_p_vec = [allice_mta_pri_p, bob_mta_pri_p, chris_mta_pri_p].to_vec();
-    let mta_pri_q_vec = [allice_mta_pri_q, bob_mta_pri_q, chris_mta_pri_q].to_vec();
+    #[test]
+    fn text(){
+    let (selfk_vec, selfr_vec, mta_pri_p_vec, mta_pri_q_vec) = setup();
+    let mta_pub_n_vec: Vec<BigUint> = mta_pri_p_vec.iter().zip(&mta_pri_q_vec).map(|(p, q)| p * q).collect();
 
     let (sharding_commitment, _) = make_commitment(
-        selfk_vec,
-        selfr_vec,
-        mta_pub_n_vec,
-        mta_pri_p_vec,
-        mta_pri_q_vec
+        &mut rand::thread_rng(),
+        &Secret::new(selfk_vec.clone()),
+        &Secret::new(selfr_vec.clone()),
+        &mta_pub_n_vec,
+        &Secret::new(mta_pri_p_vec.clone()),
+        &Secret::new(mta_pri_q_vec.clone()),
+    ).unwrap();
+
+    let v1: pallas::Scalar = selfk_vec.iter().sum();
+    let v2: pallas::Scalar = selfr_vec.iter().sum();
+    let v4: pallas::Scalar = sharding_commitment.iter().sum();
+    assert_eq!(v1 * v2, v4);
+
+    //a small n would let k * r + mask wrap, it is rejected before any cipher is sent
+    let small_n = BigUint::from(37057u32 * 55021);
+    let result = make_commitment(
+        &mut rand::thread_rng(),
+        &Secret::new(selfk_vec[..2].to_vec()),
+        &Secret::new(selfr_vec[..2].to_vec()),
+        &[mta_pub_n_vec[0].clone(), small_n.clone()],
+        &Secret::new(mta_pri_p_vec[..2].to_vec()),
+        &Secret::new(mta_pri_q_vec[..2].to_vec()),
     );
-    
+    assert_eq!(result, Err(ThresholdError::InvalidModulus(small_n)));
 
-    let v1 = allice_selfk + bob_selfk + chris_selfk;
-    let v2 = allice_selfr + bob_selfr + chris_selfr;
-    let v3 = v1 * v2;
-    let mut v4 = pallas::Scalar::zero();
-    for i in &sharding_commitment{
-        v4 = pallas::Scalar::add(&v4, i);
-    }
-    let v5 = pallas::Scalar::from_u128(v3);
-    assert_eq!(v5, v4)
+    let result = make_commitment(
+        &mut rand::thread_rng(),
+        &Secret::new(selfk_vec[..2].to_vec()),
+        &Secret::new(selfr_vec[..1].to_vec()),
+        &mta_pub_n_vec[..2],
+        &Secret::new(mta_pri_p_vec[..2].to_vec()),
+        &Secret::new(mta_pri_q_vec[..2].to_vec()),
+    );
+    assert_eq!(result, Err(ThresholdError::LengthMismatch { expected: 2, found: 1 }));
 }
+
+    #[test]
+    fn audit_test(){
+        let (selfk, selfr, pri_p, pri_q) = setup();
+        let pub_n: Vec<BigUint> = pri_p.iter().zip(&pri_q).map(|(p, q)| p * q).collect();
+        let transcript = make_commitment_transcript(
+            &mut rand::thread_rng(),
+            &Secret::new(selfk.clone()),
+            &Secret::new(selfr.clone()),
+            &pub_n,
+            &Secret::new(pri_p),
+            &Secret::new(pri_q),
+        ).unwrap();
+
+        let honest = CommitmentAudit{
+            selfk : selfk.clone(),
+            selfr : selfr.clone(),
+            mta_pub_n : pub_n.clone(),
+            transcript : transcript.clone(),
+        };
+        assert_eq!(honest.audit(), Ok(Vec::new()));
+
+        //party 1 open a mask it didn't use in its mta with party 0
+        let mut bad = transcript.clone();
+        bad.add_num_neg[3] += 1u32;
+        let audit = CommitmentAudit{ transcript : bad, ..honest };
+        assert_eq!(audit.audit(), Ok([1].to_vec()));
+
+        //party 1 use a mask above the bound, it could wrap mod n_0 and hide k_0 * r_1 no more
+        let mut bad = transcript.clone();
+        bad.add_num_neg[3] = mta_mask_bound::<pallas::Scalar>();
+        let audit = CommitmentAudit{ transcript : bad, ..audit };
+        assert_eq!(audit.audit(), Ok([1].to_vec()));
+
+        //party 2 report a wrong plain text for party 0's mta
+        let mut bad = transcript.clone();
+        bad.plain_text[2] += 1u32;
+        let audit = CommitmentAudit{ transcript : bad, ..audit };
+        assert_eq!(audit.audit(), Ok([2].to_vec()));
+
+        //party 0 publish a share that doesn't come from its plain text and masks
+        let mut bad = transcript.clone();
+        bad.sharding_commitment[0] = pallas::Scalar::add(&bad.sharding_commitment[0], &pallas::Scalar::one());
+        let audit = CommitmentAudit{ transcript : bad, ..audit };
+        assert_eq!(audit.audit(), Ok([0].to_vec()));
+
+        //party 2 open a different k than the one it encrypted
+        let mut wrong_k = selfk.clone();
+        wrong_k[2] += pallas::Scalar::one();
+        let audit = CommitmentAudit{ selfk : wrong_k, transcript : transcript.clone(), ..audit };
+        assert_eq!(audit.audit(), Ok([2].to_vec()));
+
+        //a modulus too small for the mask is reported, nobody is blamed for it
+        let small_n = BigUint::from(37057u32 * 55021);
+        let audit = CommitmentAudit{ selfk, mta_pub_n : vec![pub_n[0].clone(), small_n.clone(), pub_n[2].clone()], ..audit };
+        assert_eq!(audit.audit(), Err(ThresholdError::InvalidModulus(small_n)));
+    }
 }
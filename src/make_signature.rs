@@ -1,68 +1,72 @@
-use crate::generate_random_u128_in_range;
-use myp::{Encrypt,EncryptAddMut,Decrypt};
-use pasta_curves::{group::ff::PrimeField, pallas};
+use num_bigint::{BigUint, RandBigInt};
+use crate::random_unit;
+use crate::curve::{big_to_scalar, scalar_to_big};
+use crate::party::{check_mta_modulus, mta_mask_bound};
+use myp::{Encrypt,EncryptAddMut,Decrypt,Secret,ThresholdError};
+use rand::{CryptoRng, RngCore};
+use pasta_curves::pallas;
 //encrypt k and send
-struct FirstStep{
-    selfk : Vec<u128>,
-    mta_pub_n : Vec<u128>,
+struct FirstStep<'a>{
+    selfk : &'a [pallas::Scalar],
+    mta_pub_n : &'a [BigUint],
 }
 //out put = n
-impl FirstStep {
-    fn encrypt_k(&self) -> Vec<u128>{
+impl FirstStep<'_> {
+    fn encrypt_k(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<Vec<BigUint>, ThresholdError>{
         let mut random_r = Vec::new();
-        let mut iter = 0;
-        for i in self.mta_pub_n.clone(){
-            random_r.push(generate_random_u128_in_range(1, i));
+        for i in self.mta_pub_n{
+            //k * w + mask should < n , because it will mod n
+            check_mta_modulus::<pallas::Scalar>(i)?;
+            random_r.push(random_unit(rng, i));
         }
 
         let mut encrypt_instance = Vec::new();
-        for (i, j) in self.selfk.iter().zip(&self.mta_pub_n) {
+        for ((i, j), rand) in self.selfk.iter().zip(self.mta_pub_n).zip(random_r) {
             encrypt_instance.push(Encrypt {
-                mta_pub_n: *j,
-                rand: random_r[iter],
-                message: *i,
+                mta_pub_n: j.clone(),
+                rand,
+                message: scalar_to_big(i),
             });
-            iter+=1;
         }
 
         let mut cipher_k = Vec::new();
         for i in encrypt_instance {
-            cipher_k.push(i.encrypt());
+            cipher_k.push(i.encrypt()?);
         }
 
-        cipher_k 
+        Ok(cipher_k)
     }
 }
 
 //recive cipherk and generate cipher(k2*r1+c1) and keep -c1 as key
-struct SecondStep{
-    others_mta_pub_n : Vec<u128>,//lens = n
-    others_cipher_k : Vec<u128>,//lens = n
-    selfw : Vec<u128>,//lens = n
+struct SecondStep<'a>{
+    others_mta_pub_n : &'a [BigUint],//lens = n
+    others_cipher_k : &'a [BigUint],//lens = n
+    selfw : &'a [pallas::Scalar],//lens = n
 }
 //lens =(n*n, n*n)
-impl SecondStep {
-    fn cipher_k(&self) -> (Vec<u128>, Vec<u128>){
-        // let random_unm = generate_random_u128_in_range(1,  self.mta_pub_n);
+impl SecondStep<'_> {
+    fn cipher_k(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<(Vec<BigUint>, Vec<BigUint>), ThresholdError>{
+        let bound = mta_mask_bound::<pallas::Scalar>();
         let mut add_num_neg = Vec::new();
         let mut random_num2 = Vec::new();
         for _ in self.others_mta_pub_n.iter(){
-            for (i, j) in self.others_mta_pub_n.iter().zip(&self.selfw){
-                add_num_neg.push(generate_random_u128_in_range(1, *j));
-                random_num2.push(generate_random_u128_in_range(1, *i));
+            for i in self.others_mta_pub_n.iter(){
+                add_num_neg.push(rng.gen_biguint_below(&bound));
+                random_num2.push(random_unit(rng, i));
             }
         }
         
         let mut iter : usize = 0;
         let mut encrypt_instance = Vec::new();
-        for k in &self.selfw {
-            for (i, j) in self.others_mta_pub_n.iter().zip(&self.others_cipher_k) {
+        for k in self.selfw {
+            for (i, j) in self.others_mta_pub_n.iter().zip(self.others_cipher_k) {
                 encrypt_instance.push(EncryptAddMut {
-                    mta_pub_n : *i,
-                    cipher : *j,
-                    add_num : add_num_neg[iter],
-                    mut_num : *k,
-                    rand : random_num2[iter],
+                    mta_pub_n : i.clone(),
+                    cipher : j.clone(),
+                    add_num : add_num_neg[iter].clone(),
+                    mut_num : scalar_to_big(k),
+                    rand : random_num2[iter].clone(),
                 });
                 iter+=1;
             }
@@ -72,67 +76,61 @@ impl SecondStep {
         let mut cipher_k2w1_plus_rand = Vec::new();
      
         for i in encrypt_instance{
-            cipher_k2w1_plus_rand.push(i.mut_and_add());
+            cipher_k2w1_plus_rand.push(i.mut_and_add()?);
         }
         
-        (cipher_k2w1_plus_rand,  add_num_neg)
+        Ok((cipher_k2w1_plus_rand,  add_num_neg))
     }
 }
 
 //decrypt cipher get k1*r2+c2
-struct ThirdStep{
-    pri_p : Vec<u128>,//n
-    pri_q : Vec<u128>,//n
-    cipher : Vec<u128>,//n*n
+struct ThirdStep<'a>{
+    pri_p : &'a [BigUint],//n
+    pri_q : &'a [BigUint],//n
+    cipher : Vec<BigUint>,//n*n
 }
 //out put = n*(n-1)
-impl ThirdStep {
-    fn decrypt_cipher(&self) -> Vec<u128>{
-        let mut pri_keyp = Vec::new();
-        let mut pri_keyq = Vec::new();
-        for (p, q) in self.pri_p.iter().zip(&self.pri_q){
-            pri_keyp.push(*p);
-            pri_keyq.push(*q);
-        }
-
+impl ThirdStep<'_> {
+    fn decrypt_cipher(&self) -> Result<Vec<BigUint>, ThresholdError>{
         let mut iter= 0;
         let mut encrypt_instance = Vec::new();
         
         for i in &self.cipher {
             encrypt_instance.push( Decrypt{
-                pri_p : pri_keyp[iter],
-                pri_q : pri_keyq[iter],
-                cipher : *i,
+                pri_p : Secret::new(self.pri_p[iter].clone()),
+                pri_q : Secret::new(self.pri_q[iter].clone()),
+                cipher : i.clone(),
             });
             iter+=1;
-            if iter == pri_keyp.len(){
+            if iter == self.pri_p.len(){
                 iter=0;
             }
         }
 
         let mut plain_text = Vec::new();
         for i in encrypt_instance{
-            plain_text.push(i.decrypt());
+            plain_text.push(i.decrypt()?);
         }
       
-        plain_text
+        Ok(plain_text)
     }
 }
 
-struct FourthStep{
+struct FourthStep<'a>{
     plain_text : Vec<pallas::Scalar>,//n*n
-    selfk : Vec<pallas::Scalar>,//n
-    selfw : Vec<pallas::Scalar>,
+    selfk : &'a [pallas::Scalar],//n
+    selfw : &'a [pallas::Scalar],
     add_num_neg : Vec<pallas::Scalar>,//n*n
     message : pallas::Scalar,
     r : pallas::Scalar,
 }
 //out put n
-impl FourthStep {
-    fn combine(&self) -> Vec<pallas::Scalar>{
+impl FourthStep<'_> {
+    fn combine(&self) -> Vec<SignatureShare>{
         let mut sharding_signature = Vec::new();
         //k1w1
-        for (i ,j) in self.selfk.iter().zip(&self.selfw){
+        let (selfk, selfw) = (self.selfk, self.selfw);
+        for (i ,j) in selfk.iter().zip(selfw){
             sharding_signature.push(pallas::Scalar::mul(i, j));
         }
         
@@ -145,7 +143,7 @@ impl FourthStep {
                 sharding_signature[player] = pallas::Scalar::add(&sharding_signature[player], i);
             }
             player+=1;
-            if player == self.selfk.len(){
+            if player == selfk.len(){
                 player=0;
                 raw+=1;
             }
@@ -158,101 +156,114 @@ impl FourthStep {
                 sharding_signature[raw] = pallas::Scalar::sub(&sharding_signature[raw], j);
             }
             player+=1;
-            if player == self.selfk.len(){
+            if player == selfk.len(){
                 player=0;
                 raw+=1;
             }
         }
         
         //mk + rkx
-        player = 0;
-        let mut mk : pallas::Scalar;
-        for i in &self.selfk{
-            sharding_signature[player] = pallas::Scalar::mul(&sharding_signature[player], &self.r);
-            mk = pallas::Scalar::mul(i, &self.message);
-            sharding_signature[player] = pallas::Scalar::add(&sharding_signature[player], &mk);
-            player+=1;
+        let mut share = Vec::new();
+        for (i, sigma) in selfk.iter().zip(sharding_signature){
+            let mk = pallas::Scalar::mul(i, &self.message);
+            share.push(SignatureShare {
+                s : pallas::Scalar::add(&pallas::Scalar::mul(&sigma, &self.r), &mk),
+                k : *i,
+                sigma,
+            });
         }
-        sharding_signature
+        share
     }
 }
 
+//s = m * k + r * sigma, sigma is the party's share of k * x from mta
+//k and sigma stay with the party, they are only used if the group has to find a bad s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureShare<F = pallas::Scalar>{
+    pub s : F,
+    pub k : F,
+    pub sigma : F,
+}
+
 pub struct MakeSignature{
-    pub selfk : Vec<u128>,
-    pub mta_pub_n : Vec<u128>,
-    pub selfw : Vec<u128>,//lens = n
-    pub pri_p : Vec<u128>,//n
-    pub pri_q : Vec<u128>,//n
-    pub message : u128,
+    pub selfk : Secret<Vec<pallas::Scalar>>,
+    pub mta_pub_n : Vec<BigUint>,
+    pub selfw : Secret<Vec<pallas::Scalar>>,//lens = n
+    pub pri_p : Secret<Vec<BigUint>>,//n
+    pub pri_q : Secret<Vec<BigUint>>,//n
+    pub message : pallas::Scalar,
     pub r : pallas::Scalar,
 }
 
 impl MakeSignature{
-    pub fn make_signature(&self) -> Vec<pallas::Scalar> {
+    pub fn make_signature(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<Vec<pallas::Scalar>, ThresholdError> {
+        Ok(self.make_signature_share(rng)?.iter().map(|i| i.s).collect())
+    }
+
+    pub fn make_signature_share(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<Vec<SignatureShare>, ThresholdError> {
+        let (selfk, selfw) = (self.selfk.expose(), self.selfw.expose());
+        let member = selfk.len();
+        for found in [self.mta_pub_n.len(), selfw.len(), self.pri_p.expose().len(), self.pri_q.expose().len()] {
+            if found != member {
+                return Err(ThresholdError::LengthMismatch { expected: member, found });
+            }
+        }
         let step1 = FirstStep{
-            selfk : self.selfk.clone(),
-            mta_pub_n : self.mta_pub_n.clone(),
+            selfk,
+            mta_pub_n : &self.mta_pub_n,
         };
-        let cipher_k = step1.encrypt_k();
+        let cipher_k = step1.encrypt_k(rng)?;
 
         let step2 = SecondStep{
-            others_mta_pub_n : self.mta_pub_n.clone(),
-            others_cipher_k : cipher_k,
-            selfw : self.selfw.clone(),
+            others_mta_pub_n : &self.mta_pub_n,
+            others_cipher_k : &cipher_k,
+            selfw,
         };
-        let (cipher_k2w1_plus_rand, add_nun_key) =  step2.cipher_k();
+        let (cipher_k2w1_plus_rand, add_nun_key) =  step2.cipher_k(rng)?;
 
         let step3 = ThirdStep{
-            pri_p : self.pri_p.clone(),
-            pri_q : self.pri_q.clone(),
+            pri_p : self.pri_p.expose(),
+            pri_q : self.pri_q.expose(),
             cipher : cipher_k2w1_plus_rand,
             };
-        let plentext = step3.decrypt_cipher();
+        let plentext = step3.decrypt_cipher()?;
 
         let mut plentext2 = Vec::new();
-        for i in plentext{
-            plentext2.push(pallas::Scalar::from_u128(i));
-        }
-        let mut kfq = Vec::new();
-        for i in &self.selfk{
-            kfq.push(pallas::Scalar::from_u128(*i));
-        }
-        let mut wfq = Vec::new();
-        for i in &self.selfw{
-            wfq.push(pallas::Scalar::from_u128(*i));
+        for i in &plentext{
+            plentext2.push(big_to_scalar(i));
         }
         let mut add_nun_key2 = Vec::new();
-        for i in add_nun_key{
-            add_nun_key2.push(pallas::Scalar::from_u128(i));
+        for i in &add_nun_key{
+            add_nun_key2.push(big_to_scalar(i));
         }
 
         let step4 = FourthStep{
             plain_text : plentext2,
-            selfk : kfq,
-            selfw : wfq,
+            selfk,
+            selfw,
             add_num_neg : add_nun_key2,
-            message : pallas::Scalar::from_u128(self.message),
+            message : self.message,
             r : self.r,
         };
         let result = step4.combine();
 
-        result
+        Ok(result)
     }
 }
 
 pub struct MakeSignature2{
     pub message :  pallas::Scalar,
-    pub k : u128,
+    pub k : Secret<pallas::Scalar>,
     pub r : pallas::Scalar,
-    pub w : pallas::Scalar,
+    pub w : Secret<pallas::Scalar>,
 }
 
 impl MakeSignature2{
     pub fn make_signature2(&self) -> pallas::Scalar{
-        let k = pallas::Scalar::from_u128(self.k);
-        let wr = pallas::Scalar::mul(&self.w, &self.r);
+        let k = &self.k;
+        let wr = pallas::Scalar::mul(self.w.expose(), &self.r);
         let m_plus_wr = pallas::Scalar::add(&self.message,&wr);
-        let k_m_plus_wr = pallas::Scalar::mul(&m_plus_wr,& k);
+        let k_m_plus_wr = pallas::Scalar::mul(&m_plus_wr, k.expose());
 
         k_m_plus_wr
     }
@@ -261,49 +272,37 @@ impl MakeSignature2{
 #[cfg(test)]
 mod tests{
     use super::*;
+    use ff::Field;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::paillier_modulus::test_paillier_key;
     #[test]
     fn text(){
-        let ap = 37057;
-        let bp = 45497;
-        let cp = 58237;
+        let mut rng = rand::thread_rng();
+        let (pri_p, pri_q): (Vec<BigUint>, Vec<BigUint>) = (0..3).map(test_paillier_key).unzip();
+        let mta_pub_n = pri_p.iter().zip(&pri_q).map(|(p, q)| p * q).collect();
 
-        let aq = 55021;
-        let bq = 61363;
-        let cq = 50129;
+        let k: Vec<pallas::Scalar> = (0..3).map(|_| pallas::Scalar::random(&mut rng)).collect();
+        let w: Vec<pallas::Scalar> = (0..3).map(|_| pallas::Scalar::random(&mut rng)).collect();
 
-        let an = ap*aq;
-        let bn = bp*bq;
-        let cn = cp*cq;
-
-        let ak = generate_random_u128_in_range(1, std::u8::MAX as u128);
-        let bk = generate_random_u128_in_range(1, std::u8::MAX as u128);
-        let ck = generate_random_u128_in_range(1, std::u8::MAX as u128);
-
-        let aw = generate_random_u128_in_range(1, std::u8::MAX as u128);
-        let bw = generate_random_u128_in_range(1, std::u8::MAX as u128);
-        let cw = generate_random_u128_in_range(1, std::u8::MAX as u128);
-
-        let message = generate_random_u128_in_range(1, std::u64::MAX as u128);
-        let r = generate_random_u128_in_range(1, std::u64::MAX as u128);
-        let r2 = pallas::Scalar::from_u128(r);
+        let message = pallas::Scalar::random(&mut rng);
+        let r = pallas::Scalar::random(&mut rng);
+        let seed = rand::Rng::gen::<u64>(&mut rng);
 
         let instence = MakeSignature{
-            selfk : [ak, bk, ck].to_vec(),
-            mta_pub_n : [an, bn, cn].to_vec(),
-            selfw : [aw, bw, cw].to_vec(),
-            pri_p : [ap, bp, cp].to_vec(),
-            pri_q : [aq, bq, cq].to_vec(),
-            message : message,
-            r : r2,
+            selfk : Secret::new(k.clone()),
+            mta_pub_n,
+            selfw : Secret::new(w.clone()),
+            pri_p : Secret::new(pri_p),
+            pri_q : Secret::new(pri_q),
+            message,
+            r,
         };
 
-        let result = instence.make_signature();
-        let mut ans = pallas::Scalar::zero();
-        for i in &result{
-            ans = pallas::Scalar::add(&ans, i);
-        } 
-        let check = message * (ak + bk + ck) + r * (ak + bk + ck) * (aw + bw + cw);
-        let check2 = pallas::Scalar::from_u128(check);
-        assert_eq!(check2, ans);
+        let result = instence.make_signature(&mut StdRng::seed_from_u64(seed)).unwrap();
+        //every mask and paillier randomness come from the rng, the same seed give the same shares
+        assert_eq!(instence.make_signature(&mut StdRng::seed_from_u64(seed)).unwrap(), result);
+        let ans: pallas::Scalar = result.iter().sum();
+        let (k, w): (pallas::Scalar, pallas::Scalar) = (k.iter().sum(), w.iter().sum());
+        assert_eq!(message * k + r * k * w, ans);
     }
-}
\ No newline at end of file
+}
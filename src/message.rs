@@ -0,0 +1,878 @@
+use num_bigint::{BigInt, BigUint};
+use num_traits::Zero;
+use pasta_curves::group::{ff::PrimeField, GroupEncoding};
+use pasta_curves::pallas;
+use myp::ThresholdError;
+use crate::curve::EcdsaCurve;
+use crate::identifiable_abort::{DleqProof, ShareCheck};
+use crate::identity::IdentitySignature;
+use crate::paillier_modulus::{NoSmallFactorProof, NoSmallFactorRound, PaillierBlumProof, PaillierModulusProof};
+use crate::party::{Round1Msg, Round2Msg, Round3Msg, Round4Msg};
+use crate::ring_pedersen::{RingPedersenParams, RingPedersenProof};
+
+//every message on the bus is
+//version u8 | session id 32 bytes | from u32 | to u32 (u32::MAX for broadcast) | tag u8 | payload
+//integers are big endian, vec and big integer have a u32 length first, a scalar is its 32 bytes repr
+//and a point is its compressed form, decode reject anything that isn't the canonical encoding.
+//the key ceremony and the signing rounds 3 and 4 are on the curve of the Payload, 33 bytes a point on
+//secp256k1 and P-256, the bip340 rounds are always on secp256k1 and everything else is on pallas
+pub const MESSAGE_VERSION: u8 = 5;
+const BROADCAST: u32 = u32::MAX;
+
+pub type SessionId = [u8; 32];
+
+//key ceremony, p_i(to + 1) from DealScalarShare, point to point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyShareMsg<F = pallas::Scalar> {
+    pub from : usize,
+    pub to : usize,
+    pub key_share : F,
+}
+
+//key ceremony, a_ij * G for every coefficient of the sender's polynomial
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyCommitMsg<P = pallas::Affine> {
+    pub from : usize,
+    pub commitment : Vec<P>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingPedersenMsg {
+    pub from : usize,
+    pub params : RingPedersenParams,
+    pub proof : RingPedersenProof,
+}
+
+//the no small factor part is made with the receiver's ring pedersen params, so it is point to point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaillierModulusMsg {
+    pub from : usize,
+    pub to : usize,
+    pub mta_pub_n : BigUint,
+    pub proof : PaillierModulusProof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShareCheckMsg<P: EcdsaCurve = pallas::Affine> {
+    pub from : usize,
+    pub check : ShareCheck<P>,
+}
+
+//threshold eddsa partial (R_i, s_i)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EddsaPartialMsg {
+    pub from : usize,
+    pub commitment : pallas::Affine,
+    pub s : pallas::Scalar,
+}
+
+//bip340 signing, the two nonce points D_i and E_i, see bip340.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bip340CommitMsg {
+    pub from : usize,
+    pub hiding : k256::AffinePoint,
+    pub binding : k256::AffinePoint,
+}
+
+//bip340 signing, s_i over the commitment of every signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bip340ShareMsg {
+    pub from : usize,
+    pub s : k256::Scalar,
+}
+
+//a point to point message encrypted to the receiver's identity key and signed by the sender, see channel.rs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedMsg {
+    pub from : usize,
+    pub to : usize,
+    pub ephemeral : pallas::Affine,
+    pub ciphertext : Vec<u8>,
+    pub signature : IdentitySignature,
+}
+
+//a broadcast message, body is the encoded inner Message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedMsg {
+    pub from : usize,
+    pub body : Vec<u8>,
+    pub signature : IdentitySignature,
+}
+
+//from pass on signed, a broadcast it got from signed.from, so the others can compare
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoMsg {
+    pub from : usize,
+    pub signed : SignedMsg,
+}
+
+//reshare, f_i(to) where f_i(0) is lambda_i times the sender's old key share, point to point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReshareMsg<F = pallas::Scalar> {
+    pub from : usize,
+    pub to : usize,
+    pub share : F,
+}
+
+//reshare, a_ij * G for every coefficient of f_i, so a receiver can check its share
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReshareCommitMsg<P = pallas::Affine> {
+    pub from : usize,
+    pub commitment : Vec<P>,
+}
+
+//pallas unless the curve is given, Payload::<k256::AffinePoint> carry the rounds of a secp256k1 signing
+//or key ceremony
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload<P: EcdsaCurve = pallas::Affine> {
+    KeyShare(KeyShareMsg<P::Scalar>),
+    KeyCommit(KeyCommitMsg<P>),
+    RingPedersen(RingPedersenMsg),
+    PaillierModulus(PaillierModulusMsg),
+    Round1(Round1Msg),
+    Round2(Round2Msg<P>),
+    Round3(Round3Msg<P>),
+    Round4(Round4Msg<P::Scalar>),
+    //boxed, a share check is about ten times the size of any other payload
+    ShareCheck(Box<ShareCheckMsg<P>>),
+    EddsaPartial(EddsaPartialMsg),
+    Sealed(SealedMsg),
+    Signed(SignedMsg),
+    Echo(EchoMsg),
+    Reshare(ReshareMsg<P::Scalar>),
+    ReshareCommit(ReshareCommitMsg<P>),
+    Bip340Commit(Bip340CommitMsg),
+    Bip340Share(Bip340ShareMsg),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message<P: EcdsaCurve = pallas::Affine> {
+    pub session_id : SessionId,
+    pub payload : Payload<P>,
+}
+
+pub trait Encode: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], ThresholdError> {
+    if input.len() < len {
+        return Err(ThresholdError::InvalidEncoding("message is too short"));
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], ThresholdError> {
+    let mut result = [0u8; N];
+    result.copy_from_slice(take(input, N)?);
+    Ok(result)
+}
+
+fn encode_index(index: usize, out: &mut Vec<u8>) {
+    (index as u32).encode(out);
+}
+
+fn decode_index(input: &mut &[u8]) -> Result<usize, ThresholdError> {
+    let index = u32::decode(input)?;
+    if index == BROADCAST {
+        return Err(ThresholdError::InvalidEncoding("party index"));
+    }
+    Ok(index as usize)
+}
+
+impl Encode for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(take_array::<1>(input)?[0])
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(u32::from_be_bytes(take_array(input)?))
+    }
+}
+
+impl Encode for u128 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(u128::from_be_bytes(take_array(input)?))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ThresholdError::InvalidEncoding("bool")),
+        }
+    }
+}
+
+//a scalar or point of any curve, the length is the one of its repr
+pub fn encode_scalar<F: PrimeField>(value: &F, out: &mut Vec<u8>) {
+    out.extend_from_slice(value.to_repr().as_ref());
+}
+
+pub fn decode_scalar<F: PrimeField>(input: &mut &[u8]) -> Result<F, ThresholdError> {
+    let mut repr = F::Repr::default();
+    let len = repr.as_ref().len();
+    repr.as_mut().copy_from_slice(take(input, len)?);
+    Option::from(F::from_repr(repr)).ok_or(ThresholdError::InvalidEncoding("scalar"))
+}
+
+pub fn encode_point<P: GroupEncoding>(value: &P, out: &mut Vec<u8>) {
+    out.extend_from_slice(value.to_bytes().as_ref());
+}
+
+pub fn decode_point<P: GroupEncoding>(input: &mut &[u8]) -> Result<P, ThresholdError> {
+    let mut repr = P::Repr::default();
+    let len = repr.as_ref().len();
+    repr.as_mut().copy_from_slice(take(input, len)?);
+    Option::from(P::from_bytes(&repr)).ok_or(ThresholdError::InvalidEncoding("point"))
+}
+
+//a u32 length and the points
+pub fn encode_points<P: GroupEncoding>(value: &[P], out: &mut Vec<u8>) {
+    (value.len() as u32).encode(out);
+    for i in value {
+        encode_point(i, out);
+    }
+}
+
+pub fn decode_points<P: GroupEncoding>(input: &mut &[u8]) -> Result<Vec<P>, ThresholdError> {
+    let len = u32::decode(input)?;
+    let mut result = Vec::new();
+    for _ in 0..len {
+        result.push(decode_point(input)?);
+    }
+    Ok(result)
+}
+
+impl Encode for pallas::Scalar {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_scalar(self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        decode_scalar(input)
+    }
+}
+
+impl Encode for pallas::Affine {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_point(self, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        decode_point(input)
+    }
+}
+
+impl Encode for BigInt {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to_signed_bytes_be().encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        let bytes = Vec::<u8>::decode(input)?;
+        let value = BigInt::from_signed_bytes_be(&bytes);
+        //no redundant sign byte, so a big integer only has one encoding
+        if value.to_signed_bytes_be() != bytes {
+            return Err(ThresholdError::InvalidEncoding("big integer"));
+        }
+        Ok(value)
+    }
+}
+
+impl Encode for BigUint {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let bytes = if self.is_zero() { Vec::new() } else { self.to_bytes_be() };
+        bytes.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        let bytes = Vec::<u8>::decode(input)?;
+        //no leading zero byte, 0 is the empty vec
+        if bytes.first() == Some(&0) {
+            return Err(ThresholdError::InvalidEncoding("big integer"));
+        }
+        Ok(BigUint::from_bytes_be(&bytes))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for i in self {
+            i.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        let len = u32::decode(input)?;
+        let mut result = Vec::new();
+        for _ in 0..len {
+            result.push(T::decode(input)?);
+        }
+        Ok(result)
+    }
+}
+
+impl Encode for RingPedersenParams {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.n_tilde.encode(out);
+        self.h1.encode(out);
+        self.h2.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(RingPedersenParams {
+            n_tilde : BigUint::decode(input)?,
+            h1 : BigUint::decode(input)?,
+            h2 : BigUint::decode(input)?,
+        })
+    }
+}
+
+impl Encode for RingPedersenProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.commitment.encode(out);
+        self.response.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(RingPedersenProof {
+            commitment : Vec::decode(input)?,
+            response : Vec::decode(input)?,
+        })
+    }
+}
+
+impl Encode for PaillierBlumProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.w.encode(out);
+        self.x.encode(out);
+        self.a.encode(out);
+        self.b.encode(out);
+        self.z.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(PaillierBlumProof {
+            w : BigUint::decode(input)?,
+            x : Vec::decode(input)?,
+            a : Vec::decode(input)?,
+            b : Vec::decode(input)?,
+            z : Vec::decode(input)?,
+        })
+    }
+}
+
+impl Encode for NoSmallFactorRound {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.a.encode(out);
+        self.b.encode(out);
+        self.t.encode(out);
+        self.z1.encode(out);
+        self.z2.encode(out);
+        self.w1.encode(out);
+        self.w2.encode(out);
+        self.v.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(NoSmallFactorRound {
+            a : BigUint::decode(input)?,
+            b : BigUint::decode(input)?,
+            t : BigUint::decode(input)?,
+            z1 : BigInt::decode(input)?,
+            z2 : BigInt::decode(input)?,
+            w1 : BigInt::decode(input)?,
+            w2 : BigInt::decode(input)?,
+            v : BigInt::decode(input)?,
+        })
+    }
+}
+
+impl Encode for NoSmallFactorProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.p_commitment.encode(out);
+        self.q_commitment.encode(out);
+        self.sigma.encode(out);
+        self.rounds.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(NoSmallFactorProof {
+            p_commitment : BigUint::decode(input)?,
+            q_commitment : BigUint::decode(input)?,
+            sigma : BigInt::decode(input)?,
+            rounds : Vec::decode(input)?,
+        })
+    }
+}
+
+impl Encode for PaillierModulusProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.blum.encode(out);
+        self.no_small_factor.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(PaillierModulusProof {
+            blum : PaillierBlumProof::decode(input)?,
+            no_small_factor : NoSmallFactorProof::decode(input)?,
+        })
+    }
+}
+
+impl<P: EcdsaCurve> Encode for DleqProof<P> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_point(&self.a1, out);
+        encode_point(&self.a2, out);
+        encode_scalar(&self.z, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(DleqProof {
+            a1 : decode_point(input)?,
+            a2 : decode_point(input)?,
+            z : decode_scalar(input)?,
+        })
+    }
+}
+
+impl<P: EcdsaCurve> Encode for ShareCheck<P> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_point(&self.k_commitment, out);
+        encode_point(&self.sigma_commitment, out);
+        encode_point(&self.k_point, out);
+        encode_point(&self.sigma_point, out);
+        self.k_proof.encode(out);
+        self.sigma_proof.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(ShareCheck {
+            k_commitment : decode_point(input)?,
+            sigma_commitment : decode_point(input)?,
+            k_point : decode_point(input)?,
+            sigma_point : decode_point(input)?,
+            k_proof : DleqProof::decode(input)?,
+            sigma_proof : DleqProof::decode(input)?,
+        })
+    }
+}
+
+impl Encode for IdentitySignature {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.commitment.encode(out);
+        self.s.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, ThresholdError> {
+        Ok(IdentitySignature {
+            commitment : pallas::Affine::decode(input)?,
+            s : pallas::Scalar::decode(input)?,
+        })
+    }
+}
+
+impl<P: EcdsaCurve> Payload<P> {
+    pub fn from(&self) -> usize {
+        match self {
+            Payload::KeyShare(i) => i.from,
+            Payload::KeyCommit(i) => i.from,
+            Payload::RingPedersen(i) => i.from,
+            Payload::PaillierModulus(i) => i.from,
+            Payload::Round1(i) => i.from,
+            Payload::Round2(i) => i.from,
+            Payload::Round3(i) => i.from,
+            Payload::Round4(i) => i.from,
+            Payload::ShareCheck(i) => i.from,
+            Payload::EddsaPartial(i) => i.from,
+            Payload::Sealed(i) => i.from,
+            Payload::Signed(i) => i.from,
+            Payload::Echo(i) => i.from,
+            Payload::Reshare(i) => i.from,
+            Payload::ReshareCommit(i) => i.from,
+            Payload::Bip340Commit(i) => i.from,
+            Payload::Bip340Share(i) => i.from,
+        }
+    }
+
+    //None for a broadcast
+    pub fn to(&self) -> Option<usize> {
+        match self {
+            Payload::KeyShare(i) => Some(i.to),
+            Payload::PaillierModulus(i) => Some(i.to),
+            Payload::Round2(i) => Some(i.to),
+            Payload::Sealed(i) => Some(i.to),
+            Payload::Reshare(i) => Some(i.to),
+            _ => None,
+        }
+    }
+
+    //the kind of message, a party send at most one broadcast of each kind in a session
+    pub fn tag(&self) -> u8 {
+        match self {
+            Payload::KeyShare(_) => 1,
+            Payload::KeyCommit(_) => 2,
+            Payload::RingPedersen(_) => 3,
+            Payload::PaillierModulus(_) => 4,
+            Payload::Round1(_) => 5,
+            Payload::Round2(_) => 6,
+            Payload::Round3(_) => 7,
+            Payload::Round4(_) => 8,
+            Payload::ShareCheck(_) => 9,
+            Payload::EddsaPartial(_) => 10,
+            Payload::Sealed(_) => 11,
+            Payload::Signed(_) => 12,
+            Payload::Echo(_) => 13,
+            Payload::Reshare(_) => 14,
+            Payload::ReshareCommit(_) => 15,
+            Payload::Bip340Commit(_) => 16,
+            Payload::Bip340Share(_) => 17,
+        }
+    }
+
+    //from and to are in the header, the body only has the rest
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Payload::KeyShare(i) => encode_scalar(&i.key_share, out),
+            Payload::KeyCommit(i) => encode_points(&i.commitment, out),
+            Payload::RingPedersen(i) => {
+                i.params.encode(out);
+                i.proof.encode(out);
+            }
+            Payload::PaillierModulus(i) => {
+                i.mta_pub_n.encode(out);
+                i.proof.encode(out);
+            }
+            Payload::Round1(i) => i.cipher_k.encode(out),
+            Payload::Round2(i) => {
+                i.cipher_kr.encode(out);
+                i.cipher_kw.encode(out);
+                let (point, value) = &i.range_proof;
+                for j in point {
+                    encode_point(j, out);
+                }
+                for j in value {
+                    j.encode(out);
+                }
+            }
+            Payload::Round3(i) => {
+                encode_scalar(&i.sharding_commitment, out);
+                encode_point(&i.verify_point, out);
+                encode_point(&i.k_commitment, out);
+                encode_point(&i.sigma_commitment, out);
+            }
+            Payload::Round4(i) => encode_scalar(&i.s, out),
+            Payload::ShareCheck(i) => i.check.encode(out),
+            Payload::EddsaPartial(i) => {
+                i.commitment.encode(out);
+                i.s.encode(out);
+            }
+            Payload::Sealed(i) => {
+                i.ephemeral.encode(out);
+                i.ciphertext.encode(out);
+                i.signature.encode(out);
+            }
+            Payload::Signed(i) => {
+                i.body.encode(out);
+                i.signature.encode(out);
+            }
+            Payload::Echo(i) => {
+                encode_index(i.signed.from, out);
+                i.signed.body.encode(out);
+                i.signed.signature.encode(out);
+            }
+            Payload::Reshare(i) => encode_scalar(&i.share, out),
+            Payload::ReshareCommit(i) => encode_points(&i.commitment, out),
+            Payload::Bip340Commit(i) => {
+                encode_point(&i.hiding, out);
+                encode_point(&i.binding, out);
+            }
+            Payload::Bip340Share(i) => encode_scalar(&i.s, out),
+        }
+    }
+
+    fn decode_body(tag: u8, from: usize, to: Option<usize>, input: &mut &[u8]) -> Result<Payload<P>, ThresholdError> {
+        let receiver = || to.ok_or(ThresholdError::InvalidEncoding("point to point message without receiver"));
+        let payload = match tag {
+            1 => Payload::KeyShare(KeyShareMsg {
+                from,
+                to : receiver()?,
+                key_share : decode_scalar(input)?,
+            }),
+            2 => Payload::KeyCommit(KeyCommitMsg {
+                from,
+                commitment : decode_points(input)?,
+            }),
+            3 => Payload::RingPedersen(RingPedersenMsg {
+                from,
+                params : RingPedersenParams::decode(input)?,
+                proof : RingPedersenProof::decode(input)?,
+            }),
+            4 => Payload::PaillierModulus(PaillierModulusMsg {
+                from,
+                to : receiver()?,
+                mta_pub_n : BigUint::decode(input)?,
+                proof : PaillierModulusProof::decode(input)?,
+            }),
+            5 => Payload::Round1(Round1Msg {
+                from,
+                cipher_k : BigUint::decode(input)?,
+            }),
+            6 => Payload::Round2(Round2Msg {
+                from,
+                to : receiver()?,
+                cipher_kr : BigUint::decode(input)?,
+                cipher_kw : BigUint::decode(input)?,
+                range_proof : (
+                    [decode_point(input)?, decode_point(input)?],
+                    [BigUint::decode(input)?, BigUint::decode(input)?, BigUint::decode(input)?, BigUint::decode(input)?, BigUint::decode(input)?, BigUint::decode(input)?],
+                ),
+            }),
+            7 => Payload::Round3(Round3Msg {
+                from,
+                sharding_commitment : decode_scalar(input)?,
+                verify_point : decode_point(input)?,
+                k_commitment : decode_point(input)?,
+                sigma_commitment : decode_point(input)?,
+            }),
+            8 => Payload::Round4(Round4Msg {
+                from,
+                s : decode_scalar(input)?,
+            }),
+            9 => Payload::ShareCheck(Box::new(ShareCheckMsg {
+                from,
+                check : ShareCheck::decode(input)?,
+            })),
+            10 => Payload::EddsaPartial(EddsaPartialMsg {
+                from,
+                commitment : pallas::Affine::decode(input)?,
+                s : pallas::Scalar::decode(input)?,
+            }),
+            11 => Payload::Sealed(SealedMsg {
+                from,
+                to : receiver()?,
+                ephemeral : pallas::Affine::decode(input)?,
+                ciphertext : Vec::decode(input)?,
+                signature : IdentitySignature::decode(input)?,
+            }),
+            12 => Payload::Signed(SignedMsg {
+                from,
+                body : Vec::decode(input)?,
+                signature : IdentitySignature::decode(input)?,
+            }),
+            13 => Payload::Echo(EchoMsg {
+                from,
+                signed : SignedMsg {
+                    from : decode_index(input)?,
+                    body : Vec::decode(input)?,
+                    signature : IdentitySignature::decode(input)?,
+                },
+            }),
+            14 => Payload::Reshare(ReshareMsg {
+                from,
+                to : receiver()?,
+                share : decode_scalar(input)?,
+            }),
+            15 => Payload::ReshareCommit(ReshareCommitMsg {
+                from,
+                commitment : decode_points(input)?,
+            }),
+            16 => Payload::Bip340Commit(Bip340CommitMsg {
+                from,
+                hiding : decode_point(input)?,
+                binding : decode_point(input)?,
+            }),
+            17 => Payload::Bip340Share(Bip340ShareMsg {
+                from,
+                s : decode_scalar(input)?,
+            }),
+            _ => return Err(ThresholdError::InvalidEncoding("unknown message type")),
+        };
+        if payload.to() != to {
+            return Err(ThresholdError::InvalidEncoding("broadcast message with a receiver"));
+        }
+        Ok(payload)
+    }
+}
+
+//version, session id, from and to
+fn decode_header(input: &mut &[u8]) -> Result<(SessionId, usize, Option<usize>), ThresholdError> {
+    if u8::decode(input)? != MESSAGE_VERSION {
+        return Err(ThresholdError::InvalidEncoding("unknown version"));
+    }
+    let session_id = take_array(input)?;
+    let from = decode_index(input)?;
+    let to = match u32::decode(input)? {
+        BROADCAST => None,
+        to => Some(to as usize),
+    };
+    Ok((session_id, from, to))
+}
+
+//from and to of an encoded message without decoding the payload, so it can be routed before the curve is known
+pub fn route(bytes: &[u8]) -> Result<(usize, Option<usize>), ThresholdError> {
+    let (_, from, to) = decode_header(&mut &bytes[..])?;
+    Ok((from, to))
+}
+
+impl<P: EcdsaCurve> Message<P> {
+    pub fn new(session_id: SessionId, payload: Payload<P>) -> Message<P> {
+        Message {
+            session_id,
+            payload,
+        }
+    }
+
+    pub fn from(&self) -> usize {
+        self.payload.from()
+    }
+
+    pub fn to(&self) -> Option<usize> {
+        self.payload.to()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        MESSAGE_VERSION.encode(&mut out);
+        out.extend_from_slice(&self.session_id);
+        encode_index(self.from(), &mut out);
+        match self.to() {
+            Some(to) => encode_index(to, &mut out),
+            None => BROADCAST.encode(&mut out),
+        }
+        self.payload.tag().encode(&mut out);
+        self.payload.encode_body(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Message<P>, ThresholdError> {
+        let mut input = bytes;
+        let (session_id, from, to) = decode_header(&mut input)?;
+        let tag = u8::decode(&mut input)?;
+        let payload = Payload::decode_body(tag, from, to, &mut input)?;
+        if !input.is_empty() {
+            return Err(ThresholdError::InvalidEncoding("trailing bytes"));
+        }
+        Ok(Message {
+            session_id,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::Mul;
+    use pasta_curves::group::{cofactor::CofactorCurveAffine, Curve};
+    use crate::make_signature::SignatureShare;
+    use crate::paillier_modulus::test_paillier_key;
+    use crate::ring_pedersen::test_ring_pedersen;
+    #[test]
+    fn message_test(){
+        let session_id = [7u8; 32];
+        let generator = pallas::Affine::generator();
+        let point = pallas::Affine::mul(generator, pallas::Scalar::from_u128(12345)).to_affine();
+        let scalar = pallas::Scalar::from_u128(678910);
+        let (params, ring_pedersen_proof) = test_ring_pedersen(0);
+        let (p, q) = test_paillier_key(0);
+        let modulus_proof = PaillierModulusProof::prove(&mut rand::thread_rng(), &p, &q, &params).unwrap();
+        let share = SignatureShare { s : scalar, k : scalar, sigma : scalar };
+        let signature = IdentitySignature { commitment : point, s : scalar };
+
+        let payload = [
+            Payload::KeyShare(KeyShareMsg { from : 0, to : 2, key_share : scalar }),
+            Payload::KeyCommit(KeyCommitMsg { from : 1, commitment : [point, generator].to_vec() }),
+            Payload::RingPedersen(RingPedersenMsg { from : 2, params, proof : ring_pedersen_proof }),
+            Payload::PaillierModulus(PaillierModulusMsg { from : 0, to : 1, mta_pub_n : &p * &q, proof : modulus_proof }),
+            Payload::Round1(Round1Msg { from : 1, cipher_k : BigUint::from(99u32) }),
+            Payload::Round2(Round2Msg { from : 1, to : 0, cipher_kr : BigUint::zero(), cipher_kw : &p * &q, range_proof : ([point, generator], [0u32, 1, 2, 3, 4, 5].map(BigUint::from)) }),
+            Payload::Round3(Round3Msg { from : 2, sharding_commitment : scalar, verify_point : point, k_commitment : generator, sigma_commitment : point }),
+            Payload::Round4(Round4Msg { from : 0, s : scalar }),
+            Payload::ShareCheck(Box::new(ShareCheckMsg { from : 1, check : share.share_check(&mut rand::thread_rng(), point) })),
+            Payload::EddsaPartial(EddsaPartialMsg { from : 2, commitment : point, s : scalar }),
+            Payload::Sealed(SealedMsg { from : 0, to : 2, ephemeral : point, ciphertext : [1, 2, 3].to_vec(), signature }),
+            Payload::Signed(SignedMsg { from : 1, body : [4, 5].to_vec(), signature }),
+            Payload::Echo(EchoMsg { from : 2, signed : SignedMsg { from : 1, body : Vec::new(), signature } }),
+            Payload::Reshare(ReshareMsg { from : 1, to : 2, share : scalar }),
+            Payload::ReshareCommit(ReshareCommitMsg { from : 0, commitment : [point, generator].to_vec() }),
+            Payload::Bip340Commit(Bip340CommitMsg { from : 2, hiding : k256::AffinePoint::GENERATOR, binding : k256::AffinePoint::GENERATOR }),
+            Payload::Bip340Share(Bip340ShareMsg { from : 1, s : -k256::Scalar::ONE }),
+        ];
+        for i in payload {
+            let message: Message = Message::new(session_id, i);
+            let bytes = message.to_bytes();
+            assert_eq!(Message::<pallas::Affine>::from_bytes(&bytes), Ok(message));
+            assert!(Message::<pallas::Affine>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            let mut longer = bytes.clone();
+            longer.push(0);
+            assert!(Message::<pallas::Affine>::from_bytes(&longer).is_err());
+        }
+
+        //the header is stable, so other implementations can read it
+        let bytes = Message::new(session_id, Payload::<pallas::Affine>::Round4(Round4Msg { from : 3, s : pallas::Scalar::one() })).to_bytes();
+        let mut expect = vec![MESSAGE_VERSION];
+        expect.extend_from_slice(&session_id);
+        expect.extend_from_slice(&[0, 0, 0, 3, 0xff, 0xff, 0xff, 0xff, 8, 1]);
+        expect.extend_from_slice(&[0u8; 31]);
+        assert_eq!(bytes, expect);
+
+        //a scalar above the modulus is not canonical
+        let mut bad = bytes.clone();
+        let len = bad.len();
+        bad[len - 32..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(Message::<pallas::Affine>::from_bytes(&bad), Err(ThresholdError::InvalidEncoding("scalar")));
+
+        //a broadcast round can't be sent to one party
+        let mut bad = bytes.clone();
+        bad[37..41].copy_from_slice(&[0, 0, 0, 1]);
+        assert!(Message::<pallas::Affine>::from_bytes(&bad).is_err());
+
+        //round 3 and 4 on secp256k1 and P-256, a point is 33 bytes there so a pallas reader can't take it
+        let round3 = Message::new(session_id, Payload::Round3(Round3Msg {
+            from : 1,
+            sharding_commitment : k256::Scalar::from_u128(42),
+            verify_point : k256::AffinePoint::GENERATOR,
+            k_commitment : k256::AffinePoint::GENERATOR,
+            sigma_commitment : k256::AffinePoint::GENERATOR,
+        }));
+        let bytes = round3.to_bytes();
+        assert_eq!(Message::from_bytes(&bytes), Ok(round3));
+        assert!(Message::<pallas::Affine>::from_bytes(&bytes).is_err());
+        let round4 = Message::new(session_id, Payload::<p256::AffinePoint>::Round4(Round4Msg { from : 2, s : -p256::Scalar::ONE }));
+        assert_eq!(Message::from_bytes(&round4.to_bytes()), Ok(round4));
+        let commit = Message::new(session_id, Payload::<k256::AffinePoint>::KeyCommit(KeyCommitMsg { from : 0, commitment : vec![k256::AffinePoint::GENERATOR; 2] }));
+        assert_eq!(Message::from_bytes(&commit.to_bytes()), Ok(commit));
+
+        //a big integer with a leading zero byte is not canonical
+        let mut bytes = Vec::new();
+        BigUint::from(258u32).encode(&mut bytes);
+        assert_eq!(bytes, [0, 0, 0, 2, 1, 2]);
+        assert_eq!(BigUint::decode(&mut &[0, 0, 0, 3, 0, 1, 2][..]), Err(ThresholdError::InvalidEncoding("big integer")));
+    }
+}
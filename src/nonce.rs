@@ -0,0 +1,143 @@
+use ff::{Field, PrimeField};
+use hmac::{Hmac, Mac};
+use pasta_curves::pallas;
+use rand::{CryptoRng, RngCore};
+use sha2::Sha256;
+use zeroize::Zeroize;
+use myp::Secret;
+use crate::curve::ThresholdCurve;
+
+//rfc 6979 hmac_drbg, seeded with the secret, the message and 32 fresh bytes from the rng.
+//a broken rng still give a new nonce for every secret and message, and a good rng keep the nonce
+//unpredictable even to someone who know the secret and watch a fault in the hash
+pub struct HedgedNonce {
+    k : [u8; 32],
+    v : [u8; 32],
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8; 32], part: &[&[u8]]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac key length");
+    for i in part {
+        mac.update(i);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+impl HedgedNonce {
+    //label keep the nonce of one protocol apart from another with the same secret and message
+    pub fn new(rng: &mut (impl CryptoRng + RngCore), label: &str, secret: &[u8], message: &[u8]) -> HedgedNonce {
+        let mut extra = [0u8; 32];
+        rng.fill_bytes(&mut extra);
+        //every part with its length, so two different inputs can't give the same seed
+        let mut seed = Vec::new();
+        for i in [label.as_bytes(), secret, message, &extra] {
+            seed.extend_from_slice(&(i.len() as u64).to_be_bytes());
+            seed.extend_from_slice(i);
+        }
+        let mut drbg = HedgedNonce { k : [0; 32], v : [1; 32] };
+        drbg.k = hmac(&drbg.k, &[&drbg.v, &[0], &seed]);
+        drbg.v = hmac(&drbg.k, &[&drbg.v]);
+        drbg.k = hmac(&drbg.k, &[&drbg.v, &[1], &seed]);
+        drbg.v = hmac(&drbg.k, &[&drbg.v]);
+        seed.zeroize();
+        extra.zeroize();
+        drbg
+    }
+
+    fn next(&mut self) -> [u8; 32] {
+        self.v = hmac(&self.k, &[&self.v]);
+        self.v
+    }
+
+    //a candidate out of range is thrown away and the state moved on, as rfc 6979 3.2 h.3
+    fn reject(&mut self) {
+        self.k = hmac(&self.k, &[&self.v, &[0]]);
+        self.v = hmac(&self.k, &[&self.v]);
+    }
+
+    //uniform non zero scalar, the top two bits are cut so most candidates are below the order
+    pub fn scalar(&mut self) -> Secret<pallas::Scalar> {
+        loop {
+            let mut bytes = self.next();
+            bytes[31] &= 0x3f;
+            let value = Option::<pallas::Scalar>::from(pallas::Scalar::from_repr(bytes));
+            bytes.zeroize();
+            match value {
+                Some(value) if !bool::from(value.is_zero()) => return Secret::new(value),
+                _ => self.reject(),
+            }
+        }
+    }
+
+    //64 bytes for a curve whose scalar is reduced from a wide value, the bias is 2^-256 at most
+    pub fn wide(&mut self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.next());
+        bytes[32..].copy_from_slice(&self.next());
+        bytes
+    }
+
+    //uniform non zero scalar of any ThresholdCurve, from 64 bytes
+    pub fn scalar_on<C: ThresholdCurve>(&mut self) -> Secret<C::Scalar> {
+        loop {
+            let mut bytes = self.wide();
+            let value = C::scalar_from_wide(&bytes);
+            bytes.zeroize();
+            if !bool::from(value.is_zero()) {
+                return Secret::new(value);
+            }
+            self.reject();
+        }
+    }
+}
+
+impl Drop for HedgedNonce {
+    fn drop(&mut self) {
+        self.k.zeroize();
+        self.v.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    //an rng that is stuck, every call give the same zero bytes
+    struct StuckRng;
+    impl RngCore for StuckRng {
+        fn next_u32(&mut self) -> u32 { 0 }
+        fn next_u64(&mut self) -> u64 { 0 }
+        fn fill_bytes(&mut self, dest: &mut [u8]) { dest.fill(0) }
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> { dest.fill(0); Ok(()) }
+    }
+    impl CryptoRng for StuckRng {}
+
+    fn nonce(rng: &mut (impl CryptoRng + RngCore), secret: &[u8], message: &[u8]) -> pallas::Scalar {
+        *HedgedNonce::new(rng, "nonce-test", secret, message).scalar().expose()
+    }
+
+    #[test]
+    fn nonce_test(){
+        //same rng state, same nonce
+        assert_eq!(nonce(&mut StdRng::seed_from_u64(1), b"secret", b"hello"), nonce(&mut StdRng::seed_from_u64(1), b"secret", b"hello"));
+        //fresh randomness change it even for the same message
+        let mut rng = rand::thread_rng();
+        assert_ne!(nonce(&mut rng, b"secret", b"hello"), nonce(&mut rng, b"secret", b"hello"));
+        //a stuck rng still give a different nonce for a different message, secret or label
+        assert_ne!(nonce(&mut StuckRng, b"secret", b"hello"), nonce(&mut StuckRng, b"secret", b"hellp"));
+        assert_ne!(nonce(&mut StuckRng, b"secret", b"hello"), nonce(&mut StuckRng, b"secreu", b"hello"));
+        assert_ne!(*HedgedNonce::new(&mut StuckRng, "nonce-test", b"secret", b"hello").scalar().expose(),
+            *HedgedNonce::new(&mut StuckRng, "other-label", b"secret", b"hello").scalar().expose());
+        //the length prefix keep the parts apart
+        assert_ne!(nonce(&mut StuckRng, b"ab", b"c"), nonce(&mut StuckRng, b"a", b"bc"));
+
+        //the same drbg give a new full width scalar on any curve every time
+        let mut drbg = HedgedNonce::new(&mut rng, "nonce-test", b"secret", b"hello");
+        let k = *drbg.scalar_on::<k256::AffinePoint>().expose();
+        assert!(!bool::from(k.is_zero()));
+        assert_ne!(k, *drbg.scalar_on::<k256::AffinePoint>().expose());
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+use chacha20poly1305::aead::{Aead, Payload as AeadPayload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use ff::PrimeField;
+use pasta_curves::group::Curve;
+use rand::{CryptoRng, Rng, RngCore};
+use zeroize::Zeroize;
+use myp::{Secret, ThresholdError};
+use crate::curve::ThresholdCurve;
+use crate::key_file::{cipher, write_atomic, KdfParams, NONCE_LEN, SALT_LEN};
+use crate::message::{decode_point, decode_scalar, encode_point, encode_scalar, Encode};
+use crate::nonce::HedgedNonce;
+
+//one time nonce pairs made before the message is known, (D_i, E_i) = (d_i * G, e_i * G) is published
+//ahead of the signing and R = sum(D_i + rho_i * E_i) with the binding factor of ed25519.rs and bip340.rs
+//  "MYPNONCE" | version u8 | argon2id m_cost u32 | t_cost u32 | p_cost u32 | salt 16 | nonce 12 | ciphertext
+//the plain text is the name of the curve, the next id and every entry: id u128 | D_i | E_i | used bool |
+//d_i e_i if not used. consume() write the entry as used and sync it to disk before the nonces leave,
+//so after a crash a pair is either still unused or already gone, never used twice
+pub const NONCE_STORE_VERSION: u8 = 1;
+const NONCE_STORE_MAGIC: &[u8; 8] = b"MYPNONCE";
+const HEADER_LEN: usize = 8 + 1 + 12 + SALT_LEN + NONCE_LEN;
+
+//d_i and e_i
+type NoncePair<F> = (Secret<F>, Secret<F>);
+
+struct NonceEntry<P: ThresholdCurve> {
+    id : u128,
+    hiding : P,
+    binding : P,
+    //d_i and e_i, None once they are given out
+    nonce : Option<NoncePair<P::Scalar>>,
+}
+
+pub struct NonceStore<P: ThresholdCurve> {
+    path : PathBuf,
+    //magic, version, kdf params and salt, the aead nonce is new for every write
+    header : Vec<u8>,
+    cipher : ChaCha20Poly1305,
+    next_id : u128,
+    entry : Vec<NonceEntry<P>>,
+}
+
+impl<P: ThresholdCurve> NonceStore<P> {
+    //a new empty store, an existing file is never overwritten
+    pub fn create(rng: &mut (impl CryptoRng + RngCore), path: &Path, passphrase: &[u8], params: KdfParams) -> Result<NonceStore<P>, ThresholdError> {
+        if path.exists() {
+            return Err(ThresholdError::Io(std::io::ErrorKind::AlreadyExists));
+        }
+        let salt: [u8; SALT_LEN] = rng.gen();
+        let mut header = NONCE_STORE_MAGIC.to_vec();
+        NONCE_STORE_VERSION.encode(&mut header);
+        params.m_cost.encode(&mut header);
+        params.t_cost.encode(&mut header);
+        params.p_cost.encode(&mut header);
+        header.extend_from_slice(&salt);
+        let store = NonceStore {
+            path : path.to_path_buf(),
+            cipher : cipher(passphrase, &salt, params)?,
+            header,
+            next_id : 0,
+            entry : Vec::new(),
+        };
+        store.save(rng)?;
+        Ok(store)
+    }
+
+    //a wrong passphrase and a changed byte look the same, the tag doesn't match
+    pub fn open(path: &Path, passphrase: &[u8]) -> Result<NonceStore<P>, ThresholdError> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < HEADER_LEN || &bytes[..8] != NONCE_STORE_MAGIC {
+            return Err(ThresholdError::InvalidEncoding("not a nonce store"));
+        }
+        if bytes[8] != NONCE_STORE_VERSION {
+            return Err(ThresholdError::InvalidEncoding("unknown nonce store version"));
+        }
+        let (header, ciphertext) = bytes.split_at(HEADER_LEN);
+        let mut input = &header[9..];
+        let params = KdfParams {
+            m_cost : u32::decode(&mut input)?,
+            t_cost : u32::decode(&mut input)?,
+            p_cost : u32::decode(&mut input)?,
+        };
+        let (salt, nonce) = input.split_at(SALT_LEN);
+        let cipher = cipher(passphrase, salt, params)?;
+        let mut plain_text = cipher
+            .decrypt(Nonce::from_slice(nonce), AeadPayload { msg : ciphertext, aad : header })
+            .map_err(|_| ThresholdError::InvalidEncoding("wrong passphrase or damaged nonce store"))?;
+        let decoded = NonceStore::decode_plain(&plain_text);
+        plain_text.zeroize();
+        let (next_id, entry) = decoded?;
+        Ok(NonceStore {
+            path : path.to_path_buf(),
+            header : header[..HEADER_LEN - NONCE_LEN].to_vec(),
+            cipher,
+            next_id,
+            entry,
+        })
+    }
+
+    //count new pairs hedged from the key share, they are on disk before their D_i and E_i are given out
+    pub fn preprocess(&mut self, rng: &mut (impl CryptoRng + RngCore), pri: &Secret<P::Scalar>, count: usize) -> Result<Vec<(u128, P, P)>, ThresholdError> {
+        let mut commitment = Vec::new();
+        for _ in 0..count {
+            let id = self.next_id;
+            self.next_id += 1;
+            let mut drbg = HedgedNonce::new(&mut *rng, &format!("{}-preprocess", P::NAME), pri.expose().to_repr().as_ref(), &id.to_be_bytes());
+            let (hiding, binding) = (drbg.scalar_on::<P>(), drbg.scalar_on::<P>());
+            let point = |i: &Secret<P::Scalar>| (P::generator() * *i.expose()).to_affine();
+            let (hiding_point, binding_point) = (point(&hiding), point(&binding));
+            self.entry.push(NonceEntry {
+                id,
+                hiding : hiding_point,
+                binding : binding_point,
+                nonce : Some((hiding, binding)),
+            });
+            commitment.push((id, hiding_point, binding_point));
+        }
+        self.save(rng)?;
+        Ok(commitment)
+    }
+
+    //the oldest unused pair, the one the next signing take
+    pub fn next_unused(&self) -> Option<(u128, P, P)> {
+        self.entry.iter().find(|i| i.nonce.is_some()).map(|i| (i.id, i.hiding, i.binding))
+    }
+
+    pub fn unused(&self) -> usize {
+        self.entry.iter().filter(|i| i.nonce.is_some()).count()
+    }
+
+    //d_i and e_i of nonce id for the sign_share of the scheme, the pair is marked used on disk first,
+    //if that write fails nothing is released and the pair is not used in this process again
+    pub fn consume(&mut self, rng: &mut (impl CryptoRng + RngCore), id: u128) -> Result<NoncePair<P::Scalar>, ThresholdError> {
+        let entry = self.entry.iter_mut().find(|i| i.id == id)
+            .ok_or(ThresholdError::OutOfRange("nonce id"))?;
+        let nonce = entry.nonce.take().ok_or(ThresholdError::NonceConsumed(id))?;
+        self.save(rng)?;
+        Ok(nonce)
+    }
+
+    fn encode_plain(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        P::NAME.as_bytes().to_vec().encode(&mut out);
+        self.next_id.encode(&mut out);
+        (self.entry.len() as u32).encode(&mut out);
+        for i in &self.entry {
+            i.id.encode(&mut out);
+            encode_point(&i.hiding, &mut out);
+            encode_point(&i.binding, &mut out);
+            i.nonce.is_none().encode(&mut out);
+            if let Some((hiding, binding)) = &i.nonce {
+                encode_scalar(hiding.expose(), &mut out);
+                encode_scalar(binding.expose(), &mut out);
+            }
+        }
+        out
+    }
+
+    fn decode_plain(bytes: &[u8]) -> Result<(u128, Vec<NonceEntry<P>>), ThresholdError> {
+        let mut input = bytes;
+        if Vec::<u8>::decode(&mut input)? != P::NAME.as_bytes() {
+            return Err(ThresholdError::InvalidEncoding("nonce store is for another curve"));
+        }
+        let next_id = u128::decode(&mut input)?;
+        let count = u32::decode(&mut input)?;
+        let mut entry = Vec::new();
+        for _ in 0..count {
+            let id = u128::decode(&mut input)?;
+            let hiding = decode_point(&mut input)?;
+            let binding = decode_point(&mut input)?;
+            let nonce = match bool::decode(&mut input)? {
+                true => None,
+                false => Some((Secret::new(decode_scalar(&mut input)?), Secret::new(decode_scalar(&mut input)?))),
+            };
+            if id >= next_id {
+                return Err(ThresholdError::InvalidEncoding("nonce id"));
+            }
+            entry.push(NonceEntry { id, hiding, binding, nonce });
+        }
+        if !input.is_empty() {
+            return Err(ThresholdError::InvalidEncoding("trailing bytes"));
+        }
+        Ok((next_id, entry))
+    }
+
+    //a new aead nonce for every write, the file is replaced with write_atomic
+    fn save(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<(), ThresholdError> {
+        let nonce: [u8; NONCE_LEN] = rng.gen();
+        let mut header = self.header.clone();
+        header.extend_from_slice(&nonce);
+        let mut plain_text = self.encode_plain();
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), AeadPayload { msg : &plain_text, aad : &header });
+        plain_text.zeroize();
+        let ciphertext = ciphertext.map_err(|_| ThresholdError::InvalidEncoding("nonce store is too long to encrypt"))?;
+        header.extend_from_slice(&ciphertext);
+        write_atomic(&self.path, &header)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use ff::Field;
+    use pasta_curves::pallas;
+    use crate::bip340;
+    use crate::tool::to_hex;
+
+    const TEST_PARAMS: KdfParams = KdfParams { m_cost : 64, t_cost : 1, p_cost : 1 };
+
+    #[test]
+    fn nonce_store_test(){
+        let mut rng = rand::thread_rng();
+        let dir = std::env::temp_dir().join(format!("myp-nonce-{}", to_hex(&rng.gen::<[u8; 8]>())));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        //two signers of a 2 of 2 bip340 key, each with its own store
+        let signer_set = [1, 2];
+        let (secret, slope) = (k256::Scalar::random(&mut rng), k256::Scalar::random(&mut rng));
+        let share: Vec<Secret<k256::Scalar>> = signer_set.iter().map(|i| Secret::new(secret + slope * k256::Scalar::from(*i as u64))).collect();
+        let verification_shares: Vec<k256::AffinePoint> = share.iter().map(bip340::pub_key_share).collect();
+        let pub_key = (k256::ProjectivePoint::GENERATOR * secret).to_affine();
+        let path: Vec<PathBuf> = (0..2).map(|i| dir.join(format!("nonce{}", i))).collect();
+        let mut store: Vec<NonceStore<k256::AffinePoint>> = path.iter().map(|i| NonceStore::create(&mut rng, i, b"pass", TEST_PARAMS).unwrap()).collect();
+        assert_eq!(NonceStore::<k256::AffinePoint>::create(&mut rng, &path[0], b"pass", TEST_PARAMS).err(), Some(ThresholdError::Io(std::io::ErrorKind::AlreadyExists)));
+
+        //the commitments are published before the message is known, the binding factor tie them to it
+        let published: Vec<Vec<(u128, k256::AffinePoint, k256::AffinePoint)>> = store.iter_mut().zip(&share).map(|(i, pri)| i.preprocess(&mut rng, pri, 3).unwrap()).collect();
+        let message = [42u8; 32];
+        let commitment: Vec<bip340::NonceCommitment> = published.iter().zip(&signer_set)
+            .map(|(i, index)| bip340::NonceCommitment { index : *index, hiding : i[1].1, binding : i[1].2 }).collect();
+        let s: Vec<k256::Scalar> = store.iter_mut().zip(&share).zip(&signer_set).map(|((i, pri), index)| {
+            let (hiding, binding) = i.consume(&mut rng, 1).unwrap();
+            bip340::sign_share(bip340::SigningNonce::new(hiding, binding), pri, *index, &signer_set, &message, &commitment, &pub_key).unwrap()
+        }).collect();
+        let signature = bip340::combine(&signer_set, &message, &commitment, &s, &verification_shares, &pub_key).unwrap();
+        assert_eq!(bip340::verify(&bip340::x_only(&pub_key), &message, &signature), Ok(()));
+
+        //a used pair never comes out again, not even after the process restart
+        assert_eq!(store[0].consume(&mut rng, 1).err(), Some(ThresholdError::NonceConsumed(1)));
+        drop(store);
+        let mut store = NonceStore::<k256::AffinePoint>::open(&path[0], b"pass").unwrap();
+        assert_eq!(store.unused(), 2);
+        assert_eq!(store.next_unused(), Some(published[0][0]));
+        assert_eq!(store.consume(&mut rng, 1).err(), Some(ThresholdError::NonceConsumed(1)));
+        assert_eq!(store.consume(&mut rng, 9).err(), Some(ThresholdError::OutOfRange("nonce id")));
+        //new pairs don't reuse an old id
+        assert_eq!(store.preprocess(&mut rng, &share[0], 1).unwrap()[0].0, 3);
+
+        //the file know its curve
+        assert_eq!(NonceStore::<pallas::Affine>::open(&path[0], b"pass").err(), Some(ThresholdError::InvalidEncoding("nonce store is for another curve")));
+        assert!(NonceStore::<k256::AffinePoint>::open(&path[0], b"wrong").is_err());
+        let mut bad = std::fs::read(&path[0]).unwrap();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        std::fs::write(&path[0], &bad).unwrap();
+        assert_eq!(NonceStore::<k256::AffinePoint>::open(&path[0], b"pass").err(), Some(ThresholdError::InvalidEncoding("wrong passphrase or damaged nonce store")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,567 @@
+use num_bigint::{BigInt, BigUint, RandBigInt, Sign};
+use num_integer::Integer;
+use num_traits::One;
+use sha2::{Digest, Sha256};
+use myp::ThresholdError;
+use rand::{CryptoRng, RngCore};
+use crate::ring_pedersen::RingPedersenParams;
+
+//proofs that a published mta_pub_n is well formed, every party run them once at setup
+//and the others reject the modulus before make_commitment or MakeSignature use it
+//the proofs are on big integer, the responses don't fit in u128
+pub const PAILLIER_BLUM_ROUNDS: usize = 80;
+//challenge is in +-2^l and the mask is in +-2^(l+epsilon)*sqrt(n), the check allow twice the mask
+//so a factor above ~3*2^(l+epsilon)*sqrt(n) is rejected, which means the other one can't be small
+//they are small, so every round only has ~1/33 soundness error and we repeat it
+pub const NO_SMALL_FACTOR_L: u32 = 4;
+pub const NO_SMALL_FACTOR_EPSILON: u32 = 4;
+pub const NO_SMALL_FACTOR_ROUNDS: usize = 16;
+//mta_pub_n is two 1024 bits primes with their top two bits set, so it has exactly 2048 bits.
+//a smaller modulus is refused by Party and by the proof
+pub const PAILLIER_MODULUS_BITS: u64 = 2048;
+const PAILLIER_PRIME_BITS: u64 = 1024;
+//miller rabin rounds with random bases, a composite pass all of them with probability < 4^-64
+const PRIME_TEST_ROUNDS: usize = 64;
+
+//n is a Paillier-Blum modulus: gcd(n, phi(n)) = 1 and p = q = 3 mod 4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaillierBlumProof {
+    pub w : BigUint,
+    pub x : Vec<BigUint>,
+    pub a : Vec<bool>,
+    pub b : Vec<bool>,
+    pub z : Vec<BigUint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoSmallFactorRound {
+    pub a : BigUint,
+    pub b : BigUint,
+    pub t : BigUint,
+    pub z1 : BigInt,
+    pub z2 : BigInt,
+    pub w1 : BigInt,
+    pub w2 : BigInt,
+    pub v : BigInt,
+}
+
+//n = p * q with p, q both close to sqrt(n), made with the verifier's ring pedersen params
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoSmallFactorProof {
+    pub p_commitment : BigUint,
+    pub q_commitment : BigUint,
+    pub sigma : BigInt,
+    pub rounds : Vec<NoSmallFactorRound>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaillierModulusProof {
+    pub blum : PaillierBlumProof,
+    pub no_small_factor : NoSmallFactorProof,
+}
+
+//the odd primes below 2000, a candidate with one of them as a factor skip miller rabin
+pub fn small_primes() -> Vec<u32> {
+    (3..2000u32).step_by(2).filter(|i| (3..*i).step_by(2).take_while(|j| j * j <= *i).all(|j| i % j != 0)).collect()
+}
+
+//n - 1 = d * 2^s, a base that doesn't reach -1 prove n composite
+fn miller_rabin(n: &BigUint, base: &BigUint) -> bool {
+    let minus_one = n - 1u32;
+    let s = minus_one.trailing_zeros().unwrap_or(0);
+    let d = &minus_one >> s;
+    let mut x = base.modpow(&d, n);
+    if x.is_one() || x == minus_one {
+        return true;
+    }
+    for _ in 1..s {
+        x = x.modpow(&BigUint::from(2u32), n);
+        if x == minus_one {
+            return true;
+        }
+    }
+    false
+}
+
+//below 2000 n is looked up, above it the bases are only taken once trial division pass
+fn passes_prime_test(n: &BigUint, mut base: impl Iterator<Item = BigUint>) -> bool {
+    let small = small_primes();
+    if *n < BigUint::from(2000u32) {
+        return small.iter().any(|i| *n == BigUint::from(*i)) || *n == BigUint::from(2u32);
+    }
+    if !n.bit(0) || small.iter().any(|i| (n % *i).bits() == 0) {
+        return false;
+    }
+    base.all(|i| miller_rabin(n, &i))
+}
+
+//for the prover and the key generation, the bases are random
+pub fn is_probable_prime(rng: &mut (impl CryptoRng + RngCore), n: &BigUint) -> bool {
+    let two = BigUint::from(2u32);
+    passes_prime_test(n, (0..PRIME_TEST_ROUNDS).map(|_| rng.gen_biguint_range(&two, &(n - 1u32))))
+}
+
+//for a verifier that reject a prime n, the bases are the small primes. a composite that fool them
+//only get its own modulus refused
+pub fn looks_prime(n: &BigUint) -> bool {
+    passes_prime_test(n, small_primes().into_iter().take(20).map(BigUint::from))
+}
+
+fn generate_blum_prime(rng: &mut (impl CryptoRng + RngCore), bits: u64) -> BigUint {
+    loop {
+        let mut p = rng.gen_biguint(bits);
+        //the top two bits so p * q has all its bits, the low two so p = 3 mod 4
+        for i in [bits - 1, bits - 2, 1, 0] {
+            p.set_bit(i, true);
+        }
+        if is_probable_prime(rng, &p) {
+            return p;
+        }
+    }
+}
+
+//pri_p and pri_q for a new mta_pub_n, different primes = 3 mod 4 of the same size,
+//so gcd(n, phi(n)) = 1 and both proofs can be made
+pub fn generate_paillier_key(rng: &mut (impl CryptoRng + RngCore)) -> (BigUint, BigUint) {
+    let p = generate_blum_prime(rng, PAILLIER_PRIME_BITS);
+    let mut q = generate_blum_prime(rng, PAILLIER_PRIME_BITS);
+    while q == p {
+        q = generate_blum_prime(rng, PAILLIER_PRIME_BITS);
+    }
+    (p, q)
+}
+
+//fixed paillier keys for the tests, looking for 1024 bits primes take too long to do in every test
+#[cfg(test)]
+pub fn test_paillier_key(index: usize) -> (BigUint, BigUint) {
+    const PRIME: [&str; 8] = [
+        "e7e2d1f4dc386f081f0e6aa33460b912b8d386c6d939c4111bcab6afabe1005a7620530a51fa7a10303a3b652378855df7cd189c636f4e3e5aa2ef8a9ef56df3d3d10b4fcd832943fabb99b0774a2f2ad36b0aa869b2d75c14d6cb88076ad92dd16094493d574cb89202f35706d9619e9750fe792ebf3005e7ca4f6045839423",
+        "e1592847f958354fab891d850cb1ab0efe71ab673bca83751397cb069ad9f6a50a9c995ed0b4292926db024a02cf3332ac05e3a077463d3d7068cd94f4af956d478d89b771e638cc1e90509c0b07abd7c19e16c7ae666d6782f716d7d48af416ebf52ab57b4e72ad47cc944a7b456aa9eaf098331033931c98a27505e4728833",
+        "c1a6ce52e5a8d2e30f3ed0a879770ca4fca17a83d586904c7b3b6802759888e45367e85c027e842defd9621cb5852c61141a5de5ba84323dc1ef13b40fc2fa23e7b8657629fc22d2bb1efca7d0036a16a94dfe0e29471aac7b8165fc7e6343f2307a50745cb2139d9996ad7d1d83ebf8f13d622a5d9c76679c0b1ba2f9093a1f",
+        "e1dadec497e8ee9dbffe94a832e377fa3a6359e6748e902a8d85a75a59039cfa9f1df6e1db3a893330993c75c5c87c16dfbf6e6a9f97712fbd40afe9e866600d958a94225911f96d8ee21a67c7802e753255929cda1e655ebdade189341bcf308cdae26fe73def43d58ea4f594483da4cbc8d24a1894624f6f2d90e4d0e9dd27",
+        "cc01317012ae83c12e431e4f426ebe0323daa8d362663b5a04f0d0a1b0708aa4138906723fadaffafdd2c82bcbc26fbcedcd74d6ffe9754abc9eb77d6f31b2d9c44aa732c2a3acb131d717c25511bb6744986c45c94e8024af3b2a4b6697920c4da71c9c556bbcde7ccffbc09754549830814be4a85b998ccdeaefcccaac477b",
+        "c5ba83099ee0d374f86d8776d1c3db4bec7185b5963ef3d2aef31caca5a9fca10ea68fae14caf2bb71bc179f647238d9b6b22539468c917f263bfa46623088178e09d60a46567a958486b63675461aca4e55cc398a0fa7ceb5f903a03a1c27797c66aeee2eeb0bff3d8f2a3d43abbcee95f80145c48ee65cdddfcb13854ecfb7",
+        "eec8fab38d4480bcc227619bf3982718d07bc3eaf0fdc1e419894b6ad39de671eba35bf3078166c4a76be9b4e43c8c5d885343c91771125b18c7c36bb66dd8a52cd4b7e005d38322ee99026d18f0046707f263b6f4225c2433683804c9df54297b5e4442f0a9aaafef9cd8284d55afcb86c8ffe302902f5314d1ae4a8c567e67",
+        "cf05e26ff2e837d1cc9db1afd25b4ff4f5b1b8bbb684a126aa4dde425a0a29b3fd51410ceb2517525d057848d0f2bcfb228e90835d5662d8f2b62b62d3f9ab7df926c490da5b65683e7f77a50a67d48ea350a7ddb647be6a0585512eddd2e359cce91d5ecd3317ea1aee50198fcf72a34207291f87d520c1019a84898f09f03b",
+    ];
+    let prime = |i: usize| BigUint::parse_bytes(PRIME[i].as_bytes(), 16).unwrap();
+    (prime(2 * index), prime(2 * index + 1))
+}
+
+fn big(value: &BigUint) -> BigInt {
+    BigInt::from(value.clone())
+}
+
+fn unsigned(value: &BigInt) -> BigUint {
+    value.to_biguint().unwrap()
+}
+
+fn inverse(a: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+    let result = a.mod_floor(modulus).extended_gcd(modulus);
+    if result.gcd != BigInt::from(1) {
+        return None;
+    }
+    Some(result.x.mod_floor(modulus))
+}
+
+//base^exponent mod modulus, negative exponent use the inverse of base
+fn pow_signed(base: &BigInt, exponent: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+    if exponent.sign() == Sign::Minus {
+        let base_inv = inverse(base, modulus)?;
+        Some(base_inv.modpow(&-exponent, modulus))
+    } else {
+        Some(base.mod_floor(modulus).modpow(exponent, modulus))
+    }
+}
+
+fn is_quadratic_residue(a: &BigInt, prime: &BigInt) -> bool {
+    let exponent: BigInt = (prime - 1) / 2;
+    a.mod_floor(prime).modpow(&exponent, prime) == BigInt::from(1)
+}
+
+fn jacobi(a: &BigInt, n: &BigInt) -> i32 {
+    let mut a = a.mod_floor(n);
+    let mut n = n.clone();
+    let mut result = 1;
+    let three = BigInt::from(3);
+    let five = BigInt::from(5);
+    let eight = BigInt::from(8);
+    let four = BigInt::from(4);
+    while a != BigInt::from(0) {
+        while a.is_even() {
+            a /= 2;
+            let r = n.mod_floor(&eight);
+            if r == three || r == five {
+                result = -result;
+            }
+        }
+        std::mem::swap(&mut a, &mut n);
+        if a.mod_floor(&four) == three && n.mod_floor(&four) == three {
+            result = -result;
+        }
+        a = a.mod_floor(&n);
+    }
+    if n == BigInt::from(1) {
+        result
+    } else {
+        0
+    }
+}
+
+pub fn hash_values(label: &str, values: &[&BigInt]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(label.as_bytes());
+    for i in values {
+        let bytes = i.to_signed_bytes_be();
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    }
+    hasher.finalize().into()
+}
+
+//y_i for the blum proof, the extra 128 bits make the bias of mod n negligible
+fn blum_challenge(n: &BigInt, w: &BigInt, index: usize) -> BigInt {
+    let index = BigInt::from(index);
+    let mut bytes = Vec::new();
+    let mut counter = BigInt::from(0);
+    while (bytes.len() as u64) * 8 < n.bits() + 128 {
+        bytes.extend(hash_values("paillier-blum", &[n, w, &index, &counter]));
+        counter += 1;
+    }
+    BigInt::from_bytes_be(Sign::Plus, &bytes).mod_floor(n)
+}
+
+//e in +-2^l
+fn no_small_factor_challenge(transcript: &[u8; 32], index: usize) -> BigInt {
+    let index = BigInt::from(index);
+    let transcript = BigInt::from_bytes_be(Sign::Plus, transcript);
+    let hash = hash_values("no-small-factor", &[&transcript, &index]);
+    let range = BigInt::from(1) << (NO_SMALL_FACTOR_L + 1);
+    BigInt::from_bytes_be(Sign::Plus, &hash).mod_floor(&(&range + 1)) - (range >> 1)
+}
+
+fn sample_signed(rng: &mut (impl CryptoRng + RngCore), bound: &BigInt) -> BigInt {
+    rng.gen_bigint_range(&-bound, &(bound + 1))
+}
+
+impl PaillierBlumProof {
+    //pri_p and pri_q should be different prime and = 3 mod 4, else there is no w to find
+    pub fn prove(rng: &mut (impl CryptoRng + RngCore), pri_p: &BigUint, pri_q: &BigUint) -> Result<PaillierBlumProof, ThresholdError> {
+        let three = BigUint::from(3u32);
+        if pri_p == pri_q || pri_p % 4u32 != three || pri_q % 4u32 != three
+            || !is_probable_prime(rng, pri_p) || !is_probable_prime(rng, pri_q) {
+            return Err(ThresholdError::InvalidModulus(pri_p * pri_q));
+        }
+        let p = BigInt::from(pri_p.clone());
+        let q = BigInt::from(pri_q.clone());
+        let n = &p * &q;
+        let phi: BigInt = (&p - 1) * (&q - 1);
+        let n_inv = inverse(&n, &phi).unwrap_or_default();
+        let minus_one: BigInt = &n - 1;
+
+        //w with jacobi symbol -1, it is a non residue mod p and a residue mod q
+        let mut w = rng.gen_bigint_range(&BigInt::from(2), &n);
+        while is_quadratic_residue(&w, &p) || !is_quadratic_residue(&w, &q) {
+            w = rng.gen_bigint_range(&BigInt::from(2), &n);
+        }
+
+        let p_root = BigInt::pow(&((&p + 1) / 4), 2).mod_floor(&(&p - 1));
+        let q_root = BigInt::pow(&((&q + 1) / 4), 2).mod_floor(&(&q - 1));
+        let p_inv = inverse(&p, &q).unwrap_or_default();
+
+        let mut x = Vec::new();
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        let mut z = Vec::new();
+        for i in 0..PAILLIER_BLUM_ROUNDS {
+            let y = blum_challenge(&n, &w, i);
+            z.push(unsigned(&y.modpow(&n_inv, &n)));
+
+            //exactly one of y, -y, wy, -wy is a residue mod n
+            let mut found = (false, false, y.clone());
+            for (sign, power) in [(false, false), (true, false), (false, true), (true, true)] {
+                let mut y2 = y.clone();
+                if sign {
+                    y2 = (&y2 * &minus_one).mod_floor(&n);
+                }
+                if power {
+                    y2 = (&y2 * &w).mod_floor(&n);
+                }
+                if is_quadratic_residue(&y2, &p) && is_quadratic_residue(&y2, &q) {
+                    found = (sign, power, y2);
+                    break;
+                }
+            }
+            let (sign, power, y2) = found;
+
+            //fourth root mod p and mod q, then crt
+            let xp = y2.modpow(&p_root, &p);
+            let xq = y2.modpow(&q_root, &q);
+            let h = ((&xq - &xp) * &p_inv).mod_floor(&q);
+            let root = (&xp + &p * h).mod_floor(&n);
+            x.push(unsigned(&root));
+            a.push(sign);
+            b.push(power);
+        }
+
+        Ok(PaillierBlumProof {
+            w: unsigned(&w),
+            x,
+            a,
+            b,
+            z,
+        })
+    }
+
+    pub fn verify(&self, mta_pub_n: &BigUint) -> bool {
+        if mta_pub_n.bits() < PAILLIER_MODULUS_BITS || !mta_pub_n.bit(0) || looks_prime(mta_pub_n) {
+            return false;
+        }
+        let n = BigInt::from(mta_pub_n.clone());
+        let w = BigInt::from(self.w.clone());
+        if self.w >= *mta_pub_n || jacobi(&w, &n) != -1 {
+            return false;
+        }
+        if self.x.len() != PAILLIER_BLUM_ROUNDS || self.z.len() != PAILLIER_BLUM_ROUNDS
+            || self.a.len() != PAILLIER_BLUM_ROUNDS || self.b.len() != PAILLIER_BLUM_ROUNDS {
+            return false;
+        }
+
+        for i in 0..PAILLIER_BLUM_ROUNDS {
+            let y = blum_challenge(&n, &w, i);
+            //z^n == y proves gcd(n, phi(n)) = 1
+            if self.z[i] >= *mta_pub_n || BigInt::from(self.z[i].clone()).modpow(&n, &n) != y {
+                return false;
+            }
+            //x^4 == (-1)^a * w^b * y proves p = q = 3 mod 4
+            let mut y2 = y;
+            if self.a[i] {
+                y2 = (&n - y2).mod_floor(&n);
+            }
+            if self.b[i] {
+                y2 = (y2 * &w).mod_floor(&n);
+            }
+            if self.x[i] >= *mta_pub_n || BigInt::from(self.x[i].clone()).modpow(&BigInt::from(4), &n) != y2 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl NoSmallFactorProof {
+    pub fn prove(rng: &mut (impl CryptoRng + RngCore), pri_p: &BigUint, pri_q: &BigUint, verifier: &RingPedersenParams) -> Result<NoSmallFactorProof, ThresholdError> {
+        let two = BigUint::from(2u32);
+        if *pri_p < two || *pri_q < two {
+            return Err(ThresholdError::InvalidModulus(pri_p * pri_q));
+        }
+        if verifier.n_tilde < two {
+            return Err(ThresholdError::InvalidModulus(verifier.n_tilde.clone()));
+        }
+        let p = BigInt::from(pri_p.clone());
+        let q = BigInt::from(pri_q.clone());
+        let n0 = &p * &q;
+        let n_tilde = big(&verifier.n_tilde);
+        let s = big(&verifier.h1);
+        let t = big(&verifier.h2);
+        let two_l = BigInt::from(1) << NO_SMALL_FACTOR_L;
+        let two_l_epsilon = BigInt::from(1) << (NO_SMALL_FACTOR_L + NO_SMALL_FACTOR_EPSILON);
+        let mask_bound = n0.sqrt() * &two_l_epsilon;
+
+        let commit = |m: &BigInt, r: &BigInt| -> Result<BigInt, ThresholdError> {
+            let left = pow_signed(&s, m, &n_tilde).ok_or(ThresholdError::NonInvertible)?;
+            let right = pow_signed(&t, r, &n_tilde).ok_or(ThresholdError::NonInvertible)?;
+            Ok((left * right).mod_floor(&n_tilde))
+        };
+
+        let mu = sample_signed(rng, &(&two_l * &n_tilde));
+        let nu = sample_signed(rng, &(&two_l * &n_tilde));
+        let sigma = sample_signed(rng, &(&two_l * &n0 * &n_tilde));
+        let p_commitment = commit(&p, &mu)?;
+        let q_commitment = commit(&q, &nu)?;
+        //r = s^n0 * t^sigma = q_commitment^p * t^(sigma - nu * p)
+        let sigma_hat = &sigma - &nu * &p;
+
+        let mut masks = Vec::new();
+        let mut rounds = Vec::new();
+        for _ in 0..NO_SMALL_FACTOR_ROUNDS {
+            let alpha = sample_signed(rng, &mask_bound);
+            let beta = sample_signed(rng, &mask_bound);
+            let x = sample_signed(rng, &(&two_l_epsilon * &n_tilde));
+            let y = sample_signed(rng, &(&two_l_epsilon * &n_tilde));
+            let r = sample_signed(rng, &(&two_l_epsilon * &n0 * &n_tilde));
+            let a = commit(&alpha, &x)?;
+            let b = commit(&beta, &y)?;
+            let t_value = (pow_signed(&q_commitment, &alpha, &n_tilde).ok_or(ThresholdError::NonInvertible)?
+                * pow_signed(&t, &r, &n_tilde).ok_or(ThresholdError::NonInvertible)?).mod_floor(&n_tilde);
+            rounds.push(NoSmallFactorRound {
+                a: unsigned(&a),
+                b: unsigned(&b),
+                t: unsigned(&t_value),
+                z1: BigInt::default(),
+                z2: BigInt::default(),
+                w1: BigInt::default(),
+                w2: BigInt::default(),
+                v: BigInt::default(),
+            });
+            masks.push((alpha, beta, x, y, r));
+        }
+
+        let mut proof = NoSmallFactorProof {
+            p_commitment: unsigned(&p_commitment),
+            q_commitment: unsigned(&q_commitment),
+            sigma,
+            rounds,
+        };
+        let transcript = proof.transcript(&(pri_p * pri_q), verifier);
+
+        for (i, (alpha, beta, x, y, r)) in masks.into_iter().enumerate() {
+            let e = no_small_factor_challenge(&transcript, i);
+            let round = &mut proof.rounds[i];
+            round.z1 = &alpha + &e * &p;
+            round.z2 = &beta + &e * &q;
+            round.w1 = &x + &e * &mu;
+            round.w2 = &y + &e * &nu;
+            round.v = &r + &e * &sigma_hat;
+        }
+        Ok(proof)
+    }
+
+    fn transcript(&self, mta_pub_n: &BigUint, verifier: &RingPedersenParams) -> [u8; 32] {
+        let mut values = vec![
+            BigInt::from(mta_pub_n.clone()),
+            big(&verifier.n_tilde),
+            big(&verifier.h1),
+            big(&verifier.h2),
+            big(&self.p_commitment),
+            big(&self.q_commitment),
+            self.sigma.clone(),
+        ];
+        for i in &self.rounds {
+            values.push(big(&i.a));
+            values.push(big(&i.b));
+            values.push(big(&i.t));
+        }
+        let refs: Vec<&BigInt> = values.iter().collect();
+        hash_values("no-small-factor", &refs)
+    }
+
+    pub fn verify(&self, mta_pub_n: &BigUint, verifier: &RingPedersenParams) -> bool {
+        if self.rounds.len() != NO_SMALL_FACTOR_ROUNDS {
+            return false;
+        }
+        let n0 = BigInt::from(mta_pub_n.clone());
+        let n_tilde = big(&verifier.n_tilde);
+        let s = big(&verifier.h1);
+        let t = big(&verifier.h2);
+        let two_l_epsilon = BigInt::from(1) << (NO_SMALL_FACTOR_L + NO_SMALL_FACTOR_EPSILON + 1);
+        let z_bound = n0.sqrt() * &two_l_epsilon;
+        let transcript = self.transcript(mta_pub_n, verifier);
+
+        let p_commitment = big(&self.p_commitment);
+        let q_commitment = big(&self.q_commitment);
+        let r = match (pow_signed(&s, &n0, &n_tilde), pow_signed(&t, &self.sigma, &n_tilde)) {
+            (Some(left), Some(right)) => (left * right).mod_floor(&n_tilde),
+            _ => return false,
+        };
+
+        for (i, round) in self.rounds.iter().enumerate() {
+            if round.z1.magnitude() > z_bound.magnitude() || round.z2.magnitude() > z_bound.magnitude() {
+                return false;
+            }
+            let e = no_small_factor_challenge(&transcript, i);
+            let check = |base1: &BigInt, exp1: &BigInt, base2: &BigInt, exp2: &BigInt, commitment: &BigUint, base3: &BigInt| -> bool {
+                let left = match (pow_signed(base1, exp1, &n_tilde), pow_signed(base2, exp2, &n_tilde)) {
+                    (Some(x), Some(y)) => (x * y).mod_floor(&n_tilde),
+                    _ => return false,
+                };
+                let right = match pow_signed(base3, &e, &n_tilde) {
+                    Some(x) => (x * big(commitment)).mod_floor(&n_tilde),
+                    None => return false,
+                };
+                left == right
+            };
+            //s^z1 * t^w1 == a * p^e
+            if !check(&s, &round.z1, &t, &round.w1, &round.a, &p_commitment) {
+                return false;
+            }
+            //s^z2 * t^w2 == b * q^e
+            if !check(&s, &round.z2, &t, &round.w2, &round.b, &q_commitment) {
+                return false;
+            }
+            //q^z1 * t^v == t * r^e
+            if !check(&q_commitment, &round.z1, &t, &round.v, &round.t, &r) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl PaillierModulusProof {
+    //the blum part can be broadcast, the no small factor part is made for one verifier
+    pub fn prove(rng: &mut (impl CryptoRng + RngCore), pri_p: &BigUint, pri_q: &BigUint, verifier: &RingPedersenParams) -> Result<PaillierModulusProof, ThresholdError> {
+        Ok(PaillierModulusProof {
+            blum: PaillierBlumProof::prove(rng, pri_p, pri_q)?,
+            no_small_factor: NoSmallFactorProof::prove(rng, pri_p, pri_q, verifier)?,
+        })
+    }
+
+    pub fn verify(&self, mta_pub_n: &BigUint, verifier: &RingPedersenParams) -> bool {
+        self.blum.verify(mta_pub_n) && self.no_small_factor.verify(mta_pub_n, verifier)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::ring_pedersen::test_ring_pedersen;
+    #[test]
+    fn paillier_modulus_test(){
+        let mut rng = rand::thread_rng();
+        let (verifier, ring_pedersen_proof) = test_ring_pedersen(0);
+        assert!(verifier.verify(&ring_pedersen_proof));
+
+        let (p, q) = test_paillier_key(0);
+        let n = &p * &q;
+        let proof = PaillierModulusProof::prove(&mut rng, &p, &q, &verifier).unwrap();
+        assert!(proof.verify(&n, &verifier));
+        //the proof is bound to the modulus
+        let (other_p, other_q) = test_paillier_key(1);
+        assert!(!proof.blum.verify(&(&other_p * &other_q)));
+        assert!(!proof.no_small_factor.verify(&(&other_p * &other_q), &verifier));
+
+        //3 * n has a factor far too small
+        let bad_p = BigUint::from(3u32);
+        let bad_n = &bad_p * &n;
+        let bad_proof = NoSmallFactorProof::prove(&mut rng, &bad_p, &n, &verifier).unwrap();
+        assert!(!bad_proof.verify(&bad_n, &verifier));
+
+        //5 = 1 mod 4, the prover can't make a blum proof for it
+        let five = BigUint::from(5u32);
+        assert_eq!(PaillierBlumProof::prove(&mut rng, &five, &q), Err(ThresholdError::InvalidModulus(&five * &q)));
+        //a blum proof for a modulus below 2048 bits is refused
+        let (small_p, small_q) = (BigUint::from(35023u32), BigUint::from(46099u32));
+        let small_proof = PaillierBlumProof::prove(&mut rng, &small_p, &small_q).unwrap();
+        assert!(!small_proof.verify(&(&small_p * &small_q)));
+
+        //a generated key pass its own proof
+        let (p, q) = generate_paillier_key(&mut rng);
+        assert_eq!((&p * &q).bits(), PAILLIER_MODULUS_BITS);
+        assert!(PaillierModulusProof::prove(&mut rng, &p, &q, &verifier).unwrap().verify(&(&p * &q), &verifier));
+    }
+
+    #[test]
+    fn prime_test(){
+        let mut rng = rand::thread_rng();
+        for i in 0..4 {
+            let (p, q) = test_paillier_key(i);
+            assert!(is_probable_prime(&mut rng, &p) && is_probable_prime(&mut rng, &q));
+            assert!(!is_probable_prime(&mut rng, &(&p * &q)) && looks_prime(&p) && !looks_prime(&(&p * &q)));
+            assert_eq!((&p * &q).bits(), PAILLIER_MODULUS_BITS);
+        }
+        //561 is a carmichael number, 7919 is prime
+        assert!(!is_probable_prime(&mut rng, &BigUint::from(561u32)) && is_probable_prime(&mut rng, &BigUint::from(7919u32)));
+        assert!(is_probable_prime(&mut rng, &BigUint::from(2u32)) && !is_probable_prime(&mut rng, &BigUint::from(1u32)));
+    }
+}
@@ -1,211 +1,271 @@
-use std::ops::{Add, Mul};
-
-use ff::PrimeField;
-use pasta_curves::{group::{cofactor::CofactorCurveAffine, Curve}, pallas};
-use crate::generate_random_u128_in_range;
-
-pub fn pow_and_mod(mut base: u128, mut exponent: u128, modulus: u128) -> u128{
-    if exponent == 0 {
-        return 1;
-    }
-
-    while exponent & 1 == 0 {
-        base = base.clone() * base;
-        base = base % modulus;
-        exponent >>= 1;
-    }
-    if exponent == 1 {
-        base = base % modulus;
-    }
-
-    let mut acc = base.clone();
-
-    while exponent > 1 {
-        exponent >>= 1;
-        base = base.clone() * base;
-        base = base % modulus;
-        if exponent & 1 == 1 {
-            acc = acc * base.clone();
-            acc = acc % modulus;
-        }
-    }
-    acc
+use num_bigint::{BigInt, BigUint, RandBigInt};
+use pasta_curves::group::Curve;
+use myp::{big_mod_inverse, Secret, ThresholdError};
+use rand::{CryptoRng, RngCore};
+use crate::curve::{big_to_scalar, scalar_modulus, ThresholdCurve};
+use crate::paillier_modulus::hash_values;
+use crate::random_unit;
+use crate::ring_pedersen::RingPedersenParams;
+
+//the secret is a scalar of the curve, below q like the k and w of the mta. e is the whole
+//transcript hash, and the masks are ZK_SLACK_BITS over e * q so s1 and s3 hide the secret.
+//Party send one with every round 2 message, for the k_i of its round 1 cipher
+pub const ZK_CHALLENGE_BITS: u64 = 256;
+pub const ZK_SLACK_BITS: u64 = 128;
+
+//[u1, y] on the curve, [z, u2, u3, s1, s2, s3] on big integer, e is made again by the verifier
+pub type ZkProof<P> = ([P;2],[BigUint;6]);
+
+fn mask_bound<P: ThresholdCurve>() -> BigUint {
+    scalar_modulus::<P::Scalar>() << (ZK_CHALLENGE_BITS + ZK_SLACK_BITS)
 }
 
-pub fn find_mod_inverse(x: u128, modulus: u128) -> u128 {
-    let mut k = 1;
-    let mut k2 = 0;
-    let mut ans = 0;
+fn point_value<P: ThresholdCurve>(point: &P) -> BigUint {
+    BigUint::from_bytes_be(point.to_bytes().as_ref())
+}
 
-    while k < modulus {
-        k2 = (k * modulus)+1;
-        if k2 % x == 0{
-            ans = k2 / x;
-            break;
-        }
-        k+=1;
+//fiat-shamir over n, cipher, y, the ring pedersen params and z, u1, u2, u3, so the prover can't pick e
+fn challenge(ring_pedersen: &RingPedersenParams, values: &[BigUint]) -> BigUint {
+    let mut values: Vec<BigInt> = values.iter().map(|i| BigInt::from(i.clone())).collect();
+    for i in [&ring_pedersen.n_tilde, &ring_pedersen.h1, &ring_pedersen.h2] {
+        values.push(BigInt::from(i.clone()));
     }
-    ans
+    let refs: Vec<&BigInt> = values.iter().collect();
+    BigUint::from_bytes_be(&hash_values("paillier-range", &refs))
 }
 
+//ring_pedersen is the verifier's params, the prover must not know the factors of n_tilde.
+//cipher = (n + 1)^secret * r^n mod n^2
 pub struct PrepareZk{
-    n : u128,
-    secret : u128,
-    r : u128,
+    pub n : BigUint,
+    pub secret : Secret<BigUint>,
+    pub r : Secret<BigUint>,
+    pub ring_pedersen : RingPedersenParams,
 }
 
 impl PrepareZk{
-    pub fn prepare_zk_verify(&self) -> ([pallas::Affine;2],[u128;9]){
-        let n = self.n;
-        let tau = n+1;
-        let secret = self.secret;
-        let generator = pallas::Affine::generator();
-        let y = pallas::Affine::mul(generator, pallas::Scalar::from_u128(secret)).to_affine();
-        let range_q = generate_random_u128_in_range(1, std::u16::MAX as u128);
-
-        let h1 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-        let h2 = generate_random_u128_in_range(1, std::u64::MAX as u128);
-        let a = generate_random_u128_in_range(1, range_q);
-        let b = generate_random_u128_in_range(1, n);
-        let p = generate_random_u128_in_range(1, range_q*n);
-        let r = self.r;
-
-        let z1 = pow_and_mod(h1, secret, n);
-        let z2 = pow_and_mod(h2, p, n);
-        let z = (z1 * z2) % n;
-        let u1 = pallas::Affine::mul(generator, pallas::Scalar::from_u128(a)).to_affine();
-        let u21  = pow_and_mod(tau, a, n*n);
-        let u22  = pow_and_mod(b, n, n*n);
-        let u2 = (u21 * u22) % (n*n);
-        let u31 = pow_and_mod(h1, a, n);
-        let u32 = pow_and_mod(h2, r, n);
-        let u3 = (u31 * u32) % n;
-        let e = generate_random_u128_in_range(1, std::u8::MAX as u128);
-        let s1 = (e * secret) + a;
-        let s20 = pow_and_mod(r, e, n*n);
-        let s2 = s20 * b;
-        let s3 = (e * p) + r;
-
-        (
+    //y = secret * G on P
+    pub fn prepare_zk_verify<P: ThresholdCurve>(&self, rng: &mut (impl CryptoRng + RngCore)) -> Result<ZkProof<P>, ThresholdError>{
+        let n = &self.n;
+        if *n < BigUint::from(2u32) {
+            return Err(ThresholdError::InvalidModulus(n.clone()));
+        }
+        let range_q = scalar_modulus::<P::Scalar>();
+        let secret = self.secret.expose();
+        if *secret >= range_q {
+            return Err(ThresholdError::OutOfRange("secret"));
+        }
+        let nn = n * n;
+        let tau = n + 1u32;
+        let y = (P::generator() * big_to_scalar::<P::Scalar>(secret)).to_affine();
+
+        let n_tilde = &self.ring_pedersen.n_tilde;
+        let h1 = &self.ring_pedersen.h1;
+        let h2 = &self.ring_pedersen.h2;
+        let a = rng.gen_biguint_below(&mask_bound::<P>());
+        let b = random_unit(rng, n);
+        let p = rng.gen_biguint_below(&(range_q * n_tilde));
+        let gamma = rng.gen_biguint_below(&(mask_bound::<P>() * n_tilde));
+        let r = self.r.expose();
+        let cipher = (tau.modpow(secret, &nn) * r.modpow(n, &nn)) % &nn;
+
+        let z1 = h1.modpow(secret, n_tilde);
+        let z2 = h2.modpow(&p, n_tilde);
+        let z = (z1 * z2) % n_tilde;
+        let u1 = (P::generator() * big_to_scalar::<P::Scalar>(&a)).to_affine();
+        let u21  = tau.modpow(&a, &nn);
+        let u22  = b.modpow(n, &nn);
+        let u2 = (u21 * u22) % &nn;
+        let u31 = h1.modpow(&a, n_tilde);
+        let u32 = h2.modpow(&gamma, n_tilde);
+        let u3 = (u31 * u32) % n_tilde;
+        let e = challenge(&self.ring_pedersen, &[n.clone(), cipher, point_value(&y), z.clone(), point_value(&u1), u2.clone(), u3.clone()]);
+        let s1 = &e * secret + a;
+        let s20 = r.modpow(&e, &nn);
+        let s2 = (s20 * b) % &nn;
+        let s3 = &e * &p + gamma;
+
+        Ok((
             [u1,y],
-            [z,u2,u3,e,s1,s2,s3,h1,h2],
-        )
+            [z,u2,u3,s1,s2,s3],
+        ))
     }
 }
 
-pub struct ZkVerify{
-    n : u128,
-    cipher : u128,
-    u1 : pallas::Affine,
-    y : pallas::Affine,
-    z : u128,
-    u2 : u128,
-    u3 : u128,
-    e : u128,
-    s1 : u128,
-    s2 : u128,
-    s3 : u128,
-    h1 : u128,
-    h2 : u128,
+pub struct ZkVerify<P: ThresholdCurve>{
+    n : BigUint,
+    cipher : BigUint,
+    u1 : P,
+    y : P,
+    z : BigUint,
+    u2 : BigUint,
+    u3 : BigUint,
+    s1 : BigUint,
+    s2 : BigUint,
+    s3 : BigUint,
+    ring_pedersen : RingPedersenParams,
 }
 
-impl ZkVerify{
+impl<P: ThresholdCurve> ZkVerify<P>{
+    //ring_pedersen is the verifier's own params, the ones the prover made the proof to
+    pub fn new(n: &BigUint, cipher: &BigUint, proof: &ZkProof<P>, ring_pedersen: &RingPedersenParams) -> ZkVerify<P> {
+        let ([u1, y], [z, u2, u3, s1, s2, s3]) = proof.clone();
+        ZkVerify {
+            n : n.clone(),
+            cipher : cipher.clone(),
+            u1,
+            y,
+            z,
+            u2,
+            u3,
+            s1,
+            s2,
+            s3,
+            ring_pedersen : ring_pedersen.clone(),
+        }
+    }
+
     pub fn zk_verify(&self) -> bool{
-        let n = self.n;
-        let w = self.cipher;
-        let e = self.e;
+        let n = &self.n;
+        let w = &self.cipher;
         let y= self.y;
-        let z= self.z;
-        let s1 = self.s1;
-        let s2 = self.s2;
-        let s3 = self.s3;
+        let z= &self.z;
+        let s1 = &self.s1;
+        let s2 = &self.s2;
+        let s3 = &self.s3;
         let u1 = self.u1;
-        let u2 = self.u2;
-        let u3 = self.u3;
-        let h1 = self.h1;
-        let h2 = self.h2;
-
-        let generator = pallas::Affine::generator();
-        let tau = n+1;
-
-        let e_neg = pallas::Scalar::from_u128(e).neg();
-        let s1g = pallas::Affine::mul(generator, pallas::Scalar::from_u128(s1)).to_affine();
-        let ye_neg = pallas::Affine::mul(y, e_neg).to_affine();
-        let s1g_plus_ye_neg = pallas::Affine::add(s1g, ye_neg).to_affine();
-        let result1 = pallas::Affine::eq(&s1g_plus_ye_neg, &u1);
-
-        let v1 = pow_and_mod(tau, s1, n*n);
-        let v2 = pow_and_mod(s2, n, n*n);
-        let w_inv = find_mod_inverse(w,n*n);
-        let check = (w * w_inv) % (n*n);
-        if check != 1{
-            println!("no inv");
+        let u2 = &self.u2;
+        let u3 = &self.u3;
+        let n_tilde = &self.ring_pedersen.n_tilde;
+        let h1 = &self.ring_pedersen.h1;
+        let h2 = &self.ring_pedersen.h2;
+
+        if *n < BigUint::from(2u32) {
+            return false;
         }
-        let v3 = pow_and_mod(w_inv, e, n*n);
-        let v4 = (v1 * v2) % (n * n);
-        let v5 = (v3 * v4) % (n * n);
-        let result2 = u128::eq(&u2, &v5);
+        //an honest s1 is below twice the mask bound, a bigger one means the secret is out of range
+        if *s1 >= mask_bound::<P>() << 1 {
+            return false;
+        }
+        let nn = n * n;
+        let tau = n + 1u32;
+        let e = challenge(&self.ring_pedersen, &[n.clone(), w.clone(), point_value(&y), z.clone(), point_value(&u1), u2.clone(), u3.clone()]);
+
+        //s1 * G - e * y == u1
+        let s1g = P::generator() * big_to_scalar::<P::Scalar>(s1);
+        let result1 = (s1g - y * big_to_scalar::<P::Scalar>(&e)).to_affine() == u1;
+
+        let v1 = tau.modpow(s1, &nn);
+        let v2 = s2.modpow(n, &nn);
+        //a cipher without inverse mod n^2 is not a valid paillier cipher, fail closed
+        let w_inv = match big_mod_inverse(w, &nn) {
+            Some(w_inv) => w_inv,
+            None => return false,
+        };
+        let v3 = w_inv.modpow(&e, &nn);
+        let v4 = (v1 * v2) % &nn;
+        let v5 = (v3 * v4) % &nn;
+        let result2 = BigUint::eq(u2, &v5);
  
-        let z2 = z % n;
-        let v6 = pow_and_mod(h1, s1, n);
-        let v7 = pow_and_mod(h2, s3, n);
-        let neg_e3 = n - (e+1);
-        let v8 = pow_and_mod(z2, neg_e3, n);
-        let v9 = (v6 * v7) % n;
-        let v = (v8 * v9) % n;
-        let result3 = u128::eq(&u3, &v);
-
-        let result = result1 && result2 && result3;
-    
-        result
+        //h1^s1 * h2^s3 == u3 * z^e mod n_tilde
+        let v6 = h1.modpow(s1, n_tilde);
+        let v7 = h2.modpow(s3, n_tilde);
+        let v8 = z.modpow(&e, n_tilde);
+        let v9 = (v6 * v7) % n_tilde;
+        let v = (u3 * v8) % n_tilde;
+        let result3 = BigUint::eq(&v9, &v);
+
+        result1 && result2 && result3
     }
 }
 
 #[cfg(test)]
 mod tests{
     use super::*;
+    use std::ops::Mul;
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use pasta_curves::pallas;
+    use crate::paillier_modulus::test_paillier_key;
+    use crate::ring_pedersen::test_ring_pedersen;
     #[test]
     fn zk_verify_test(){
-        let n = 3517;
-        let tau = n + 1;
-        let secret = generate_random_u128_in_range(1, std::u8::MAX as u128);
+        let mut rng = rand::thread_rng();
+        let (pri_p, pri_q) = test_paillier_key(0);
+        let n = &pri_p * &pri_q;
+        let nn = &n * &n;
+        let tau = &n + 1u32;
+        //a full scalar, as the k and w of the mta
+        let q = scalar_modulus::<pallas::Scalar>();
+        let secret = rng.gen_biguint_below(&q);
 
         let generator = pallas::Affine::generator();
-        let y = pallas::Affine::mul(generator, pallas::Scalar::from_u128(secret)).to_affine();
-        let range_q = generate_random_u128_in_range(1, std::u16::MAX as u128);
-        let r = generate_random_u128_in_range(1, range_q*range_q*range_q*n);
+        let y = pallas::Affine::mul(generator, big_to_scalar::<pallas::Scalar>(&secret)).to_affine();
+        let r = random_unit(&mut rng, &n);
+
+        let w1 = tau.modpow(&secret, &nn);
+        let w2 = r.modpow(&n, &nn);
+        let w = (w1 * w2) % &nn;
 
-        let w1 = pow_and_mod(tau, secret, n*n);
-        let w2 = pow_and_mod(r, n, n*n);
-        let w = (w1 * w2) % (n*n);
+        //verifier publish the ring pedersen params at key setup
+        let (ring_pedersen, ring_pedersen_proof) = test_ring_pedersen(0);
+        assert!(ring_pedersen.verify(&ring_pedersen_proof));
 
         let prover_side = PrepareZk{
-            n : n,
-            secret : secret,
-            r : r,
+            n : n.clone(),
+            secret : Secret::new(secret.clone()),
+            r : Secret::new(r.clone()),
+            ring_pedersen : ring_pedersen.clone(),
         };
 
-        let ([u1,y],[z,u2,u3,e,s1,s2,s3,h1,h2]) = prover_side.prepare_zk_verify();
+        let ([u1,prover_y],[z,u2,u3,s1,s2,s3]) = prover_side.prepare_zk_verify::<pallas::Affine>(&mut rng).unwrap();
+        assert_eq!(prover_y, y);
 
-        let verifier_side = ZkVerify{
-            n : n,
-            cipher : w,
+        let verify = |cipher: &BigUint, z: &BigUint, s1: &BigUint| ZkVerify::<pallas::Affine>{
+            n : n.clone(),
+            cipher : cipher.clone(),
             u1 : u1,
             y : y,
-            z : z,
-            u2 : u2,
-            u3 : u3,
-            e : e,
-            s1 : s1,
-            s2 : s2,
-            s3 : s3,
-            h1 : h1,
-            h2 : h2,
+            z : z.clone(),
+            u2 : u2.clone(),
+            u3 : u3.clone(),
+            s1 : s1.clone(),
+            s2 : s2.clone(),
+            s3 : s3.clone(),
+            ring_pedersen : ring_pedersen.clone(),
+        }.zk_verify();
+
+        assert!(verify(&w, &z, &s1));
+
+        //e comes from the transcript, the proof doesn't fit another cipher or another z
+        assert!(!verify(&((&w * &tau) % &nn), &z, &s1));
+        assert!(!verify(&w, &((&z * &ring_pedersen.h1) % &ring_pedersen.n_tilde), &s1));
+        //s1 above the bound would let a secret out of range pass
+        assert!(!verify(&w, &z, &(&s1 + (mask_bound::<pallas::Affine>() << 1))));
+
+        //cipher share a factor with n has no inverse, the verifier must reject it
+        assert!(!verify(&(&n * &tau), &z, &s1));
+
+        //the same proof on secp256k1, y is on the curve of the signing
+        let proof = prover_side.prepare_zk_verify::<k256::AffinePoint>(&mut rng).unwrap();
+        assert!(ZkVerify::new(&n, &w, &proof, &ring_pedersen).zk_verify());
+        assert!(!ZkVerify::new(&n, &((&w * &tau) % &nn), &proof, &ring_pedersen).zk_verify());
+        //a proof to another party's params doesn't verify with ours
+        let (other, _) = test_ring_pedersen(1);
+        assert!(!ZkVerify::new(&n, &w, &proof, &other).zk_verify());
+
+        //the prover refuse a secret out of range and a modulus below 2
+        let bad_prover_side = PrepareZk{
+            secret : Secret::new(q),
+            r : Secret::new(r.clone()),
+            n : n.clone(),
+            ring_pedersen : ring_pedersen.clone(),
         };
-
-        let result = verifier_side.zk_verify();
-
-        assert_eq!(result,true);
+        assert_eq!(bad_prover_side.prepare_zk_verify::<pallas::Affine>(&mut rng), Err(ThresholdError::OutOfRange("secret")));
+        let bad_prover_side = PrepareZk{
+            n : BigUint::from(1u32),
+            ..bad_prover_side
+        };
+        assert_eq!(bad_prover_side.prepare_zk_verify::<pallas::Affine>(&mut rng), Err(ThresholdError::InvalidModulus(BigUint::from(1u32))));
     }
 }
 
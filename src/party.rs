@@ -0,0 +1,569 @@
+use ff::{Field, PrimeField};
+use num_bigint::{BigUint, RandBigInt};
+use pasta_curves::group::{Curve, Group};
+use pasta_curves::pallas;
+use myp::{Decrypt, Encrypt, EncryptAddMut, Secret, ThresholdError};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+use crate::random_unit;
+use crate::curve::{big_to_scalar, scalar_modulus, scalar_to_big, EcdsaCurve};
+use crate::make_signature::SignatureShare;
+use crate::nonce::HedgedNonce;
+use crate::paillier_modulus::PAILLIER_MODULUS_BITS;
+use crate::paillier_verify::{PrepareZk, ZkProof, ZkVerify};
+use crate::ring_pedersen::RingPedersenParams;
+
+//make_commitment and MakeSignature run every party in one call, here each party only hold its own
+//secrets and its own paillier key, and talk to the others with the round messages
+//k, r and w are full scalars below q. the mta mask is below q^2 * 2^MTA_MASK_BITS, so it hide
+//k_j * r_i < q^2 up to 2^-MTA_MASK_BITS, and the sum stay below n_j as long as n_j > q^2 * 2^(MTA_MASK_BITS + 1)
+pub const MTA_MASK_BITS: usize = 128;
+
+pub(crate) fn mta_mask_bound<F: PrimeField>() -> BigUint {
+    let q = scalar_modulus::<F>();
+    (&q * &q) << MTA_MASK_BITS
+}
+
+//k_j * r_i + mask < q^2 + mask_bound, so a modulus above twice the mask bound never wrap it
+pub(crate) fn check_mta_modulus<F: PrimeField>(n: &BigUint) -> Result<(), ThresholdError> {
+    if n.bits() < PAILLIER_MODULUS_BITS || *n <= mta_mask_bound::<F>() << 1 {
+        return Err(ThresholdError::InvalidModulus(n.clone()));
+    }
+    Ok(())
+}
+
+//no Debug and no Clone, every field is wiped when dropped and there is only ever one copy of it.
+//a new nonce move w and the paillier key into the new PartySecret instead of copying them
+pub struct PartySecret<P: EcdsaCurve = pallas::Affine> {
+    pub k : Secret<P::Scalar>,
+    pub r : Secret<P::Scalar>,
+    pub w : Secret<P::Scalar>,
+    pub pri_p : Secret<BigUint>,
+    pub pri_q : Secret<BigUint>,
+}
+
+impl<P: EcdsaCurve> PartySecret<P> {
+    //the same w and paillier key with another k and r, the old k and r are wiped
+    pub fn with_nonce(self, k: Secret<P::Scalar>, r: Secret<P::Scalar>) -> PartySecret<P> {
+        PartySecret {
+            k,
+            r,
+            w : self.w,
+            pri_p : self.pri_p,
+            pri_q : self.pri_q,
+        }
+    }
+
+    //w and the paillier key with k and r hedged from them, nothing else can set k and r outside the tests
+    pub fn hedged(rng: &mut (impl CryptoRng + RngCore), digest: &[u8; 32], w: Secret<P::Scalar>, pri_p: Secret<BigUint>, pri_q: Secret<BigUint>) -> PartySecret<P> {
+        //zero k and r are never used, they are replaced before the PartySecret is returned
+        PartySecret {
+            k : Secret::new(P::Scalar::ZERO),
+            r : Secret::new(P::Scalar::ZERO),
+            w,
+            pri_p,
+            pri_q,
+        }.with_hedged_nonce(rng, digest)
+    }
+
+    //k and r hedged from w, the paillier key, the message digest and the rng, for one signature.
+    //a stuck rng still give new k and r for every message, so k is never used twice
+    pub fn with_hedged_nonce(self, rng: &mut (impl CryptoRng + RngCore), digest: &[u8; 32]) -> PartySecret<P> {
+        let mut secret = self.w.expose().to_repr().as_ref().to_vec();
+        for i in [&self.pri_p, &self.pri_q] {
+            let mut bytes = i.expose().to_bytes_be();
+            secret.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            secret.extend_from_slice(&bytes);
+            bytes.zeroize();
+        }
+        let mut nonce = HedgedNonce::new(rng, "ecdsa-nonce", &secret, digest);
+        secret.zeroize();
+        let k = nonce.scalar_on::<P>();
+        let r = nonce.scalar_on::<P>();
+        self.with_nonce(k, r)
+    }
+}
+
+//random k, r, w and the fixed paillier key of the party, for the tests
+#[cfg(test)]
+pub fn test_party_secret<P: EcdsaCurve>(rng: &mut (impl CryptoRng + RngCore), index: usize) -> PartySecret<P> {
+    let (pri_p, pri_q) = crate::paillier_modulus::test_paillier_key(index);
+    PartySecret {
+        k : Secret::new(P::Scalar::random(&mut *rng)),
+        r : Secret::new(P::Scalar::random(&mut *rng)),
+        w : Secret::new(P::Scalar::random(&mut *rng)),
+        pri_p : Secret::new(pri_p),
+        pri_q : Secret::new(pri_q),
+    }
+}
+
+//broadcast, cipher of k_i under the sender's own key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Round1Msg {
+    pub from : usize,
+    pub cipher_k : BigUint,
+}
+
+//point to point, cipher of k_to * r_from + mask and k_to * w_from + mask under the receiver's key,
+//and the range proof of k_from in the sender's round 1 cipher, made to the receiver's ring pedersen params
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Round2Msg<P: EcdsaCurve = pallas::Affine> {
+    pub from : usize,
+    pub to : usize,
+    pub cipher_kr : BigUint,
+    pub cipher_kw : BigUint,
+    pub range_proof : ZkProof<P>,
+}
+
+//broadcast, the share of k * r and r_i * G. k_i * G and sigma_i * G tie the party to its k_i and
+//sigma_i before any s_i is out, identify_abort hold its share check to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Round3Msg<P: EcdsaCurve = pallas::Affine> {
+    pub from : usize,
+    pub sharding_commitment : P::Scalar,
+    pub verify_point : P,
+    pub k_commitment : P,
+    pub sigma_commitment : P,
+}
+
+impl<P: EcdsaCurve> Round3Msg<P> {
+    //what identify_abort hold the party's share check to
+    pub fn share_commitment(&self) -> (P, P) {
+        (self.k_commitment, self.sigma_commitment)
+    }
+}
+
+//broadcast, s_i = m * k_i + r * sigma_i
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Round4Msg<F = pallas::Scalar> {
+    pub from : usize,
+    pub s : F,
+}
+
+//the party's round 4 message and what identify_abort need from it
+pub type SignOutput<F> = (Round4Msg<F>, SignatureShare<F>);
+
+//what is left of the signing rounds once R is known, made before the message. sign take it
+//by value, so a presignature give s_i for one message only and k_i, sigma_i are wiped after
+pub struct Presignature<P: EcdsaCurve = pallas::Affine> {
+    index : usize,
+    member : usize,
+    commitment : P,
+    //the round 3 message of every party, in index order
+    round3 : Vec<Round3Msg<P>>,
+    k : Secret<P::Scalar>,
+    sigma : Secret<P::Scalar>,
+}
+
+impl<P: EcdsaCurve> Presignature<P> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn member(&self) -> usize {
+        self.member
+    }
+
+    pub fn commitment(&self) -> P {
+        self.commitment
+    }
+
+    pub fn round3(&self) -> &[Round3Msg<P>] {
+        &self.round3
+    }
+
+    //the 32 byte hash of the message, as the standard verifiers of the curve take it
+    pub fn sign_digest(self, digest: &[u8; 32]) -> Result<SignOutput<P::Scalar>, ThresholdError> {
+        self.sign_scalar(P::digest_scalar(digest))
+    }
+
+    //s_i = m * k_i + r * sigma_i, and the share identify_abort need if s doesn't verify
+    pub fn sign_scalar(self, message: P::Scalar) -> Result<SignOutput<P::Scalar>, ThresholdError> {
+        let (r, _) = self.commitment.ecdsa_r()?;
+        let s = message * self.k.expose() + r * self.sigma.expose();
+        Ok((Round4Msg { from : self.index, s }, SignatureShare { s, k : *self.k.expose(), sigma : *self.sigma.expose() }))
+    }
+}
+
+//s = sum(s_i), incoming must hold the party's own round 4 message too
+pub fn combine_round4<F: Field>(index: usize, member: usize, incoming: &[Round4Msg<F>]) -> Result<F, ThresholdError> {
+    if incoming.len() != member {
+        return Err(ThresholdError::LengthMismatch { expected: member, found: incoming.len() });
+    }
+    from_others(index, member, incoming, |i| i.from)?;
+    let mut s = F::ZERO;
+    for i in incoming {
+        s += i.s;
+    }
+    Ok(s)
+}
+
+//pallas unless the curve is given, Party::<k256::AffinePoint> sign for secp256k1
+pub struct Party<P: EcdsaCurve = pallas::Affine> {
+    index : usize,
+    secret : PartySecret<P>,
+    mta_pub_n : Vec<BigUint>,
+    ring_pedersen : Vec<RingPedersenParams>,
+    round : usize,
+    //the randomness of the round 1 cipher, the range proofs of round 2 need it
+    rand_k : Secret<BigUint>,
+    //the round 1 cipher of every party, and the k_j * G the range proof of party j gave
+    cipher_k : Vec<BigUint>,
+    k_commitment : Vec<P>,
+    //sum of the masks this party added in round 2, they are taken back in round 3
+    mask_kr : P::Scalar,
+    mask_kw : P::Scalar,
+    sigma : P::Scalar,
+    commitment : P,
+}
+
+//one message from every other party, in index order
+fn from_others<M>(index: usize, member: usize, incoming: &[M], from: impl Fn(&M) -> usize) -> Result<Vec<&M>, ThresholdError> {
+    let mut result: Vec<Option<&M>> = vec![None; member];
+    for i in incoming {
+        let j = from(i);
+        if j >= member {
+            return Err(ThresholdError::OutOfRange("from"));
+        }
+        if j != index {
+            result[j] = Some(i);
+        }
+    }
+    let found = result.iter().filter(|i| i.is_some()).count();
+    if found != member - 1 {
+        return Err(ThresholdError::LengthMismatch { expected: member - 1, found });
+    }
+    Ok(result.into_iter().flatten().collect())
+}
+
+impl<P: EcdsaCurve> Party<P> {
+    //mta_pub_n is everyone's published paillier key, mta_pub_n[index] must be pri_p * pri_q.
+    //ring_pedersen is everyone's params from key setup, each already checked with its proof
+    pub fn new(index: usize, secret: PartySecret<P>, mta_pub_n: Vec<BigUint>, ring_pedersen: Vec<RingPedersenParams>) -> Result<Party<P>, ThresholdError> {
+        if index >= mta_pub_n.len() || mta_pub_n.len() < 2 {
+            return Err(ThresholdError::OutOfRange("index"));
+        }
+        if ring_pedersen.len() != mta_pub_n.len() {
+            return Err(ThresholdError::LengthMismatch { expected: mta_pub_n.len(), found: ring_pedersen.len() });
+        }
+        if secret.pri_p.expose() * secret.pri_q.expose() != mta_pub_n[index] {
+            return Err(ThresholdError::InvalidModulus(mta_pub_n[index].clone()));
+        }
+        for n in &mta_pub_n {
+            check_mta_modulus::<P::Scalar>(n)?;
+        }
+        for i in [&secret.k, &secret.r, &secret.w] {
+            if bool::from(i.expose().is_zero()) {
+                return Err(ThresholdError::OutOfRange("k, r, w"));
+            }
+        }
+        Ok(Party {
+            index,
+            secret,
+            mta_pub_n,
+            ring_pedersen,
+            round : 0,
+            rand_k : Secret::new(BigUint::default()),
+            cipher_k : Vec::new(),
+            k_commitment : Vec::new(),
+            mask_kr : P::Scalar::ZERO,
+            mask_kw : P::Scalar::ZERO,
+            sigma : P::Scalar::ZERO,
+            commitment : P::identity(),
+        })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    //the round only move on when it succeed, so a bad incoming message can be retried
+    fn check_round(&self, round: usize) -> Result<(), ThresholdError> {
+        if self.round + 1 != round {
+            return Err(ThresholdError::UnexpectedRound(round));
+        }
+        Ok(())
+    }
+
+    pub fn round1(&mut self, rng: &mut (impl CryptoRng + RngCore)) -> Result<Round1Msg, ThresholdError> {
+        self.check_round(1)?;
+        let n = &self.mta_pub_n[self.index];
+        let rand_k = random_unit(rng, n);
+        let cipher_k = Encrypt {
+            mta_pub_n : n.clone(),
+            rand : rand_k.clone(),
+            message : scalar_to_big(self.secret.k.expose()),
+        }.encrypt()?;
+        self.rand_k = Secret::new(rand_k);
+        self.round = 1;
+        Ok(Round1Msg {
+            from : self.index,
+            cipher_k,
+        })
+    }
+
+    //incoming must not hold this party's own round 1 message
+    pub fn round2(&mut self, rng: &mut (impl CryptoRng + RngCore), incoming: &[Round1Msg]) -> Result<Vec<Round2Msg<P>>, ThresholdError> {
+        self.check_round(2)?;
+        let member = self.mta_pub_n.len();
+        let mut result = Vec::new();
+        let mut mask_kr = P::Scalar::ZERO;
+        let mut mask_kw = P::Scalar::ZERO;
+        let bound = mta_mask_bound::<P::Scalar>();
+        let mut cipher_k = vec![BigUint::default(); member];
+        let own_k = Secret::new(scalar_to_big(self.secret.k.expose()));
+        for i in from_others(self.index, member, incoming, |i| i.from)? {
+            cipher_k[i.from] = i.cipher_k.clone();
+            let n = &self.mta_pub_n[i.from];
+            let mut mta = |mut_num: &P::Scalar| -> Result<(BigUint, P::Scalar), ThresholdError> {
+                let add_num = rng.gen_biguint_below(&bound);
+                let cipher = EncryptAddMut {
+                    mta_pub_n : n.clone(),
+                    cipher : i.cipher_k.clone(),
+                    add_num : add_num.clone(),
+                    mut_num : scalar_to_big(mut_num),
+                    rand : random_unit(rng, n),
+                }.mut_and_add()?;
+                Ok((cipher, big_to_scalar(&add_num)))
+            };
+            let (cipher_kr, add_kr) = mta(self.secret.r.expose())?;
+            let (cipher_kw, add_kw) = mta(self.secret.w.expose())?;
+            mask_kr += add_kr;
+            mask_kw += add_kw;
+            let range_proof = PrepareZk {
+                n : self.mta_pub_n[self.index].clone(),
+                secret : Secret::new(own_k.expose().clone()),
+                r : Secret::new(self.rand_k.expose().clone()),
+                ring_pedersen : self.ring_pedersen[i.from].clone(),
+            }.prepare_zk_verify(rng)?;
+            result.push(Round2Msg {
+                from : self.index,
+                to : i.from,
+                cipher_kr,
+                cipher_kw,
+                range_proof,
+            });
+        }
+        self.cipher_k = cipher_k;
+        self.mask_kr = mask_kr;
+        self.mask_kw = mask_kw;
+        self.round = 2;
+        Ok(result)
+    }
+
+    //incoming can hold the messages to every party, only the ones sent to this party are used.
+    //a range proof that doesn't verify with our own ring pedersen params blame its sender
+    pub fn round3(&mut self, incoming: &[Round2Msg<P>]) -> Result<Round3Msg<P>, ThresholdError> {
+        self.check_round(3)?;
+        let member = self.mta_pub_n.len();
+        let mine: Vec<&Round2Msg<P>> = incoming.iter().filter(|i| i.to == self.index).collect();
+        let k = *self.secret.k.expose();
+        let mut delta = k * self.secret.r.expose();
+        let mut sigma = k * self.secret.w.expose();
+        let mut k_commitment = vec![P::identity(); member];
+        let mine = from_others(self.index, member, &mine, |i| i.from)?;
+        for i in &mine {
+            if !ZkVerify::new(&self.mta_pub_n[i.from], &self.cipher_k[i.from], &i.range_proof, &self.ring_pedersen[self.index]).zk_verify() {
+                return Err(ThresholdError::VerificationFailed { party : i.from });
+            }
+            k_commitment[i.from] = i.range_proof.0[1];
+        }
+        for i in mine {
+            for (cipher, share) in [(&i.cipher_kr, &mut delta), (&i.cipher_kw, &mut sigma)] {
+                let plain_text = Decrypt {
+                    pri_p : Secret::new(self.secret.pri_p.expose().clone()),
+                    pri_q : Secret::new(self.secret.pri_q.expose().clone()),
+                    cipher : cipher.clone(),
+                }.decrypt()?;
+                *share += big_to_scalar::<P::Scalar>(&plain_text);
+            }
+        }
+        delta -= self.mask_kr;
+        self.sigma = sigma - self.mask_kw;
+        k_commitment[self.index] = (P::generator() * k).to_affine();
+        self.k_commitment = k_commitment;
+        self.round = 3;
+
+        Ok(Round3Msg {
+            from : self.index,
+            sharding_commitment : delta,
+            verify_point : (P::generator() * self.secret.r.expose()).to_affine(),
+            k_commitment : self.k_commitment[self.index],
+            sigma_commitment : (P::generator() * self.sigma).to_affine(),
+        })
+    }
+
+    //incoming must hold this party's own round 3 message too, nothing here need the message
+    pub fn presign(&mut self, incoming: &[Round3Msg<P>]) -> Result<Presignature<P>, ThresholdError> {
+        self.check_round(4)?;
+        let member = self.mta_pub_n.len();
+        if incoming.len() != member {
+            return Err(ThresholdError::LengthMismatch { expected: member, found: incoming.len() });
+        }
+        //k_j * G must be the one the range proof of round 2 was for
+        for i in from_others(self.index, member, incoming, |i| i.from)? {
+            if i.k_commitment != self.k_commitment[i.from] {
+                return Err(ThresholdError::VerificationFailed { party : i.from });
+            }
+        }
+        let mut round3 = incoming.to_vec();
+        round3.sort_by_key(|i| i.from);
+
+        //R = (k * r)^-1 * (r * G) = k^-1 * G
+        let mut delta = P::Scalar::ZERO;
+        let mut gamma = P::Curve::identity();
+        for i in incoming {
+            delta += i.sharding_commitment;
+            gamma += i.verify_point;
+        }
+        let delta_inv: P::Scalar = Option::from(delta.invert()).ok_or(ThresholdError::NonInvertible)?;
+        let commitment = (gamma * delta_inv).to_affine();
+        commitment.ecdsa_r()?;
+        self.commitment = commitment;
+        self.round = 4;
+        Ok(Presignature {
+            index : self.index,
+            member,
+            commitment,
+            round3,
+            k : Secret::new(*self.secret.k.expose()),
+            sigma : Secret::new(self.sigma),
+        })
+    }
+
+    //presign and sign_digest in one step, the ceremonies split them and only the tests use this
+    #[cfg(test)]
+    pub fn round4(&mut self, incoming: &[Round3Msg<P>], digest: &[u8; 32]) -> Result<Round4Msg<P::Scalar>, ThresholdError> {
+        let (own, _) = self.presign(incoming)?.sign_digest(digest)?;
+        Ok(own)
+    }
+
+    //the commitment R and s = sum(s_i)
+    #[cfg(test)]
+    pub fn finalize(&mut self, incoming: &[Round4Msg<P::Scalar>]) -> Result<(P, P::Scalar), ThresholdError> {
+        self.check_round(5)?;
+        let s = combine_round4(self.index, self.mta_pub_n.len(), incoming)?;
+        self.round = 5;
+        Ok((self.commitment, s))
+    }
+
+    //for identify_abort if the finalized signature doesn't verify
+    #[cfg(test)]
+    pub fn signature_share(&self, s: P::Scalar) -> SignatureShare<P::Scalar> {
+        SignatureShare {
+            s,
+            k : *self.secret.k.expose(),
+            sigma : self.sigma,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::{Add, Mul};
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use crate::identifiable_abort::commitment_r;
+    use rand::Rng;
+    use crate::identifiable_abort::identify_abort;
+    use crate::paillier_modulus::test_paillier_key;
+    use crate::ring_pedersen::test_ring_pedersen_params;
+    #[test]
+    fn party_test(){
+        let key: Vec<(BigUint, BigUint)> = (0..3).map(test_paillier_key).collect();
+        let mta_pub_n: Vec<BigUint> = key.iter().map(|(p, q)| p * q).collect();
+        let mut rng = rand::thread_rng();
+        let digest: [u8; 32] = rng.gen();
+        let mut party = Vec::new();
+        let mut w_sum = pallas::Scalar::ZERO;
+        for i in 0..3 {
+            let secret = test_party_secret(&mut rng, i);
+            //the last party let its k and r be hedged from its secrets and the message
+            let secret = if i == 2 { secret.with_hedged_nonce(&mut rng, &digest) } else { secret };
+            w_sum += secret.w.expose();
+            party.push(Party::new(i, secret, mta_pub_n.clone(), test_ring_pedersen_params(3)).unwrap());
+        }
+
+        let round1: Vec<Round1Msg> = party.iter_mut().map(|i| i.round1(&mut rng).unwrap()).collect();
+        let mut round2 = Vec::new();
+        for i in party.iter_mut() {
+            round2.extend(i.round2(&mut rng, &round1).unwrap());
+        }
+        let round3: Vec<Round3Msg> = party.iter_mut().map(|i| i.round3(&round2).unwrap()).collect();
+        let round4 = match party.iter_mut().map(|i| i.round4(&round3, &digest)).collect::<Result<Vec<Round4Msg>, ThresholdError>>() {
+            Ok(round4) => round4,
+            //x of R doesn't fit in the scalar field, too rare to care in a test
+            Err(ThresholdError::OutOfRange("r")) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let (commitment, s) = party[0].finalize(&round4).unwrap();
+
+        //s * R == m * G + r * pub_key
+        let generator = pallas::Affine::generator();
+        let pub_key = pallas::Affine::mul(generator, w_sum).to_affine();
+        let r = commitment_r(commitment).unwrap();
+        let left = pallas::Affine::mul(commitment, s).to_affine();
+        let right = pallas::Affine::add(
+            pallas::Affine::mul(generator, pallas::Affine::digest_scalar(&digest)).to_affine(),
+            pallas::Affine::mul(pub_key, r),
+        ).to_affine();
+        assert_eq!(left, right);
+
+        //the shares also work with identify_abort
+        let share: Vec<pallas::Scalar> = round4.iter().map(|i| i.s).collect();
+        let check: Vec<_> = party.iter().zip(&share).map(|(i, s)| i.signature_share(*s).share_check(&mut rng, commitment)).collect();
+        let share_commitment: Vec<_> = round3.iter().map(|i| i.share_commitment()).collect();
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &share_commitment, &share, &check), Ok(Vec::new()));
+        //party 0 send a wrong s_0, and can't open its round 3 commitment to another k_0 to fit it.
+        //the check it took is party 1's, so party 1's place doesn't open either and both are named
+        let mut share = share;
+        share[0] += pallas::Scalar::ONE;
+        let mut check = check;
+        check.swap(0, 1);
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &share_commitment, &share, &check), Ok([0, 1].to_vec()));
+
+        //a round can't be run twice and a message from every other party is needed
+        assert_eq!(party[1].round1(&mut rng), Err(ThresholdError::UnexpectedRound(1)));
+        assert_eq!(party[1].finalize(&round4[..2]), Err(ThresholdError::LengthMismatch { expected: 3, found: 2 }));
+
+        //a paillier key below 2048 bits is refused
+        let (p, q) = (BigUint::from(35023u32), BigUint::from(46099u32));
+        let small = vec![&p * &q, mta_pub_n[1].clone()];
+        let secret = PartySecret::<pallas::Affine> {
+            k : Secret::new(w_sum),
+            r : Secret::new(w_sum),
+            w : Secret::new(w_sum),
+            pri_p : Secret::new(p),
+            pri_q : Secret::new(q),
+        };
+        assert_eq!(Party::new(0, secret, small.clone(), test_ring_pedersen_params(2)).err(), Some(ThresholdError::InvalidModulus(small[0].clone())));
+
+        //every party's ring pedersen params are needed
+        let secret = test_party_secret::<pallas::Affine>(&mut rng, 0);
+        assert_eq!(Party::new(0, secret, mta_pub_n.clone(), test_ring_pedersen_params(2)).err(), Some(ThresholdError::LengthMismatch { expected: 3, found: 2 }));
+
+        //party 0's range proof to party 1 doesn't fit its cipher of k, so party 1 doesn't answer the mta
+        let mut party: Vec<Party> = (0..3).map(|i| Party::new(i, test_party_secret(&mut rng, i), mta_pub_n.clone(), test_ring_pedersen_params(3)).unwrap()).collect();
+        let round1: Vec<Round1Msg> = party.iter_mut().map(|i| i.round1(&mut rng).unwrap()).collect();
+        let mut round2 = Vec::new();
+        for i in party.iter_mut() {
+            round2.extend(i.round2(&mut rng, &round1).unwrap());
+        }
+        let wrong = round2.iter().position(|i| i.from == 0 && i.to == 1).unwrap();
+        round2[wrong].range_proof.1[0] += 1u32;
+        assert_eq!(party[1].round3(&round2), Err(ThresholdError::VerificationFailed { party: 0 }));
+        //party 2 got a good proof from party 0, but a round 3 k_0 * G other than the proof's is refused
+        let round3: Vec<Round3Msg> = [0, 2].iter().map(|i| party[*i].round3(&round2).unwrap()).collect();
+        let mut round3 = vec![round3[0], round3[0], round3[1]];
+        round3[0].k_commitment = pallas::Affine::generator();
+        round3[1].from = 1;
+        assert_eq!(party[2].presign(&round3).err(), Some(ThresholdError::VerificationFailed { party: 0 }));
+
+        //a new nonce keep w and the paillier key, they are moved and not copied
+        let secret = test_party_secret::<pallas::Affine>(&mut rng, 1);
+        let (w, n) = (*secret.w.expose(), secret.pri_p.expose() * secret.pri_q.expose());
+        let secret = secret.with_nonce(Secret::new(pallas::Scalar::ONE), Secret::new(w_sum));
+        assert_eq!((*secret.k.expose(), *secret.r.expose(), *secret.w.expose()), (pallas::Scalar::ONE, w_sum, w));
+        assert_eq!(secret.pri_p.expose() * secret.pri_q.expose(), n);
+    }
+}
@@ -0,0 +1,200 @@
+use num_bigint::{BigInt, BigUint, RandBigInt};
+use num_integer::Integer;
+use num_traits::One;
+use rand::{CryptoRng, RngCore};
+use crate::paillier_modulus::{hash_values, is_probable_prime, looks_prime, small_primes};
+
+//h1, h2 live in the group of quadratic residues mod n_tilde, the owner proves h2 = h1^lambda
+//so a verifier can use them as commitment base without trusting the owner
+pub const RING_PEDERSEN_ROUNDS: usize = 80;
+//two 1024 bits safe prime, n_tilde has 2048 bits like mta_pub_n and a smaller one is refused
+pub const RING_PEDERSEN_MODULUS_BITS: u64 = 2048;
+const SAFE_PRIME_BITS: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingPedersenParams {
+    pub n_tilde : BigUint,
+    pub h1 : BigUint,
+    pub h2 : BigUint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingPedersenProof {
+    pub commitment : Vec<BigUint>,
+    pub response : Vec<BigUint>,
+}
+
+//p = 2q + 1, q and p are sieved by the small primes before miller rabin run on them
+fn generate_safe_prime(rng: &mut (impl CryptoRng + RngCore), bits: u64) -> BigUint {
+    let small = small_primes();
+    loop {
+        let mut q = rng.gen_biguint(bits - 1);
+        //the top two bits so p * q has all its bits
+        for i in [bits - 2, bits - 3, 0] {
+            q.set_bit(i, true);
+        }
+        //r divides p when q = (r - 1) / 2 mod r
+        if small.iter().any(|i| {
+            let rest = &q % *i;
+            rest == BigUint::default() || rest == BigUint::from((i - 1) / 2)
+        }) {
+            continue;
+        }
+        let p: BigUint = (&q << 1) + 1u32;
+        if is_probable_prime(rng, &q) && is_probable_prime(rng, &p) {
+            return p;
+        }
+    }
+}
+
+fn challenge(params: &RingPedersenParams, commitment: &[BigUint]) -> Vec<bool> {
+    let mut values: Vec<BigInt> = [&params.n_tilde, &params.h1, &params.h2].into_iter().map(|i| BigInt::from(i.clone())).collect();
+    values.extend(commitment.iter().map(|i| BigInt::from(i.clone())));
+    let refs: Vec<&BigInt> = values.iter().collect();
+    let hash = hash_values("ring-pedersen", &refs);
+    (0..RING_PEDERSEN_ROUNDS)
+        .map(|i| (hash[i / 8] >> (i % 8)) & 1 == 1)
+        .collect()
+}
+
+//fixed safe primes for the tests, looking for 1024 bits safe primes take too long to do in every test
+#[cfg(test)]
+pub fn test_ring_pedersen(index: usize) -> (RingPedersenParams, RingPedersenProof) {
+    const PRIME: [&str; 6] = [
+        "e4b7aef4fc8bc7520a6d58be7071a54d1552ccf0cf46623fb08b6f5dce0916b34616f278dda5052fd7aee5fd6afe5684cc685b55aef93e19215384aabd95fa770b71568935e253a6c345c6fd16497b4d328b15f57af392248bd0c67b41dcf07254b507bcb0c0348c64a198acbe9e62fa10766e3c3273f280e87bb8e1aebe9837",
+        "df5f249a8300059dc77a6757d0e2333ca1bf419629b79ff92933eca98018539b16f7ebf387af2d4aee09211af0e10936395cf3121f522451a95f882faf161996fb8c636b5155856efcd27c95b831b0f1dbe01a3d878d78ed09397acec3063003438107e8d8a4efa8d85ba980aa1564944044b4ea98aa9d96d6db311c61d6da1b",
+        "edb85ef3c95ada0491bec0b7e9e12c7ce9b04533e987089b4c6a77e70c3047c9dec7195d46c056c8e2a6707e57675d8928fed3e7ba2548bbf9cab181cc9bc48a92111fa369f836950193e49cd33bd342cc6982119e9f42ed641edceea1ee8360a2446d977d8d8e721ee6db0e2fbddf976f387a2aa6a9cbaba1cb3f36daf92c1b",
+        "db90416437144c361d6c5d8f635531b8d234d33336e0447f65bd523493dc70fdbb9c70276060337978809b03556cf0d2794b19040c02c452dc2bb6d949aed5d3be4d791740b2876a7597f5f913f75eacba5eb54ac12da580a428d29da83b26a489d5e4233f339003acbc4918f44b6fad2e64968098d6a591077985a0ae65398f",
+        "f784861e9943112207c74680f577a65c49da471eaecaaeb1006f8a3494c7aa2b8e53ca0cfe498051c82d467b5e2af4117888d3552cdcb0a36da264eac92649259aea9c0cd096c868b82c2edb852bc93a2c19b1ae0d506c89aa80b03d69ddc4b9fa8b26e11407c6b43f0578bfae11f818394c59a251dcd1b45ceddccd9ed79dff",
+        "d729e1cf52ff569ef77a343f09d6e70a14ff1db04b8356f84f3f3c30b1dec1217ee537329afd5bee482ed639de1fa73e8490b2b3b2f970e863b4710a0e207a19845a774499159028988a770b8caa0c547c5e0cabc207cb1c61dacfefcfe2484ec251eaa8ecd1c07c03b408d04e838c12660f2c75697d7dd711f8d300ec82e313",
+    ];
+    let prime = |i: usize| BigUint::parse_bytes(PRIME[i].as_bytes(), 16).unwrap();
+    RingPedersenParams::from_safe_primes(&mut rand::thread_rng(), &prime(2 * index), &prime(2 * index + 1))
+}
+
+//the params of test_ring_pedersen for parties 0..member, made once and shared by every test
+#[cfg(test)]
+pub fn test_ring_pedersen_params(member: usize) -> Vec<RingPedersenParams> {
+    static PARAMS: std::sync::OnceLock<Vec<RingPedersenParams>> = std::sync::OnceLock::new();
+    PARAMS.get_or_init(|| (0..3).map(|i| test_ring_pedersen(i).0).collect())[..member].to_vec()
+}
+
+impl RingPedersenParams {
+    //each party run this once at key setup and publish the params with the proof
+    pub fn generate(rng: &mut (impl CryptoRng + RngCore)) -> (RingPedersenParams, RingPedersenProof) {
+        let p = generate_safe_prime(rng, SAFE_PRIME_BITS);
+        let mut q = generate_safe_prime(rng, SAFE_PRIME_BITS);
+        while q == p {
+            q = generate_safe_prime(rng, SAFE_PRIME_BITS);
+        }
+        RingPedersenParams::from_safe_primes(rng, &p, &q)
+    }
+
+    fn from_safe_primes(rng: &mut (impl CryptoRng + RngCore), p: &BigUint, q: &BigUint) -> (RingPedersenParams, RingPedersenProof) {
+        let n_tilde = p * q;
+        //order of the quadratic residues is p'q'
+        let order = (p >> 1) * (q >> 1);
+        let two = BigUint::from(2u32);
+
+        let mut tau = rng.gen_biguint_range(&two, &n_tilde);
+        while !tau.gcd(&n_tilde).is_one() {
+            tau = rng.gen_biguint_range(&two, &n_tilde);
+        }
+        let h1 = tau.modpow(&two, &n_tilde);
+        let lambda = rng.gen_biguint_range(&BigUint::one(), &order);
+        let h2 = h1.modpow(&lambda, &n_tilde);
+
+        let params = RingPedersenParams {
+            n_tilde,
+            h1,
+            h2,
+        };
+        let proof = params.prove(rng, &lambda, &order);
+        (params, proof)
+    }
+
+    fn prove(&self, rng: &mut (impl CryptoRng + RngCore), lambda: &BigUint, order: &BigUint) -> RingPedersenProof {
+        let mut mask = Vec::new();
+        let mut commitment = Vec::new();
+        for _ in 0..RING_PEDERSEN_ROUNDS {
+            let a = rng.gen_biguint_below(order);
+            commitment.push(self.h1.modpow(&a, &self.n_tilde));
+            mask.push(a);
+        }
+
+        let e = challenge(self, &commitment);
+        let mut response = Vec::new();
+        for (a, bit) in mask.into_iter().zip(&e) {
+            if *bit {
+                response.push((a + lambda) % order);
+            } else {
+                response.push(a);
+            }
+        }
+
+        RingPedersenProof {
+            commitment,
+            response,
+        }
+    }
+
+    //h1^z == a * h2^e mod n_tilde for every round
+    pub fn verify(&self, proof: &RingPedersenProof) -> bool {
+        let n_tilde = &self.n_tilde;
+        if n_tilde.bits() < RING_PEDERSEN_MODULUS_BITS || !n_tilde.bit(0) || looks_prime(n_tilde) {
+            return false;
+        }
+        for h in [&self.h1, &self.h2] {
+            if *h <= BigUint::one() || h >= n_tilde || !h.gcd(n_tilde).is_one() {
+                return false;
+            }
+        }
+        if proof.commitment.len() != RING_PEDERSEN_ROUNDS || proof.response.len() != RING_PEDERSEN_ROUNDS {
+            return false;
+        }
+
+        let e = challenge(self, &proof.commitment);
+        for ((a, z), bit) in proof.commitment.iter().zip(&proof.response).zip(&e) {
+            let left = self.h1.modpow(z, n_tilde);
+            let right = if *bit {
+                (a * &self.h2) % n_tilde
+            } else {
+                a % n_tilde
+            };
+            if left != right {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    #[test]
+    fn ring_pedersen_test(){
+        let (params, proof) = test_ring_pedersen(0);
+        assert!(params.verify(&proof));
+        assert_eq!(params.n_tilde.bits(), RING_PEDERSEN_MODULUS_BITS);
+
+        //h2 which isn't a power of h1 can't pass
+        let mut bad_params = params.clone();
+        bad_params.h2 = (&params.h2 * &params.h2 + 1u32) % &params.n_tilde;
+        assert!(!bad_params.verify(&proof));
+
+        let mut bad_proof = proof.clone();
+        bad_proof.response[0] += 1u32;
+        assert!(!params.verify(&bad_proof));
+
+        //a proof for another n_tilde doesn't fit
+        let (other_params, _) = test_ring_pedersen(1);
+        assert!(!other_params.verify(&proof));
+
+        //the search, on a size that is quick to find
+        let mut rng = rand::thread_rng();
+        let p = generate_safe_prime(&mut rng, 128);
+        assert_eq!(p.bits(), 128);
+        assert!(is_probable_prime(&mut rng, &p) && is_probable_prime(&mut rng, &(&p >> 1)));
+    }
+}
@@ -1,6 +1,20 @@
-use rand::Rng;
+pub use myp::random_unit;
+#[cfg(test)]
+pub use myp::random_in_range;
+//random_in_range with thread_rng, only the tests use it
+#[cfg(test)]
 pub fn generate_random_u128_in_range(min: u128, max: u128) -> u128 {
-    let mut rng = rand::thread_rng();
-    let random_value = rng.gen_range(min..=max);
-    random_value
-}
\ No newline at end of file
+    random_in_range(&mut rand::thread_rng(), min, max)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|i| format!("{:02x}", i)).collect()
+}
+
+//None if the length is odd or a char is not a hex digit
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|i| i.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
@@ -0,0 +1,773 @@
+use std::time::{Duration, Instant};
+use ff::Field;
+use rand::{CryptoRng, RngCore};
+use pasta_curves::group::{Curve, Group};
+use pasta_curves::pallas;
+use myp::{Secret, ThresholdError};
+use crate::bip340;
+use crate::curve::EcdsaCurve;
+use crate::key_generate::{lagrange_coefficient, CollectScalarShare, DealScalarShare, ScalarKeyShare};
+use crate::message::{Bip340CommitMsg, Bip340ShareMsg, KeyCommitMsg, KeyShareMsg, Message, Payload, ReshareCommitMsg, ReshareMsg, SessionId};
+use crate::make_signature::SignatureShare;
+use crate::nonce_store::NonceStore;
+use crate::party::{combine_round4, Party, Presignature, Round3Msg};
+//only the memory network and the simulator need these, and they are only built for the tests
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(test)]
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+#[cfg(test)]
+use rand::Rng;
+
+//how long a round waits for the others before it gives up
+pub const ROUND_TIMEOUT: Duration = Duration::from_secs(10);
+//on a lossy transport everything sent so far is sent again this often, the receiver drop the copies
+const RESEND_INTERVAL: Duration = Duration::from_millis(20);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+//P is the curve of the signing rounds, see Payload, a transport carry the messages of any curve
+pub trait Transport {
+    fn index(&self) -> usize;
+    fn parties(&self) -> usize;
+    //message.to() must be a party other than this one
+    fn send<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError>;
+    //message.to() must be None, every other party get it
+    fn broadcast<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError>;
+    //None if nothing came in before the timeout
+    fn receive<P: EcdsaCurve>(&mut self, timeout: Duration) -> Result<Option<Message<P>>, ThresholdError>;
+    //a reliable transport never lose a message, so the session doesn't resend
+    fn reliable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+//the chance of each fault for every message the memory transport deliver
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Faults {
+    pub drop : f64,
+    pub duplicate : f64,
+    pub reorder : f64,
+}
+
+#[cfg(test)]
+impl Faults {
+    pub fn none() -> Faults {
+        Faults {
+            drop : 0.0,
+            duplicate : 0.0,
+            reorder : 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+//one end of an in process network, messages go through the channels as bytes so the wire
+//encoding is used the same way as on a socket
+pub struct MemoryTransport {
+    index : usize,
+    outgoing : Vec<Sender<Vec<u8>>>,
+    incoming : Receiver<Vec<u8>>,
+    faults : Faults,
+    //messages held back by a reorder, they go out after the next one
+    held : Vec<(usize, Vec<u8>)>,
+}
+
+#[cfg(test)]
+pub fn memory_network(parties: usize, faults: Faults) -> Vec<MemoryTransport> {
+    let (outgoing, incoming): (Vec<_>, Vec<_>) = (0..parties).map(|_| channel::<Vec<u8>>()).unzip();
+    incoming.into_iter().enumerate().map(|(index, incoming)| MemoryTransport {
+        index,
+        outgoing : outgoing.clone(),
+        incoming,
+        faults,
+        held : Vec::new(),
+    }).collect()
+}
+
+#[cfg(test)]
+impl MemoryTransport {
+    fn deliver(&mut self, to: usize, bytes: Vec<u8>) -> Result<(), ThresholdError> {
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.faults.drop) {
+            return Ok(());
+        }
+        if rng.gen_bool(self.faults.reorder) {
+            self.held.push((to, bytes));
+            return Ok(());
+        }
+        if rng.gen_bool(self.faults.duplicate) {
+            self.push(to, bytes.clone())?;
+        }
+        self.push(to, bytes)?;
+        self.flush()
+    }
+
+    fn push(&self, to: usize, bytes: Vec<u8>) -> Result<(), ThresholdError> {
+        self.outgoing[to].send(bytes).map_err(|_| ThresholdError::Disconnected { party: to })
+    }
+
+    fn flush(&mut self) -> Result<(), ThresholdError> {
+        for (to, bytes) in std::mem::take(&mut self.held) {
+            self.push(to, bytes)?;
+        }
+        Ok(())
+    }
+
+    fn check_sender<P: EcdsaCurve>(&self, message: &Message<P>) -> Result<(), ThresholdError> {
+        if message.from() != self.index {
+            return Err(ThresholdError::OutOfRange("from"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Transport for MemoryTransport {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn parties(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    fn send<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        self.check_sender(message)?;
+        match message.to() {
+            Some(to) if to != self.index && to < self.parties() => self.deliver(to, message.to_bytes()),
+            _ => Err(ThresholdError::OutOfRange("to")),
+        }
+    }
+
+    fn broadcast<P: EcdsaCurve>(&mut self, message: &Message<P>) -> Result<(), ThresholdError> {
+        self.check_sender(message)?;
+        if message.to().is_some() {
+            return Err(ThresholdError::OutOfRange("to"));
+        }
+        let bytes = message.to_bytes();
+        for to in 0..self.parties() {
+            if to != self.index {
+                self.deliver(to, bytes.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn receive<P: EcdsaCurve>(&mut self, timeout: Duration) -> Result<Option<Message<P>>, ThresholdError> {
+        self.flush()?;
+        match self.incoming.recv_timeout(timeout) {
+            Ok(bytes) => Message::from_bytes(&bytes).map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(ThresholdError::Disconnected { party: self.index }),
+        }
+    }
+}
+
+//keeps what a party sent and received in one session, drop copies and messages from other
+//sessions, keep messages of later rounds until they are asked for, and resend on a lossy transport
+pub struct Session<T: Transport, P: EcdsaCurve = pallas::Affine> {
+    transport : T,
+    session_id : SessionId,
+    sent : Vec<Message<P>>,
+    received : Vec<Message<P>>,
+    last_resend : Instant,
+    timeout : Duration,
+}
+
+impl<T: Transport, P: EcdsaCurve> Session<T, P> {
+    pub fn new(transport: T, session_id: SessionId) -> Session<T, P> {
+        Session {
+            transport,
+            session_id,
+            sent : Vec::new(),
+            received : Vec::new(),
+            last_resend : Instant::now(),
+            timeout : ROUND_TIMEOUT,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn index(&self) -> usize {
+        self.transport.index()
+    }
+
+    pub fn parties(&self) -> usize {
+        self.transport.parties()
+    }
+
+    pub fn send(&mut self, payload: Payload<P>) -> Result<(), ThresholdError> {
+        let message = Message::new(self.session_id, payload);
+        match message.to() {
+            Some(_) => self.transport.send(&message)?,
+            None => self.transport.broadcast(&message)?,
+        }
+        self.sent.push(message);
+        Ok(())
+    }
+
+    fn resend(&mut self) {
+        if self.transport.reliable() || self.last_resend.elapsed() < RESEND_INTERVAL {
+            return;
+        }
+        for message in &self.sent {
+            //best effort, a party that already left doesn't need it
+            let _ = match message.to() {
+                Some(_) => self.transport.send(message),
+                None => self.transport.broadcast(message),
+            };
+        }
+        self.last_resend = Instant::now();
+    }
+
+    fn poll(&mut self) -> Result<(), ThresholdError> {
+        self.resend();
+        let message = match self.transport.receive(POLL_INTERVAL) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(()),
+            //a garbled message is dropped like a lost one
+            Err(ThresholdError::InvalidEncoding(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if message.session_id == self.session_id && !self.received.contains(&message) {
+            self.received.push(message);
+        }
+        Ok(())
+    }
+
+    //the first message picked from every other party, in index order
+    pub fn collect<M>(&mut self, pick: impl Fn(&Payload<P>) -> Option<M>) -> Result<Vec<M>, ThresholdError> {
+        let index = self.index();
+        let expected = self.parties() - 1;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let mut found: Vec<Option<M>> = (0..self.parties()).map(|_| None).collect();
+            for message in &self.received {
+                let from = message.from();
+                if from < found.len() && from != self.index() && found[from].is_none() {
+                    found[from] = pick(&message.payload);
+                }
+            }
+            let count = found.iter().filter(|i| i.is_some()).count();
+            if count == expected {
+                return Ok(found.into_iter().flatten().collect());
+            }
+            if Instant::now() >= deadline {
+                let missing = found.iter().enumerate().filter(|(i, m)| *i != index && m.is_none()).map(|(i, _)| i).collect();
+                return Err(ThresholdError::Timeout { missing });
+            }
+            self.poll()?;
+        }
+    }
+
+    #[cfg(test)]
+    //keep answering with resends after this party is done, until done() says everyone is
+    pub fn linger(&mut self, done: impl Fn() -> bool) {
+        while !done() {
+            if self.poll().is_err() {
+                return;
+            }
+        }
+    }
+}
+
+//the key ceremony of DealScalarShare and CollectScalarShare over the transport, on the curve of the
+//session. every party deal a random polynomial of degree threshold - 1, a dealer whose share doesn't
+//fit its commitment is blamed
+//out put the key share p(index + 1), the group public key and p(j + 1) * G of every party j
+pub fn run_keygen<T: Transport, P: EcdsaCurve>(session: &mut Session<T, P>, rng: &mut (impl CryptoRng + RngCore), threshold: usize) -> Result<ScalarKeyShare<P>, ThresholdError> {
+    let index = session.index();
+    let member = session.parties();
+    let (share, commitment) = DealScalarShare { output_max : member, output_min : threshold }.deal::<P>(rng)?;
+    session.send(Payload::KeyCommit(KeyCommitMsg { from : index, commitment : commitment.clone() }))?;
+    for (to, key_share) in share.expose().iter().enumerate() {
+        if to != index {
+            session.send(Payload::KeyShare(KeyShareMsg { from : index, to, key_share : *key_share }))?;
+        }
+    }
+    let mut commitment_vec = vec![commitment; member];
+    for i in session.collect(|i| match i {
+        Payload::KeyCommit(i) => Some(i.clone()),
+        _ => None,
+    })? {
+        commitment_vec[i.from] = i.commitment;
+    }
+    let mut key_share = vec![share.expose()[index]; member];
+    for i in session.collect(|i| match i {
+        Payload::KeyShare(i) if i.to == index => Some(*i),
+        _ => None,
+    })? {
+        key_share[i.from] = i.key_share;
+    }
+    CollectScalarShare {
+        key_share : Secret::new(key_share),
+        commitment : commitment_vec,
+        self_num : (index + 1) as u128,
+    }.collect()
+}
+
+//every party deal lambda_i * key_share with a new polynomial of degree threshold - 1, the sum of
+//what it get is its share of the same key under the new threshold, every party of the session must
+//hold a share of the old key at index + 1 and the old threshold must be no more than the parties
+//verification_share is the old share * G of every party, a dealer whose constant term doesn't match
+//its own is blamed
+//out put the new share and the new verification share of every party
+pub fn run_reshare<T: Transport, P: EcdsaCurve>(session: &mut Session<T, P>, rng: &mut (impl CryptoRng + RngCore), key_share: P::Scalar, pub_key: P, verification_share: &[P], threshold: usize) -> Result<(P::Scalar, Vec<P>), ThresholdError> {
+    let index = session.index();
+    let member = session.parties();
+    if threshold == 0 || threshold > member {
+        return Err(ThresholdError::OutOfRange("threshold"));
+    }
+    if verification_share.len() != member {
+        return Err(ThresholdError::LengthMismatch { expected: member, found: verification_share.len() });
+    }
+    let signer_set: Vec<u128> = (1..=member as u128).collect();
+    let lambda: P::Scalar = lagrange_coefficient((index + 1) as u128, &signer_set)?;
+    let mut coefficient = vec![lambda * key_share];
+    for _ in 1..threshold {
+        coefficient.push(P::Scalar::random(&mut *rng));
+    }
+    let commitment: Vec<P> = coefficient.iter().map(|i| (P::generator() * i).to_affine()).collect();
+    //f(x) with horner
+    let evaluate = |x: usize| coefficient.iter().rev().fold(P::Scalar::ZERO, |acc, i| acc * P::Scalar::from((x + 1) as u64) + i);
+
+    session.send(Payload::ReshareCommit(ReshareCommitMsg { from : index, commitment : commitment.clone() }))?;
+    for to in 0..member {
+        if to != index {
+            session.send(Payload::Reshare(ReshareMsg { from : index, to, share : evaluate(to) }))?;
+        }
+    }
+    let mut commitment_vec = vec![commitment; member];
+    for i in session.collect(|i| match i {
+        Payload::ReshareCommit(i) => Some(i.clone()),
+        _ => None,
+    })? {
+        if i.commitment.len() != threshold {
+            return Err(ThresholdError::VerificationFailed { party : i.from });
+        }
+        //the constant term is lambda_i * old share_i, else the dealer reshare something else
+        let lambda: P::Scalar = lagrange_coefficient((i.from + 1) as u128, &signer_set)?;
+        if i.commitment[0] != (verification_share[i.from] * lambda).to_affine() {
+            return Err(ThresholdError::VerificationFailed { party : i.from });
+        }
+        commitment_vec[i.from] = i.commitment;
+    }
+    let incoming = session.collect(|i| match i {
+        Payload::Reshare(i) if i.to == index => Some(*i),
+        _ => None,
+    })?;
+
+    //f_i(x) * G == sum(x^j * a_ij * G)
+    let commitment_at = |i: usize, x: usize| {
+        let x = P::Scalar::from((x + 1) as u64);
+        commitment_vec[i].iter().rev().fold(P::Curve::identity(), |acc, j| acc * x + j)
+    };
+    let mut new_share = evaluate(index);
+    for i in incoming {
+        if P::generator() * i.share != commitment_at(i.from, index) {
+            return Err(ThresholdError::VerificationFailed { party : i.from });
+        }
+        new_share += i.share;
+    }
+
+    //the constant terms add up to the old key, else the verification shares are not of this key
+    let sum = commitment_vec.iter().fold(P::Curve::identity(), |acc, i| acc + i[0]);
+    if sum.to_affine() != pub_key {
+        return Err(ThresholdError::Unattributable);
+    }
+    let verification_share = (0..member).map(|j| {
+        (0..member).fold(P::Curve::identity(), |acc, i| acc + commitment_at(i, j)).to_affine()
+    }).collect();
+    Ok((new_share, verification_share))
+}
+
+//the commitment R, s, this party's own share and the round 3 message of every party
+pub type SigningOutput<P> = (P, <P as pasta_curves::group::prime::PrimeCurveAffine>::Scalar, SignatureShare<<P as pasta_curves::group::prime::PrimeCurveAffine>::Scalar>, Vec<Round3Msg<P>>);
+
+//the commitment and signature of Party over the transport, party.index() must be the session's
+//out put the commitment R, s, and this party's own share and the round 3 messages for identify_abort
+pub fn run_signing<T: Transport, P: EcdsaCurve>(session: &mut Session<T, P>, rng: &mut (impl CryptoRng + RngCore), party: &mut Party<P>, digest: &[u8; 32]) -> Result<SigningOutput<P>, ThresholdError> {
+    let presignature = run_presigning(session, rng, party)?;
+    run_online_signing(session, presignature, digest)
+}
+
+//the rounds of run_signing that don't need the message, they can run long before it is known
+pub fn run_presigning<T: Transport, P: EcdsaCurve>(session: &mut Session<T, P>, rng: &mut (impl CryptoRng + RngCore), party: &mut Party<P>) -> Result<Presignature<P>, ThresholdError> {
+    let index = session.index();
+    if party.index() != index {
+        return Err(ThresholdError::OutOfRange("index"));
+    }
+
+    session.send(Payload::Round1(party.round1(rng)?))?;
+    let round1 = session.collect(|i| match i {
+        Payload::Round1(i) => Some(i.clone()),
+        _ => None,
+    })?;
+
+    for i in party.round2(rng, &round1)? {
+        session.send(Payload::Round2(i))?;
+    }
+    let round2 = session.collect(|i| match i {
+        Payload::Round2(i) if i.to == index => Some(i.clone()),
+        _ => None,
+    })?;
+
+    let own3 = party.round3(&round2)?;
+    session.send(Payload::Round3(own3))?;
+    let mut round3 = session.collect(|i| match i {
+        Payload::Round3(i) => Some(*i),
+        _ => None,
+    })?;
+    round3.push(own3);
+
+    party.presign(&round3)
+}
+
+//one broadcast round with a presignature, the session can be a later one than the presigning
+pub fn run_online_signing<T: Transport, P: EcdsaCurve>(session: &mut Session<T, P>, presignature: Presignature<P>, digest: &[u8; 32]) -> Result<SigningOutput<P>, ThresholdError> {
+    let index = session.index();
+    if presignature.index() != index {
+        return Err(ThresholdError::OutOfRange("index"));
+    }
+    let member = presignature.member();
+    let commitment = presignature.commitment();
+    let round3 = presignature.round3().to_vec();
+    let (own4, share) = presignature.sign_digest(digest)?;
+    session.send(Payload::Round4(own4))?;
+    let mut round4 = session.collect(|i| match i {
+        Payload::Round4(i) => Some(*i),
+        _ => None,
+    })?;
+    round4.push(own4);
+
+    let s = combine_round4(index, member, &round4)?;
+    Ok((commitment, s, share, round3))
+}
+
+//bip340 with every party of the session signing, key_share is the share at index + 1 and
+//verification_share the share * G of every party, pub_key is the full group key point.
+//a nonce round then a share round, a bad share is blamed by the index of its sender.
+//with a store the oldest unused preprocessed pair is marked used on disk before its commitment
+//is sent, so a pair whose D_i and E_i went out in one session never sign in another one.
+//out put x(R) | s
+pub fn run_bip340_signing<T: Transport>(session: &mut Session<T, k256::AffinePoint>, rng: &mut (impl CryptoRng + RngCore), key_share: &Secret<k256::Scalar>, pub_key: &k256::AffinePoint, verification_share: &[k256::AffinePoint], message: &[u8], store: Option<&mut NonceStore<k256::AffinePoint>>) -> Result<[u8; 64], ThresholdError> {
+    let index = session.index();
+    let member = session.parties();
+    if verification_share.len() != member {
+        return Err(ThresholdError::LengthMismatch { expected: member, found: verification_share.len() });
+    }
+    let signer_set: Vec<u128> = (1..=member as u128).collect();
+    let (nonce, own) = match store {
+        Some(store) => {
+            let (id, hiding, binding) = store.next_unused().ok_or(ThresholdError::OutOfRange("no unused nonce in the store"))?;
+            let (hiding_nonce, binding_nonce) = store.consume(rng, id)?;
+            (bip340::SigningNonce::new(hiding_nonce, binding_nonce), bip340::NonceCommitment { index : (index + 1) as u128, hiding, binding })
+        }
+        None => bip340::commit(rng, key_share, (index + 1) as u128, message),
+    };
+    session.send(Payload::Bip340Commit(Bip340CommitMsg { from : index, hiding : own.hiding, binding : own.binding }))?;
+    let mut commitment = vec![own; member];
+    for i in session.collect(|i| match i {
+        Payload::Bip340Commit(i) => Some(*i),
+        _ => None,
+    })? {
+        commitment[i.from] = bip340::NonceCommitment { index : (i.from + 1) as u128, hiding : i.hiding, binding : i.binding };
+    }
+
+    let own_s = bip340::sign_share(nonce, key_share, (index + 1) as u128, &signer_set, message, &commitment, pub_key)?;
+    session.send(Payload::Bip340Share(Bip340ShareMsg { from : index, s : own_s }))?;
+    let mut s = vec![own_s; member];
+    for i in session.collect(|i| match i {
+        Payload::Bip340Share(i) => Some(*i),
+        _ => None,
+    })? {
+        s[i.from] = i.s;
+    }
+    bip340::combine(&signer_set, message, &commitment, &s, verification_share, pub_key)
+}
+
+#[cfg(test)]
+//run every party on its own thread over a memory network, a party that is done keep resending
+//until all the others are done too, the result is in index order
+pub fn simulate<P, R, F>(parties: usize, faults: Faults, run: F) -> Vec<Result<R, ThresholdError>>
+where
+    P: EcdsaCurve,
+    R: Send,
+    F: Fn(&mut Session<MemoryTransport, P>) -> Result<R, ThresholdError> + Sync,
+{
+    simulate_over(memory_network(parties, faults), run)
+}
+
+#[cfg(test)]
+//the same over any transport, e.g. a memory network wrapped in secure channels
+pub fn simulate_over<T, P, R, F>(transport: Vec<T>, run: F) -> Vec<Result<R, ThresholdError>>
+where
+    T: Transport + Send,
+    P: EcdsaCurve,
+    R: Send,
+    F: Fn(&mut Session<T, P>) -> Result<R, ThresholdError> + Sync,
+{
+    let parties = transport.len();
+    let session_id: SessionId = rand::thread_rng().gen();
+    let done = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let handle: Vec<_> = transport.into_iter().map(|transport| {
+            let run = &run;
+            let done = &done;
+            scope.spawn(move || {
+                let mut session = Session::new(transport, session_id);
+                let result = run(&mut session);
+                done.fetch_add(1, Ordering::SeqCst);
+                session.linger(|| done.load(Ordering::SeqCst) == parties);
+                result
+            })
+        }).collect();
+        handle.into_iter().map(|i| i.join().unwrap_or(Err(ThresholdError::Disconnected { party : parties }))).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::ops::{Add, Mul};
+    use pasta_curves::group::cofactor::CofactorCurveAffine;
+    use crate::generate_random_u128_in_range;
+    use crate::identifiable_abort::{commitment_r, identify_abort};
+    use num_bigint::BigUint;
+    use crate::party::{test_party_secret, PartySecret};
+    use crate::ring_pedersen::test_ring_pedersen_params;
+    use crate::curve::EcdsaSignature;
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+
+    fn lossy() -> Faults {
+        Faults {
+            drop : 0.2,
+            duplicate : 0.2,
+            reorder : 0.3,
+        }
+    }
+
+    #[test]
+    fn keygen_test(){
+        let result = simulate(3, lossy(), |session| run_keygen(session, &mut rand::thread_rng(), 2));
+        let result: Vec<_> = result.into_iter().map(|i| i.unwrap()).collect();
+        let generator = pallas::Affine::generator();
+        let pub_key = result[0].1;
+        for (index, (key_share, key, verification_share)) in result.iter().enumerate() {
+            assert_eq!((*key, verification_share), (pub_key, &result[0].2));
+            assert_eq!(verification_share[index], pallas::Affine::mul(generator, key_share.expose()).to_affine());
+        }
+        //any 2 of the shares give the key
+        let lambda: [pallas::Scalar; 2] = [lagrange_coefficient(1, &[1, 3]).unwrap(), lagrange_coefficient(3, &[1, 3]).unwrap()];
+        let secret = lambda[0] * result[0].0.expose() + lambda[1] * result[2].0.expose();
+        assert_eq!(pallas::Affine::mul(generator, secret).to_affine(), pub_key);
+
+        //the same on secp256k1, a threshold above the parties is refused
+        let result = simulate::<k256::AffinePoint, _, _>(3, Faults::none(), |session| run_keygen(session, &mut rand::thread_rng(), 3));
+        let (key_share, pub_key, verification_share) = result[1].as_ref().unwrap();
+        assert_eq!(verification_share[1], (k256::AffinePoint::GENERATOR * key_share.expose()).to_affine());
+        assert!(result.iter().all(|i| i.as_ref().unwrap().1 == *pub_key));
+        let result = simulate::<pallas::Affine, _, _>(3, Faults::none(), |session| run_keygen(session, &mut rand::thread_rng(), 4));
+        assert!(result.iter().all(|i| i.as_ref().err() == Some(&ThresholdError::OutOfRange("output_min"))));
+    }
+
+    #[test]
+    fn reshare_test(){
+        //p(x) = secret + a * x, threshold 2, reshared to threshold 3
+        let secret = pallas::Scalar::random(rand::thread_rng());
+        let a = pallas::Scalar::random(rand::thread_rng());
+        let old: Vec<pallas::Scalar> = (1..=3u64).map(|i| pallas::Scalar::add(&secret, &pallas::Scalar::mul(&a, &pallas::Scalar::from(i)))).collect();
+        let generator = pallas::Affine::generator();
+        let pub_key = pallas::Affine::mul(generator, secret).to_affine();
+        let old_verification: Vec<pallas::Affine> = old.iter().map(|i| pallas::Affine::mul(generator, i).to_affine()).collect();
+        let result = simulate(3, lossy(), |session| run_reshare(session, &mut rand::thread_rng(), old[session.index()], pub_key, &old_verification, 3));
+        let result: Vec<(pallas::Scalar, Vec<pallas::Affine>)> = result.into_iter().map(|i| i.unwrap()).collect();
+        let new: Vec<pallas::Scalar> = result.iter().map(|i| i.0).collect();
+        //every party get the same verification shares, and they match the new shares
+        for (i, share) in new.iter().enumerate() {
+            assert_eq!(result[0].1, result[i].1);
+            assert_eq!(result[0].1[i], pallas::Affine::mul(generator, share).to_affine());
+        }
+        let signer_set = [1, 2, 3];
+        let mut sum = pallas::Scalar::zero();
+        for (i, share) in new.iter().enumerate() {
+            sum = pallas::Scalar::add(&sum, &pallas::Scalar::mul(&lagrange_coefficient(i as u128 + 1, &signer_set).unwrap(), share));
+        }
+        assert_eq!(sum, secret);
+        //two of the new shares are not enough any more
+        let two = pallas::Scalar::add(
+            &pallas::Scalar::mul(&lagrange_coefficient(1, &[1, 2]).unwrap(), &new[0]),
+            &pallas::Scalar::mul(&lagrange_coefficient(2, &[1, 2]).unwrap(), &new[1]),
+        );
+        assert_ne!(two, secret);
+
+        //a wrong old share is caught at its dealer
+        let result = simulate(3, Faults::none(), |session| {
+            let share = if session.index() == 1 { pallas::Scalar::one() } else { old[session.index()] };
+            run_reshare(session, &mut rand::thread_rng(), share, pub_key, &old_verification, 2)
+        });
+        assert_eq!(result[0], Err(ThresholdError::VerificationFailed { party : 1 }));
+        assert_eq!(result[2], Err(ThresholdError::VerificationFailed { party : 1 }));
+    }
+
+    #[test]
+    fn signing_test(){
+        let secret: Vec<PartySecret> = (0..3).map(|i| test_party_secret(&mut rand::thread_rng(), i)).collect();
+        let mta_pub_n: Vec<BigUint> = secret.iter().map(|i| i.pri_p.expose() * i.pri_q.expose()).collect();
+        let w: pallas::Scalar = secret.iter().map(|i| i.w.expose()).sum();
+        //every secret move into its party once, nothing can copy it
+        let secret: Vec<std::sync::Mutex<Option<PartySecret>>> = secret.into_iter().map(|i| std::sync::Mutex::new(Some(i))).collect();
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let result = simulate(3, lossy(), |session| {
+            let own = secret[session.index()].lock().unwrap().take().ok_or(ThresholdError::UnexpectedRound(1))?;
+            let mut party = Party::new(session.index(), own, mta_pub_n.clone(), test_ring_pedersen_params(3))?;
+            run_signing(session, &mut rand::thread_rng(), &mut party, &digest)
+        });
+
+        let mut share = Vec::new();
+        let mut check = Vec::new();
+        for i in &result {
+            match i {
+                Ok((commitment, s, own, _)) => {
+                    assert_eq!((*commitment, *s), (result[0].as_ref().unwrap().0, result[0].as_ref().unwrap().1));
+                    share.push(own.s);
+                    check.push(own.share_check(&mut rand::thread_rng(), *commitment));
+                }
+                //x of R doesn't fit in the scalar field, too rare to care in a test
+                Err(ThresholdError::OutOfRange("r")) => return,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        //the shares are the ones each party finalized, the sum is s
+        let (commitment, s, _, round3) = result[0].as_ref().unwrap();
+        assert_eq!(share.iter().fold(pallas::Scalar::zero(), |a, b| a + b), *s);
+        let pub_key = pallas::Affine::mul(pallas::Affine::generator(), w).to_affine();
+        //every party got the same round 3 messages
+        assert!(result.iter().all(|i| i.as_ref().unwrap().3 == *round3));
+        let share_commitment: Vec<_> = round3.iter().map(|i| i.share_commitment()).collect();
+        assert_eq!(identify_abort(&digest, *commitment, pub_key, &share_commitment, &share, &check), Ok(Vec::new()));
+    }
+
+    //the same rounds on secp256k1 and P-256, the standard verifiers take the signature
+    fn curve_signing<P: EcdsaCurve>(digest: &[u8; 32]) -> Option<(P, EcdsaSignature<P>)> {
+        let secret: Vec<PartySecret<P>> = (0..3).map(|i| test_party_secret(&mut rand::thread_rng(), i)).collect();
+        let mta_pub_n: Vec<BigUint> = secret.iter().map(|i| i.pri_p.expose() * i.pri_q.expose()).collect();
+        let w: P::Scalar = secret.iter().map(|i| *i.w.expose()).sum();
+        let secret: Vec<std::sync::Mutex<Option<PartySecret<P>>>> = secret.into_iter().map(|i| std::sync::Mutex::new(Some(i))).collect();
+        let result = simulate(3, lossy(), |session| {
+            let own = secret[session.index()].lock().unwrap().take().ok_or(ThresholdError::UnexpectedRound(1))?;
+            let mut party = Party::<P>::new(session.index(), own, mta_pub_n.clone(), test_ring_pedersen_params(3))?;
+            run_signing(session, &mut rand::thread_rng(), &mut party, digest)
+        });
+        let (commitment, s) = match result.into_iter().collect::<Result<Vec<_>, ThresholdError>>() {
+            Ok(result) => (result[0].0, result[0].1),
+            //x of R >= n on P-256 or secp256k1 has no r that round trip, too rare to care in a test
+            Err(ThresholdError::OutOfRange("r")) => return None,
+            Err(e) => panic!("{}", e),
+        };
+        Some(((P::generator() * w).to_affine(), EcdsaSignature::new(commitment, s).unwrap()))
+    }
+
+    #[test]
+    fn curve_signing_test(){
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        if let Some((pub_key, signature)) = curve_signing::<k256::AffinePoint>(&digest) {
+            let verifying_key = k256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+            let ecdsa = k256::ecdsa::Signature::from_slice(&signature.to_bytes()).unwrap();
+            assert!(verifying_key.verify_prehash(&digest, &ecdsa).is_ok());
+        }
+        if let Some((pub_key, signature)) = curve_signing::<p256::AffinePoint>(&digest) {
+            let verifying_key = p256::ecdsa::VerifyingKey::from_affine(pub_key).unwrap();
+            let ecdsa = p256::ecdsa::Signature::from_slice(&signature.to_bytes()).unwrap();
+            assert!(verifying_key.verify_prehash(&digest, &ecdsa).is_ok());
+        }
+    }
+
+    #[test]
+    fn bip340_signing_test(){
+        let key = crate::key_file::test_key_files::<k256::AffinePoint>(&mut rand::thread_rng());
+        let message = generate_random_u128_in_range(1, u64::MAX as u128).to_be_bytes();
+        let result = simulate(3, lossy(), |session| {
+            let own = &key[session.index()];
+            run_bip340_signing(session, &mut rand::thread_rng(), &own.share, &own.pub_key, &own.verification_share, &message, None)
+        });
+        let signature = *result[0].as_ref().unwrap();
+        assert!(result.iter().all(|i| i.as_ref() == Ok(&signature)));
+        assert_eq!(bip340::verify(&bip340::x_only(&key[0].pub_key), &message, &signature), Ok(()));
+        assert!(bip340::verify(&bip340::x_only(&key[0].pub_key), &[0u8; 16], &signature).is_err());
+
+        //a verification share of another key blame its party
+        let result = simulate(3, Faults::none(), |session| {
+            let own = &key[session.index()];
+            let mut verification_share = own.verification_share.clone();
+            verification_share[1] = k256::AffinePoint::GENERATOR;
+            run_bip340_signing(session, &mut rand::thread_rng(), &own.share, &own.pub_key, &verification_share, &message, None)
+        });
+        assert!(result.iter().all(|i| *i == Err(ThresholdError::VerificationFailed { party : 1 })));
+    }
+
+    #[test]
+    fn presigning_test(){
+        let secret: Vec<PartySecret> = (0..3).map(|i| test_party_secret(&mut rand::thread_rng(), i)).collect();
+        let mta_pub_n: Vec<BigUint> = secret.iter().map(|i| i.pri_p.expose() * i.pri_q.expose()).collect();
+        let w: pallas::Scalar = secret.iter().map(|i| i.w.expose()).sum();
+        //every secret move into its party once, nothing can copy it
+        let secret: Vec<std::sync::Mutex<Option<PartySecret>>> = secret.into_iter().map(|i| std::sync::Mutex::new(Some(i))).collect();
+        //offline, no message yet
+        let presignature = simulate(3, lossy(), |session| {
+            let own = secret[session.index()].lock().unwrap().take().ok_or(ThresholdError::UnexpectedRound(1))?;
+            let mut party = Party::new(session.index(), own, mta_pub_n.clone(), test_ring_pedersen_params(3))?;
+            run_presigning(session, &mut rand::thread_rng(), &mut party)
+        });
+        let presignature = match presignature.into_iter().collect::<Result<Vec<Presignature>, ThresholdError>>() {
+            Ok(presignature) => presignature,
+            //x of R doesn't fit in the scalar field, too rare to care in a test
+            Err(ThresholdError::OutOfRange("r")) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let commitment = presignature[0].commitment();
+        assert!(presignature.iter().all(|i| i.commitment() == commitment));
+
+        //online, later and in a session of its own, one round each
+        let digest: [u8; 32] = rand::thread_rng().gen();
+        let presignature: Vec<std::sync::Mutex<Option<Presignature>>> = presignature.into_iter().map(|i| std::sync::Mutex::new(Some(i))).collect();
+        let result = simulate(3, lossy(), |session| {
+            let own = presignature[session.index()].lock().unwrap().take().ok_or(ThresholdError::UnexpectedRound(4))?;
+            run_online_signing(session, own, &digest)
+        });
+        let (_, s, _, round3) = result[0].as_ref().unwrap();
+        let generator = pallas::Affine::generator();
+        let pub_key = pallas::Affine::mul(generator, w).to_affine();
+        let r = commitment_r(commitment).unwrap();
+        let left = pallas::Affine::mul(commitment, s).to_affine();
+        let right = pallas::Affine::add(pallas::Affine::mul(generator, pallas::Affine::digest_scalar(&digest)).to_affine(), pallas::Affine::mul(pub_key, r)).to_affine();
+        assert_eq!(left, right);
+        let share: Vec<pallas::Scalar> = result.iter().map(|i| i.as_ref().unwrap().2.s).collect();
+        let check: Vec<_> = result.iter().map(|i| i.as_ref().unwrap().2.share_check(&mut rand::thread_rng(), commitment)).collect();
+        let share_commitment: Vec<_> = round3.iter().map(|i| i.share_commitment()).collect();
+        assert_eq!(identify_abort(&digest, commitment, pub_key, &share_commitment, &share, &check), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn missing_party_test(){
+        //party 2 never speak, the others give up on round 1
+        let result = simulate(3, Faults::none(), |session| {
+            if session.index() == 2 {
+                return Ok(());
+            }
+            session.set_timeout(Duration::from_millis(200));
+            session.send(Payload::<pallas::Affine>::Round4(crate::party::Round4Msg { from : session.index(), s : pallas::Scalar::one() }))?;
+            session.collect(|i| match i {
+                Payload::Round4(i) => Some(*i),
+                _ => None,
+            })?;
+            Ok(())
+        });
+        assert_eq!(result[0], Err(ThresholdError::Timeout { missing: vec![2] }));
+        assert_eq!(result[1], Err(ThresholdError::Timeout { missing: vec![2] }));
+        assert_eq!(result[2], Ok(()));
+    }
+}